@@ -1,7 +1,10 @@
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Request {
-    #[prost(oneof = "request::Call", tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11")]
+    #[prost(
+        oneof = "request::Call",
+        tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16"
+    )]
     pub call: ::core::option::Option<request::Call>,
 }
 /// Nested message and enum types in `Request`.
@@ -53,6 +56,44 @@ pub mod request {
         pub message: ::prost::bytes::Bytes,
     }
     #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct GetLatestBlock {
+        #[prost(bytes = "bytes", tag = "1")]
+        pub address: ::prost::bytes::Bytes,
+    }
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct GetBlock {
+        #[prost(bytes = "bytes", tag = "1")]
+        pub id: ::prost::bytes::Bytes,
+    }
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct GetBlockBySeqno {
+        #[prost(int32, tag = "1")]
+        pub workchain: i32,
+        #[prost(uint64, tag = "2")]
+        pub shard: u64,
+        #[prost(uint32, tag = "3")]
+        pub seqno: u32,
+    }
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct GetContractStateWithProof {
+        #[prost(bytes = "bytes", tag = "1")]
+        pub address: ::prost::bytes::Bytes,
+    }
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct WaitForNextBlock {
+        #[prost(bytes = "bytes", tag = "1")]
+        pub current: ::prost::bytes::Bytes,
+        #[prost(bytes = "bytes", tag = "2")]
+        pub address: ::prost::bytes::Bytes,
+        #[prost(uint64, tag = "3")]
+        pub timeout_ms: u64,
+    }
+    #[allow(clippy::derive_partial_eq_without_eq)]
     #[derive(Clone, PartialEq, ::prost::Oneof)]
     pub enum Call {
         #[prost(message, tag = "1")]
@@ -77,12 +118,25 @@ pub mod request {
         GetAccountsByCodeHash(GetAccountsByCodeHash),
         #[prost(message, tag = "11")]
         SendMessage(SendMessage),
+        #[prost(message, tag = "12")]
+        GetLatestBlock(GetLatestBlock),
+        #[prost(message, tag = "13")]
+        GetBlock(GetBlock),
+        #[prost(message, tag = "14")]
+        GetBlockBySeqno(GetBlockBySeqno),
+        #[prost(message, tag = "15")]
+        WaitForNextBlock(WaitForNextBlock),
+        #[prost(message, tag = "16")]
+        GetContractStateWithProof(GetContractStateWithProof),
     }
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Response {
-    #[prost(oneof = "response::Result", tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 10")]
+    #[prost(
+        oneof = "response::Result",
+        tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14"
+    )]
     pub result: ::core::option::Option<response::Result>,
 }
 /// Nested message and enum types in `Response`.
@@ -136,6 +190,28 @@ pub mod response {
     }
     #[allow(clippy::derive_partial_eq_without_eq)]
     #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct GetLatestBlock {
+        #[prost(bytes = "bytes", tag = "1")]
+        pub id: ::prost::bytes::Bytes,
+        #[prost(uint64, tag = "2")]
+        pub end_lt: u64,
+        #[prost(uint32, tag = "3")]
+        pub gen_utime: u32,
+    }
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct GetBlock {
+        #[prost(bytes = "bytes", tag = "1")]
+        pub block: ::prost::bytes::Bytes,
+    }
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct WaitForNextBlock {
+        #[prost(bytes = "bytes", tag = "1")]
+        pub id: ::prost::bytes::Bytes,
+    }
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
     pub struct GetBlockchainConfig {
         #[prost(int32, tag = "1")]
         pub global_id: i32,
@@ -228,6 +304,20 @@ pub mod response {
         }
     }
     #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct GetContractStateWithProof {
+        #[prost(message, optional, tag = "1")]
+        pub state: ::core::option::Option<GetContractState>,
+        #[prost(bytes = "bytes", repeated, tag = "2")]
+        pub key_block_proofs: ::prost::alloc::vec::Vec<::prost::bytes::Bytes>,
+        #[prost(bytes = "bytes", tag = "3")]
+        pub mc_block_proof: ::prost::bytes::Bytes,
+        #[prost(bytes = "bytes", optional, tag = "4")]
+        pub shard_block_proof: ::core::option::Option<::prost::bytes::Bytes>,
+        #[prost(bytes = "bytes", tag = "5")]
+        pub state_proof: ::prost::bytes::Bytes,
+    }
+    #[allow(clippy::derive_partial_eq_without_eq)]
     #[derive(Clone, PartialEq, ::prost::Oneof)]
     pub enum Result {
         #[prost(message, tag = "1")]
@@ -250,6 +340,14 @@ pub mod response {
         GetContractState(GetContractState),
         #[prost(message, tag = "10")]
         SendMessage(()),
+        #[prost(message, tag = "11")]
+        GetLatestBlock(GetLatestBlock),
+        #[prost(message, tag = "12")]
+        GetBlock(GetBlock),
+        #[prost(message, tag = "13")]
+        WaitForNextBlock(WaitForNextBlock),
+        #[prost(message, tag = "14")]
+        GetContractStateWithProof(GetContractStateWithProof),
     }
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
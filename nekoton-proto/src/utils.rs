@@ -1,6 +1,6 @@
 use anyhow::Result;
 use prost::bytes::Bytes;
-use ton_block::{Deserializable, MaybeDeserialize, MsgAddressInt};
+use ton_block::{Deserializable, MaybeDeserialize, MsgAddressInt, Serializable};
 use ton_types::UInt256;
 
 pub fn addr_to_bytes(address: &MsgAddressInt) -> Bytes {
@@ -41,3 +41,20 @@ pub fn deserialize_account_stuff(bytes: &Bytes) -> Result<ton_block::AccountStuf
         })
     })
 }
+
+/// Inverse of [`deserialize_account_stuff`]
+pub fn serialize_account_stuff(account: &ton_block::AccountStuff) -> Result<Bytes> {
+    let mut builder = ton_types::BuilderData::new();
+    account.addr.write_to(&mut builder)?;
+    account.storage_stat.write_to(&mut builder)?;
+    account.storage.last_trans_lt.write_to(&mut builder)?;
+    account.storage.balance.write_to(&mut builder)?;
+    account.storage.state.write_to(&mut builder)?;
+    if let Some(init_code_hash) = &account.storage.init_code_hash {
+        builder.append_bit_one()?;
+        init_code_hash.write_to(&mut builder)?;
+    }
+
+    let cell = builder.into_cell()?;
+    Ok(ton_types::serialize_toc(&cell)?.into())
+}
@@ -0,0 +1,239 @@
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use futures_util::stream::FuturesUnordered;
+use futures_util::StreamExt;
+use nekoton_utils::*;
+use reqwest::{IntoUrl, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::http::HttpClientSettings;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointsSettings {
+    /// Server endpoints, e.g. `https://jrpc.everwallet.net/rpc`
+    pub endpoints: Vec<String>,
+    /// Frequency of sync latency detection. Default: `60000`
+    #[serde(with = "serde_duration_ms")]
+    pub latency_detection_interval: Duration,
+    /// Maximum value for the endpoint's blockchain data sync latency. Default: `60000`
+    #[serde(with = "serde_duration_ms")]
+    pub max_latency: Duration,
+    /// Maximum amount of retries during endpoint selection
+    pub endpoint_selection_retry_count: usize,
+    /// HTTP client settings
+    #[serde(default)]
+    pub http: HttpClientSettings,
+}
+
+impl Default for EndpointsSettings {
+    fn default() -> Self {
+        Self {
+            endpoints: Vec::new(),
+            latency_detection_interval: Duration::from_secs(60),
+            max_latency: Duration::from_secs(60),
+            endpoint_selection_retry_count: 5,
+            http: Default::default(),
+        }
+    }
+}
+
+/// Picks the endpoint with an acceptable sync latency and rechecks
+/// all endpoints once per the detection interval
+pub(crate) struct EndpointSelector {
+    endpoints: Vec<Url>,
+    latency_detection_interval: u64,
+    max_latency: u64,
+    endpoint_selection_retry_count: usize,
+    state: tokio::sync::Mutex<SelectionState>,
+}
+
+#[derive(Default)]
+struct SelectionState {
+    index: usize,
+    next_detection_time: u64,
+}
+
+impl EndpointSelector {
+    pub fn single<U: IntoUrl>(endpoint: U) -> Result<Self> {
+        let settings = EndpointsSettings::default();
+        Ok(Self::with_endpoints(vec![endpoint.into_url()?], &settings))
+    }
+
+    pub fn new(settings: &EndpointsSettings) -> Result<Self> {
+        let endpoints = settings
+            .endpoints
+            .iter()
+            .map(|endpoint| {
+                endpoint
+                    .into_url()
+                    .with_context(|| format!("failed to parse endpoint: {endpoint}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if endpoints.is_empty() {
+            return Err(EndpointSelectorError::NoEndpointsSpecified.into());
+        }
+
+        Ok(Self::with_endpoints(endpoints, settings))
+    }
+
+    fn with_endpoints(endpoints: Vec<Url>, settings: &EndpointsSettings) -> Self {
+        Self {
+            endpoints,
+            latency_detection_interval: settings.latency_detection_interval.as_secs(),
+            max_latency: settings.max_latency.as_millis() as u64,
+            endpoint_selection_retry_count: settings.endpoint_selection_retry_count,
+            state: Default::default(),
+        }
+    }
+
+    /// Returns the current endpoint.
+    ///
+    /// `check_latency` must return the sync latency of the endpoint in milliseconds
+    pub async fn select<F, Fut>(&self, check_latency: F) -> Result<&'_ Url>
+    where
+        F: Fn(&Url) -> Fut,
+        Fut: Future<Output = Result<u64>>,
+    {
+        if self.endpoints.len() == 1 {
+            return Ok(&self.endpoints[0]);
+        }
+
+        // NOTE: other requests wait while the best endpoint is searched
+        let mut state = self.state.lock().await;
+
+        let now = now_sec_u64();
+        if now < state.next_detection_time {
+            return Ok(&self.endpoints[state.index]);
+        }
+
+        let index = self.find_best_endpoint(check_latency).await?;
+        state.index = index;
+        state.next_detection_time = now_sec_u64() + self.latency_detection_interval;
+
+        Ok(&self.endpoints[index])
+    }
+
+    async fn find_best_endpoint<F, Fut>(&self, check_latency: F) -> Result<usize>
+    where
+        F: Fn(&Url) -> Fut,
+        Fut: Future<Output = Result<u64>>,
+    {
+        for i in 1..=self.endpoint_selection_retry_count {
+            let mut requests = self
+                .endpoints
+                .iter()
+                .enumerate()
+                .map(|(i, endpoint)| {
+                    let fut = check_latency(endpoint);
+                    async move { (i, fut.await) }
+                })
+                .collect::<FuturesUnordered<_>>();
+
+            let mut best = None;
+            while let Some((i, response)) = requests.next().await {
+                match response {
+                    Ok(latency) if latency <= self.max_latency => return Ok(i),
+                    Ok(latency) => {
+                        if !matches!(best, Some((_, best_latency)) if best_latency <= latency) {
+                            best = Some((i, latency));
+                        }
+                    }
+                    Err(e) => {
+                        log::debug!("Endpoint selection error: {e:?}");
+                    }
+                }
+            }
+
+            if let Some((i, _)) = best {
+                return Ok(i);
+            }
+
+            let interval = std::cmp::min(i * 100, 5000);
+            tokio::time::sleep(Duration::from_millis(interval as u64)).await;
+        }
+
+        Err(EndpointSelectorError::NoEndpointFound.into())
+    }
+}
+
+/// Computes the sync latency from the generation time of the account state
+pub(crate) fn latency_from_gen_utime(gen_utime: u32) -> u64 {
+    now_ms_u64().saturating_sub(gen_utime as u64 * 1000)
+}
+
+#[derive(thiserror::Error, Debug)]
+enum EndpointSelectorError {
+    #[error("no endpoints specified")]
+    NoEndpointsSpecified,
+    #[error("no valid endpoint found")]
+    NoEndpointFound,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn selects_endpoint_with_acceptable_latency() {
+        let selector = EndpointSelector::new(&EndpointsSettings {
+            endpoints: vec![
+                "http://127.0.0.1:10001".to_owned(),
+                "http://127.0.0.1:10002".to_owned(),
+                "http://127.0.0.1:10003".to_owned(),
+            ],
+            max_latency: Duration::from_secs(1),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let endpoint = selector
+            .select(|endpoint| {
+                let port = endpoint.port();
+                async move {
+                    match port {
+                        Some(10001) => anyhow::bail!("unavailable"),
+                        Some(10002) => Ok(500),
+                        _ => Ok(100_000),
+                    }
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(endpoint.port(), Some(10002));
+
+        // Selected endpoint is reused until the next detection
+        let endpoint = selector
+            .select(|_| async { anyhow::bail!("must not be called") })
+            .await
+            .unwrap();
+        assert_eq!(endpoint.port(), Some(10002));
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_lowest_latency() {
+        let selector = EndpointSelector::new(&EndpointsSettings {
+            endpoints: vec![
+                "http://127.0.0.1:10001".to_owned(),
+                "http://127.0.0.1:10002".to_owned(),
+            ],
+            max_latency: Duration::from_secs(1),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let endpoint = selector
+            .select(|endpoint| {
+                let latency = if endpoint.port() == Some(10001) {
+                    20_000
+                } else {
+                    10_000
+                };
+                async move { Ok(latency) }
+            })
+            .await
+            .unwrap();
+        assert_eq!(endpoint.port(), Some(10002));
+    }
+}
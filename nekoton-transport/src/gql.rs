@@ -12,6 +12,8 @@ use serde::{Deserialize, Serialize};
 use tokio::sync::futures::Notified;
 use tokio::sync::Notify;
 
+use crate::http::HttpClientSettings;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GqlNetworkSettings {
     /// Path to graphql api endpoints, e.g. `https://main.ton.dev`
@@ -26,6 +28,9 @@ pub struct GqlNetworkSettings {
     pub endpoint_selection_retry_count: usize,
     /// Gql node type
     pub local: bool,
+    /// HTTP client settings
+    #[serde(default)]
+    pub http: HttpClientSettings,
 }
 
 impl Default for GqlNetworkSettings {
@@ -36,6 +41,7 @@ impl Default for GqlNetworkSettings {
             max_latency: Duration::from_secs(60),
             endpoint_selection_retry_count: 5,
             local: false,
+            http: Default::default(),
         }
     }
 }
@@ -53,6 +59,12 @@ pub struct GqlClient {
 
 impl GqlClient {
     pub fn new(settings: GqlNetworkSettings) -> Result<Arc<Self>> {
+        let client = settings.http.build_client()?;
+        Self::with_client(settings, client)
+    }
+
+    /// Uses an externally built HTTP client. HTTP settings are ignored
+    pub fn with_client(settings: GqlNetworkSettings, client: reqwest::Client) -> Result<Arc<Self>> {
         let endpoints = settings
             .endpoints
             .into_iter()
@@ -65,17 +77,6 @@ impl GqlClient {
             return Err(GqlClientError::NoEndpointsSpecified.into());
         }
 
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert(
-            reqwest::header::CONTENT_TYPE,
-            reqwest::header::HeaderValue::from_static("application/json"),
-        );
-
-        let client = reqwest::ClientBuilder::new()
-            .default_headers(headers)
-            .build()
-            .context("failed to build http client")?;
-
         Ok(Arc::new(Self {
             client,
             endpoints,
@@ -249,6 +250,7 @@ impl nekoton::external::GqlConnection for GqlClient {
         let response = self
             .client
             .post(endpoint.gql.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
             .body(req.data)
             .send()
             .await?;
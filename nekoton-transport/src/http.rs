@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use nekoton_utils::*;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+
+/// Settings of the HTTP client used by transport clients
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpClientSettings {
+    /// Timeout for the whole request. Must be greater than the timeout
+    /// of GQL long queries. Default: `None`
+    #[serde(with = "serde_optional_duration_ms")]
+    pub request_timeout: Option<Duration>,
+    /// Timeout for establishing a connection. Default: `10000`
+    #[serde(with = "serde_duration_ms")]
+    pub connect_timeout: Duration,
+    /// Maximum number of idle connections per host. Default: `None` (unlimited)
+    pub pool_max_idle_per_host: Option<usize>,
+    /// How long idle connections are kept alive. Default: `90000`
+    #[serde(with = "serde_duration_ms")]
+    pub pool_idle_timeout: Duration,
+    /// Proxy for all requests, e.g. `http://127.0.0.1:8080`
+    pub proxy: Option<String>,
+    /// Token for the `Authorization: Bearer <token>` header
+    pub bearer_token: Option<String>,
+    /// Additional headers which are sent with each request (e.g. API keys)
+    pub headers: HashMap<String, String>,
+    /// Custom `User-Agent` header
+    pub user_agent: Option<String>,
+}
+
+impl Default for HttpClientSettings {
+    fn default() -> Self {
+        Self {
+            request_timeout: None,
+            connect_timeout: Duration::from_secs(10),
+            pool_max_idle_per_host: None,
+            pool_idle_timeout: Duration::from_secs(90),
+            proxy: None,
+            bearer_token: None,
+            headers: Default::default(),
+            user_agent: None,
+        }
+    }
+}
+
+impl HttpClientSettings {
+    pub fn build_client(&self) -> Result<reqwest::Client> {
+        let mut headers = HeaderMap::with_capacity(self.headers.len() + 1);
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("invalid header name: {name}"))?;
+            let mut value = HeaderValue::from_str(value)
+                .with_context(|| format!("invalid value for header {name}"))?;
+            value.set_sensitive(true);
+            headers.insert(name, value);
+        }
+
+        if let Some(token) = &self.bearer_token {
+            let mut value = HeaderValue::from_str(&format!("Bearer {token}"))
+                .context("invalid bearer token")?;
+            value.set_sensitive(true);
+            headers.insert(reqwest::header::AUTHORIZATION, value);
+        }
+
+        let mut builder = reqwest::ClientBuilder::new()
+            .default_headers(headers)
+            .connect_timeout(self.connect_timeout)
+            .pool_idle_timeout(self.pool_idle_timeout);
+
+        if let Some(timeout) = self.request_timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(max_idle) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy).context("invalid proxy")?);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+
+        builder.build().context("failed to build http client")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_client_with_custom_headers() {
+        let settings: HttpClientSettings = serde_json::from_str(
+            r#"{
+                "request_timeout": 5000,
+                "bearer_token": "secret",
+                "headers": { "x-api-key": "key" },
+                "user_agent": "test"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(settings.request_timeout, Some(Duration::from_secs(5)));
+        assert_eq!(settings.connect_timeout, Duration::from_secs(10));
+        settings.build_client().unwrap();
+
+        let settings = HttpClientSettings {
+            headers: [("invalid header".to_owned(), "value".to_owned())].into(),
+            ..Default::default()
+        };
+        assert!(settings.build_client().is_err());
+    }
+}
@@ -1,34 +1,63 @@
 use std::sync::Arc;
 
-use anyhow::{Context, Result};
+use anyhow::Result;
+use nekoton::abi::GenTimings;
 use reqwest::{IntoUrl, Url};
+use serde::Deserialize;
+
+use crate::endpoints::{latency_from_gen_utime, EndpointSelector, EndpointsSettings};
+use crate::http::HttpClientSettings;
 
 pub struct JrpcClient {
     client: reqwest::Client,
-    base_url: Url,
+    endpoints: EndpointSelector,
     alternative_url: Option<Url>,
+    batch_requests: bool,
 }
 
 impl JrpcClient {
     pub fn new<U: IntoUrl>(endpoint: U) -> Result<Arc<Self>> {
-        let url = endpoint.into_url()?;
+        Self::with_settings(endpoint, &HttpClientSettings::default())
+    }
 
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert(
-            reqwest::header::CONTENT_TYPE,
-            reqwest::header::HeaderValue::from_static("application/json"),
-        );
+    pub fn with_settings<U: IntoUrl>(
+        endpoint: U,
+        settings: &HttpClientSettings,
+    ) -> Result<Arc<Self>> {
+        Self::with_client(endpoint, settings.build_client()?)
+    }
+
+    /// Uses an externally built HTTP client
+    pub fn with_client<U: IntoUrl>(endpoint: U, client: reqwest::Client) -> Result<Arc<Self>> {
+        Ok(Arc::new(Self::with_selector(
+            client,
+            EndpointSelector::single(endpoint)?,
+        )))
+    }
 
-        let client = reqwest::ClientBuilder::new()
-            .default_headers(headers)
-            .build()
-            .context("failed to build http client")?;
+    /// Uses the endpoint with the lowest sync latency
+    pub fn with_endpoints(settings: &EndpointsSettings) -> Result<Arc<Self>> {
+        Self::with_endpoints_and_client(settings, settings.http.build_client()?)
+    }
+
+    /// Uses the endpoint with the lowest sync latency and an externally built HTTP client
+    pub fn with_endpoints_and_client(
+        settings: &EndpointsSettings,
+        client: reqwest::Client,
+    ) -> Result<Arc<Self>> {
+        Ok(Arc::new(Self::with_selector(
+            client,
+            EndpointSelector::new(settings)?,
+        )))
+    }
 
-        Ok(Arc::new(Self {
+    fn with_selector(client: reqwest::Client, endpoints: EndpointSelector) -> Self {
+        Self {
             client,
-            base_url: url,
+            endpoints,
             alternative_url: None,
-        }))
+            batch_requests: false,
+        }
     }
 
     /// Set an alternative URL which will be used for requests that don't require a db
@@ -36,22 +65,76 @@ impl JrpcClient {
         self.alternative_url = Some(endpoint.into_url()?);
         Ok(())
     }
+
+    /// Allow sending several requests in one JSON-RPC batch
+    pub fn set_batch_requests(&mut self, enabled: bool) {
+        self.batch_requests = enabled;
+    }
+
+    async fn send(&self, url: &Url, data: String) -> Result<String> {
+        let response = self
+            .client
+            .post(url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(data)
+            .send()
+            .await?;
+        Ok(response.text().await?)
+    }
+
+    async fn check_latency(&self, endpoint: &Url) -> Result<u64> {
+        #[derive(Deserialize)]
+        struct Response {
+            result: State,
+        }
+
+        #[derive(Deserialize)]
+        struct State {
+            timings: GenTimings,
+        }
+
+        let data = nekoton::transport::jrpc::make_jrpc_request(
+            "getContractState",
+            &serde_json::json!({ "address": LATENCY_CHECK_ADDRESS }),
+        );
+        let response = self.send(endpoint, data).await?;
+        match serde_json::from_str::<Response>(&response)?.result.timings {
+            GenTimings::Known { gen_utime, .. } => Ok(latency_from_gen_utime(gen_utime)),
+            GenTimings::Unknown => Err(JrpcClientError::UnknownTimings.into()),
+        }
+    }
 }
 
 #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
 #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
 impl nekoton::external::JrpcConnection for JrpcClient {
     async fn post(&self, req: nekoton::external::JrpcRequest) -> Result<String> {
-        let url = if req.requires_db {
-            self.alternative_url.as_ref().unwrap_or(&self.base_url)
-        } else {
-            &self.base_url
+        let url = match &self.alternative_url {
+            Some(url) if req.requires_db => url,
+            _ => {
+                self.endpoints
+                    .select(|endpoint| self.check_latency(endpoint))
+                    .await?
+            }
         };
-        let response = self.client.post(url.clone()).body(req.data).send().await?;
-        Ok(response.text().await?)
+        self.send(url, req.data).await
+    }
+
+    fn supports_batch(&self) -> bool {
+        self.batch_requests
     }
 }
 
+/// Config contract address, it always exists in the masterchain
+const LATENCY_CHECK_ADDRESS: &str =
+    "-1:5555555555555555555555555555555555555555555555555555555555555555";
+
+#[derive(thiserror::Error, Debug)]
+enum JrpcClientError {
+    #[error("endpoint returned unknown state timings")]
+    UnknownTimings,
+}
+
 #[cfg(test)]
 mod tests {
     use nekoton::external::{JrpcConnection, JrpcRequest};
@@ -52,9 +52,13 @@
     clippy::dbg_macro
 )]
 
+#[cfg(any(feature = "jrpc_transport", feature = "proto_transport"))]
+pub mod endpoints;
 #[cfg(feature = "gql_transport")]
 pub mod gql;
+pub mod http;
 #[cfg(feature = "jrpc_transport")]
 pub mod jrpc;
 #[cfg(feature = "proto_transport")]
 pub mod proto;
+pub mod timer;
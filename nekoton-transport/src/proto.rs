@@ -1,36 +1,70 @@
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 use nekoton_proto::prost::Message;
 use nekoton_proto::protos::rpc;
+use reqwest::header::{HeaderMap, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE};
 use reqwest::{IntoUrl, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::endpoints::{latency_from_gen_utime, EndpointSelector, EndpointsSettings};
+use crate::http::HttpClientSettings;
 
 pub struct ProtoClient {
     client: reqwest::Client,
-    base_url: Url,
+    endpoints: EndpointSelector,
     alternative_url: Option<Url>,
+    compression: ProtoCompression,
+    /// Whether the server accepts compressed requests
+    compress_requests: AtomicBool,
 }
 
 impl ProtoClient {
     pub fn new<U: IntoUrl>(endpoint: U) -> Result<Arc<Self>> {
-        let url = endpoint.into_url()?;
+        Self::with_settings(endpoint, &HttpClientSettings::default())
+    }
 
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert(
-            reqwest::header::CONTENT_TYPE,
-            reqwest::header::HeaderValue::from_static("application/x-protobuf"),
-        );
+    pub fn with_settings<U: IntoUrl>(
+        endpoint: U,
+        settings: &HttpClientSettings,
+    ) -> Result<Arc<Self>> {
+        Self::with_client(endpoint, settings.build_client()?)
+    }
 
-        let client = reqwest::ClientBuilder::new()
-            .default_headers(headers)
-            .build()
-            .context("failed to build http client")?;
+    /// Uses an externally built HTTP client
+    pub fn with_client<U: IntoUrl>(endpoint: U, client: reqwest::Client) -> Result<Arc<Self>> {
+        Ok(Arc::new(Self::with_selector(
+            client,
+            EndpointSelector::single(endpoint)?,
+        )))
+    }
 
-        Ok(Arc::new(Self {
+    /// Uses the endpoint with the lowest sync latency
+    pub fn with_endpoints(settings: &EndpointsSettings) -> Result<Arc<Self>> {
+        Self::with_endpoints_and_client(settings, settings.http.build_client()?)
+    }
+
+    /// Uses the endpoint with the lowest sync latency and an externally built HTTP client
+    pub fn with_endpoints_and_client(
+        settings: &EndpointsSettings,
+        client: reqwest::Client,
+    ) -> Result<Arc<Self>> {
+        Ok(Arc::new(Self::with_selector(
             client,
-            base_url: url,
+            EndpointSelector::new(settings)?,
+        )))
+    }
+
+    fn with_selector(client: reqwest::Client, endpoints: EndpointSelector) -> Self {
+        Self {
+            client,
+            endpoints,
             alternative_url: None,
-        }))
+            compression: ProtoCompression::None,
+            compress_requests: AtomicBool::new(false),
+        }
     }
 
     /// Set an alternative URL which will be used for requests that don't require a db
@@ -38,29 +72,264 @@ impl ProtoClient {
         self.alternative_url = Some(endpoint.into_url()?);
         Ok(())
     }
+
+    /// Enables payload compression.
+    ///
+    /// Compressed responses are requested through the `Accept-Encoding` header.
+    /// Requests are compressed only after the server lists the encoding in
+    /// the `Accept-Encoding` header of its response, so servers without
+    /// compression support keep working.
+    pub fn set_compression(&mut self, compression: ProtoCompression) {
+        self.compression = compression;
+        self.compress_requests.store(false, Ordering::Release);
+    }
+
+    fn request(&self, url: &Url) -> reqwest::RequestBuilder {
+        let builder = self
+            .client
+            .post(url.clone())
+            .header(CONTENT_TYPE, "application/x-protobuf");
+        match self.compression {
+            ProtoCompression::None => builder,
+            _ => builder.header(ACCEPT_ENCODING, "zstd, gzip"),
+        }
+    }
+
+    async fn send(&self, url: &Url, data: Vec<u8>) -> Result<Vec<u8>> {
+        let encoding = match self.compression.encoding() {
+            Some(encoding)
+                if data.len() >= MIN_COMPRESSED_SIZE
+                    && self.compress_requests.load(Ordering::Acquire) =>
+            {
+                Some(encoding)
+            }
+            _ => None,
+        };
+
+        let response = match encoding {
+            Some(encoding) => {
+                let compressed = self.compression.compress(&data)?;
+                let response = self
+                    .request(url)
+                    .header(CONTENT_ENCODING, encoding)
+                    .body(compressed)
+                    .send()
+                    .await?;
+
+                if response.status() == StatusCode::UNSUPPORTED_MEDIA_TYPE {
+                    // Server doesn't accept compressed requests anymore
+                    self.compress_requests.store(false, Ordering::Release);
+                    self.request(url).body(data).send().await?
+                } else {
+                    response
+                }
+            }
+            None => self.request(url).body(data).send().await?,
+        };
+
+        self.update_request_compression(response.headers());
+
+        // NOTE: `reqwest` removes the header if it has already decoded the body
+        let response_compression = response
+            .headers()
+            .get(CONTENT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .map(ProtoCompression::from_encoding)
+            .transpose()?
+            .unwrap_or_default();
+
+        let status = response.status();
+        let body = response_compression.decompress(response.bytes().await?.to_vec())?;
+
+        match status {
+            StatusCode::OK => Ok(body),
+            StatusCode::UNPROCESSABLE_ENTITY => {
+                let error = rpc::Error::decode(body.as_slice())?;
+                Err(nekoton::transport::proto::ProtoClientError::from(error).into())
+            }
+            _ => anyhow::bail!(status.to_string()),
+        }
+    }
+
+    fn update_request_compression(&self, headers: &HeaderMap) {
+        let encoding = match self.compression.encoding() {
+            Some(encoding) => encoding,
+            None => return,
+        };
+
+        let supported = headers
+            .get_all(ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .any(|value| accepts_encoding(value, encoding));
+        if supported {
+            self.compress_requests.store(true, Ordering::Release);
+        }
+    }
+
+    async fn check_latency(&self, endpoint: &Url) -> Result<u64> {
+        use rpc::response::get_contract_state::{not_exist, State};
+
+        // Config contract address, it always exists in the masterchain
+        let mut address = Vec::with_capacity(33);
+        address.push(-1i8 as u8);
+        address.extend_from_slice(&[0x55; 32]);
+
+        let request = rpc::Request {
+            call: Some(rpc::request::Call::GetContractState(
+                rpc::request::GetContractState {
+                    address: address.into(),
+                    last_transaction_lt: None,
+                },
+            )),
+        };
+
+        let response = self.send(endpoint, request.encode_to_vec()).await?;
+        let timings = match rpc::Response::decode(response.as_slice())?.result {
+            Some(rpc::response::Result::GetContractState(rpc::response::GetContractState {
+                state: Some(state),
+            })) => match state {
+                State::Exists(state) => state.gen_timings,
+                State::NotExists(state) => match state.gen_timings {
+                    Some(not_exist::GenTimings::Known(timings)) => Some(timings),
+                    _ => None,
+                },
+                State::Unchanged(timings) => Some(timings),
+            },
+            _ => None,
+        };
+
+        match timings {
+            Some(timings) => Ok(latency_from_gen_utime(timings.gen_utime)),
+            None => Err(ProtoClientError::UnknownTimings.into()),
+        }
+    }
 }
 
 #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
 #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
 impl nekoton::external::ProtoConnection for ProtoClient {
     async fn post(&self, req: nekoton::external::ProtoRequest) -> Result<Vec<u8>> {
-        let url = if req.requires_db {
-            self.alternative_url.as_ref().unwrap_or(&self.base_url)
-        } else {
-            &self.base_url
+        let url = match &self.alternative_url {
+            Some(url) if req.requires_db => url,
+            _ => {
+                self.endpoints
+                    .select(|endpoint| self.check_latency(endpoint))
+                    .await?
+            }
         };
+        self.send(url, req.data).await
+    }
+}
 
-        let response = self.client.post(url.clone()).body(req.data).send().await?;
+/// Payload compression for the protobuf transport
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProtoCompression {
+    None,
+    Gzip,
+    Zstd,
+}
 
-        let res = match response.status() {
-            StatusCode::OK => response.bytes().await?.into(),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                let msg = rpc::Error::decode(response.bytes().await?)?;
-                anyhow::bail!(msg.message)
+impl Default for ProtoCompression {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+impl ProtoCompression {
+    fn encoding(self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::Gzip => Some("gzip"),
+            Self::Zstd => Some("zstd"),
+        }
+    }
+
+    fn from_encoding(encoding: &str) -> Result<Self> {
+        match encoding.trim() {
+            "" | "identity" => Ok(Self::None),
+            "gzip" => Ok(Self::Gzip),
+            "zstd" => Ok(Self::Zstd),
+            _ => Err(ProtoClientError::UnsupportedEncoding.into()),
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(match self {
+            Self::None => data.to_vec(),
+            Self::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()?
             }
-            _ => anyhow::bail!(response.status().to_string()),
-        };
+            Self::Zstd => zstd::stream::encode_all(data, ZSTD_LEVEL)?,
+        })
+    }
+
+    fn decompress(self, data: Vec<u8>) -> Result<Vec<u8>> {
+        Ok(match self {
+            Self::None => data,
+            Self::Gzip => {
+                let mut result = Vec::new();
+                flate2::read::GzDecoder::new(data.as_slice()).read_to_end(&mut result)?;
+                result
+            }
+            Self::Zstd => zstd::stream::decode_all(data.as_slice())?,
+        })
+    }
+}
+
+/// Checks whether the `Accept-Encoding` header value contains the encoding
+fn accepts_encoding(header: &str, encoding: &str) -> bool {
+    header.split(',').any(|item| {
+        let mut parts = item.split(';');
+        let name = parts.next().unwrap_or_default().trim();
+        let disabled =
+            parts.any(|param| matches!(param.trim(), "q=0" | "q=0.0" | "q=0.00" | "q=0.000"));
+        name.eq_ignore_ascii_case(encoding) && !disabled
+    })
+}
+
+/// Smaller payloads are sent as is
+const MIN_COMPRESSED_SIZE: usize = 256;
+
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(thiserror::Error, Debug)]
+enum ProtoClientError {
+    #[error("endpoint returned unknown state timings")]
+    UnknownTimings,
+    #[error("unsupported content encoding")]
+    UnsupportedEncoding,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compression_round_trip() {
+        let data = vec![0x42; 4096];
+        for compression in [
+            ProtoCompression::None,
+            ProtoCompression::Gzip,
+            ProtoCompression::Zstd,
+        ] {
+            let compressed = compression.compress(&data).unwrap();
+            if compression != ProtoCompression::None {
+                assert!(compressed.len() < data.len());
+            }
+            assert_eq!(compression.decompress(compressed).unwrap(), data);
+        }
+    }
 
-        Ok(res)
+    #[test]
+    fn parses_accept_encoding() {
+        assert!(accepts_encoding("gzip, zstd", "zstd"));
+        assert!(accepts_encoding("br;q=1.0, ZSTD;q=0.5", "zstd"));
+        assert!(!accepts_encoding("gzip, zstd;q=0", "zstd"));
+        assert!(!accepts_encoding("identity", "gzip"));
     }
 }
@@ -0,0 +1,12 @@
+use std::time::Duration;
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct TokioTimer;
+
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl nekoton::external::Timer for TokioTimer {
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
@@ -118,6 +118,26 @@ pub mod serde_duration_ms {
     }
 }
 
+pub mod serde_optional_duration_ms {
+    use super::*;
+
+    pub fn serialize<S>(data: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        data.map(|x| StringOrNumber(x.as_millis() as u64))
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Ok(Option::<StringOrNumber>::deserialize(deserializer)?
+            .map(|StringOrNumber(x)| Duration::from_millis(x)))
+    }
+}
+
 pub mod serde_base64_array {
     use super::*;
 
@@ -1,3 +1,8 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::serde_string;
+
 pub fn compute_account_lt(transaction: &ton_block::Transaction) -> u64 {
     // TODO: read in_msg and check whether dst has rewrite_pfx
     transaction.lt + 1 + transaction.outmsg_cnt as u64
@@ -52,3 +57,85 @@ pub fn compute_total_transaction_fees(
     };
     total_fees
 }
+
+/// Detailed fees of the ordinary transaction
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeBreakdown {
+    /// Fees collected during the storage phase
+    #[serde(with = "serde_string")]
+    pub storage_fees: u128,
+    /// Gas used during the compute phase. `0` if compute phase was skipped
+    pub gas_used: u64,
+    /// Fees for the used gas
+    #[serde(with = "serde_string")]
+    pub gas_fees: u128,
+    /// Fees for the actions (part of the forward fees which is charged by the validators)
+    #[serde(with = "serde_string")]
+    pub action_fees: u128,
+    /// Forward fees for all outgoing messages
+    #[serde(with = "serde_string")]
+    pub total_fwd_fees: u128,
+    /// Outgoing messages in the order of creation
+    pub out_messages: Vec<OutMessageFees>,
+    /// The same as [`compute_total_transaction_fees`]
+    #[serde(with = "serde_string")]
+    pub total_fees: u128,
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutMessageFees {
+    /// Forward fee which is carried by the message. `0` for external messages
+    #[serde(with = "serde_string")]
+    pub fwd_fee: u128,
+    /// Value attached to the message. `0` for external messages
+    #[serde(with = "serde_string")]
+    pub value: u128,
+}
+
+/// Splits the transaction fees by phases and outgoing messages
+pub fn compute_fee_breakdown(
+    transaction: &ton_block::Transaction,
+    description: &ton_block::TransactionDescrOrdinary,
+) -> Result<FeeBreakdown> {
+    let mut breakdown = FeeBreakdown {
+        total_fees: compute_total_transaction_fees(transaction, description),
+        ..Default::default()
+    };
+
+    if let Some(phase) = &description.storage_ph {
+        breakdown.storage_fees = phase.storage_fees_collected.as_u128();
+    }
+
+    if let ton_block::TrComputePhase::Vm(phase) = &description.compute_ph {
+        breakdown.gas_used = phase.gas_used.as_u64();
+        breakdown.gas_fees = phase.gas_fees.as_u128();
+    }
+
+    if let Some(phase) = &description.action {
+        breakdown.action_fees = phase
+            .total_action_fees
+            .as_ref()
+            .map(|grams| grams.as_u128())
+            .unwrap_or_default();
+        breakdown.total_fwd_fees = phase
+            .total_fwd_fees
+            .as_ref()
+            .map(|grams| grams.as_u128())
+            .unwrap_or_default();
+    }
+
+    transaction.out_msgs.iterate(|out_msg| {
+        breakdown.out_messages.push(match out_msg.0.header() {
+            ton_block::CommonMsgInfo::IntMsgInfo(header) => OutMessageFees {
+                fwd_fee: header.fwd_fee.as_u128(),
+                value: header.value.grams.as_u128(),
+            },
+            _ => OutMessageFees::default(),
+        });
+        Ok(true)
+    })?;
+
+    Ok(breakdown)
+}
@@ -1,21 +1,25 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use anyhow::Result;
 use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
-use ton_block::MsgAddressInt;
+use ton_block::{GetRepresentationHash, MsgAddressInt};
+use ton_types::UInt256;
 
 use nekoton_abi::{Executor, LastTransactionId};
 use nekoton_utils::*;
 
 use super::models::{
-    ContractState, PendingTransaction, ReliableBehavior, TransactionsBatchInfo,
-    TransactionsBatchType,
+    AccountSubscriptionError, ContractState, PendingTransaction, RebroadcastPolicy,
+    ReliableBehavior, TransactionsBatchInfo, TransactionsBatchType, TransactionsGap,
+    TransactionsGapStatus,
 };
 use super::{utils, PollingMethod};
 use crate::core::utils::{MessageContext, PendingTransactionsExt};
-use crate::transport::models::{RawContractState, RawTransaction};
-use crate::transport::Transport;
+use crate::external::Storage;
+use crate::transport::models::{AccountUpdate, RawContractState, RawTransaction};
+use crate::transport::{AccountUpdatesStream, Transport};
 
 /// Used as a base object for different listeners implementation
 pub struct ContractSubscription {
@@ -26,6 +30,13 @@ pub struct ContractSubscription {
     latest_known_lt: Option<u64>,
     pending_transactions: Vec<PendingTransaction>,
     transactions_synced: bool,
+    gaps: Vec<TransactionsGap>,
+    /// Gaps which were not reported to the handler yet
+    new_gaps: Vec<TransactionsGap>,
+    /// Failed backfill attempts by the `until_lt` of the gap
+    gap_attempts: HashMap<u64, u32>,
+    rebroadcast_policy: Option<RebroadcastPolicy>,
+    sent_messages: Vec<SentMessage>,
 }
 
 impl ContractSubscription {
@@ -44,6 +55,11 @@ impl ContractSubscription {
             latest_known_lt: None,
             pending_transactions: Vec::new(),
             transactions_synced: false,
+            gaps: Vec::new(),
+            new_gaps: Vec::new(),
+            gap_attempts: HashMap::new(),
+            rebroadcast_policy: None,
+            sent_messages: Vec::new(),
         };
 
         result.transactions_synced = !result
@@ -74,6 +90,59 @@ impl ContractSubscription {
         Ok(result)
     }
 
+    /// Resumes the subscription from the state returned by [`ContractSubscription::state`].
+    ///
+    /// Transactions which were received before are not requested again.
+    /// Pending transactions are restored and will be resolved during refresh
+    pub async fn subscribe_from_snapshot(
+        clock: Arc<dyn Clock>,
+        transport: Arc<dyn Transport>,
+        address: MsgAddressInt,
+        snapshot: ContractSubscriptionState,
+        on_contract_state: OnContractState<'_>,
+    ) -> Result<Self> {
+        if snapshot.address != address {
+            return Err(AccountSubscriptionError::SnapshotAddressMismatch.into());
+        }
+
+        let mut result = Self {
+            clock,
+            transport,
+            address,
+            contract_state: Default::default(),
+            latest_known_lt: snapshot.latest_known_lt,
+            pending_transactions: snapshot.pending_transactions,
+            transactions_synced: false,
+            gaps: snapshot.gaps,
+            new_gaps: Vec::new(),
+            gap_attempts: HashMap::new(),
+            rebroadcast_policy: None,
+            sent_messages: Vec::new(),
+        };
+
+        // NOTE: full state is requested to notify the handler even if nothing changed
+        result
+            .refresh_contract_state_impl(None, on_contract_state)
+            .await?;
+
+        result.transactions_synced =
+            snapshot.transactions_synced && result.contract_state.last_lt == snapshot.last_lt;
+
+        Ok(result)
+    }
+
+    /// Returns the state which is required to resume the subscription after restart
+    pub fn state(&self) -> ContractSubscriptionState {
+        ContractSubscriptionState {
+            address: self.address.clone(),
+            last_lt: self.contract_state.last_lt,
+            latest_known_lt: self.latest_known_lt,
+            pending_transactions: self.pending_transactions.clone(),
+            transactions_synced: self.transactions_synced,
+            gaps: self.gaps.clone(),
+        }
+    }
+
     pub fn transport(&self) -> &Arc<dyn Transport> {
         &self.transport
     }
@@ -90,11 +159,26 @@ impl ContractSubscription {
         &self.pending_transactions
     }
 
+    /// Ranges of transactions which are known to be missing
+    pub fn gaps(&self) -> &[TransactionsGap] {
+        &self.gaps
+    }
+
+    /// Logical time of the latest received transaction
+    pub fn latest_known_lt(&self) -> Option<u64> {
+        self.latest_known_lt
+    }
+
+    /// Whether all transactions until the current state were received
+    pub fn transactions_synced(&self) -> bool {
+        self.transactions_synced || self.contract_state.last_transaction_id.is_none()
+    }
+
     pub fn polling_method(&self) -> PollingMethod {
         if self.pending_transactions.is_empty() {
             // Relaxed polling when there are no pending transactions
             PollingMethod::Manual
-        } else if self.transactions_synced {
+        } else if self.transactions_synced && self.gaps.is_empty() {
             // All transports could use reliable polling if there are some
             // pending transactions and all recent transactions were received
             PollingMethod::Reliable
@@ -108,7 +192,8 @@ impl ContractSubscription {
                 // and not all recent transactions were received.
                 //
                 // It is needed to receive all these transactions first,
-                // otherwise there will be gaps.
+                // otherwise there will be gaps. Known gaps are also
+                // repaired only during refresh.
                 ReliableBehavior::BlockWalking => PollingMethod::Manual,
             }
         }
@@ -118,11 +203,38 @@ impl ContractSubscription {
         self.pending_transactions.push(pending_transaction);
     }
 
+    pub fn rebroadcast_policy(&self) -> Option<RebroadcastPolicy> {
+        self.rebroadcast_policy
+    }
+
+    /// Enables resending of messages sent after this call.
+    ///
+    /// NOTE: messages are not saved in [`ContractSubscriptionState`],
+    /// so they are not resent after restart
+    pub fn set_rebroadcast_policy(&mut self, policy: Option<RebroadcastPolicy>) {
+        self.rebroadcast_policy = policy;
+        if policy.is_none() {
+            self.sent_messages.clear();
+        }
+    }
+
     pub async fn send(
         &mut self,
         message: &ton_block::Message,
         expire_at: u32,
     ) -> Result<PendingTransaction> {
+        // The same message is only broadcast again
+        let message_hash = message.hash()?;
+        if let Some(pending_transaction) = self
+            .pending_transactions
+            .iter()
+            .find(|pending| pending.message_hash == message_hash)
+            .cloned()
+        {
+            self.transport.send_message(message).await?;
+            return Ok(pending_transaction);
+        }
+
         let ctx = MessageContext {
             latest_lt: self
                 .contract_state
@@ -138,7 +250,18 @@ impl ContractSubscription {
 
         match self.transport.send_message(message).await {
             // return pending transaction on success
-            Ok(()) => Ok(pending_transaction),
+            Ok(()) => {
+                if self.rebroadcast_policy.is_some() {
+                    self.sent_messages.push(SentMessage {
+                        message_hash,
+                        message: message.clone(),
+                        expire_at,
+                        sent_at: ctx.created_at,
+                        attempts: 0,
+                    });
+                }
+                Ok(pending_transaction)
+            }
             // remove pending transaction from queue on error
             Err(e) => {
                 self.pending_transactions.cancel(&pending_transaction);
@@ -187,10 +310,55 @@ impl ContractSubscription {
             self.check_expired_transactions(current_utime, on_message_expired);
         }
 
+        self.rebroadcast_messages().await;
+
         Ok(())
     }
 
-    pub fn handle_block(
+    /// Sends again pending messages according to the rebroadcast policy.
+    /// Returns the number of resent messages.
+    ///
+    /// It is called during refresh and block handling
+    pub async fn rebroadcast_messages(&mut self) -> usize {
+        let policy = match self.rebroadcast_policy {
+            Some(policy) => policy,
+            None => return 0,
+        };
+
+        let pending_transactions = &self.pending_transactions;
+        self.sent_messages.retain(|sent| {
+            pending_transactions
+                .iter()
+                .any(|pending| pending.message_hash == sent.message_hash)
+        });
+
+        let now = self.clock.now_sec_u64() as u32;
+        let mut count = 0;
+        for sent in &mut self.sent_messages {
+            if now >= sent.expire_at
+                || now < sent.sent_at.saturating_add(policy.interval_sec)
+                || matches!(policy.max_attempts, Some(max) if sent.attempts >= max)
+            {
+                continue;
+            }
+
+            sent.sent_at = now;
+            sent.attempts += 1;
+
+            // NOTE: errors are ignored because the message could be already processed
+            match self.transport.send_message(&sent.message).await {
+                Ok(()) => count += 1,
+                Err(e) => log::debug!(
+                    "Failed to rebroadcast message {:x}: {e:?}",
+                    sent.message_hash
+                ),
+            }
+        }
+
+        count
+    }
+
+    pub async fn handle_block(
         &mut self,
         block: &ton_block::Block,
         on_transactions_found: OnTransactionsFound<'_>,
@@ -205,6 +373,7 @@ impl ContractSubscription {
 
             if let Some((mut new_transactions, batch_info)) = new_transactions {
                 new_transactions.reverse();
+                self.add_gaps(find_gaps(&new_transactions, self.latest_known_lt));
                 self.check_executed_transactions(&new_transactions, on_message_sent);
 
                 if let Some(first) = new_transactions.first() {
@@ -216,10 +385,103 @@ impl ContractSubscription {
 
         self.check_expired_transactions(block.current_utime, on_message_expired);
 
+        self.rebroadcast_messages().await;
+
         Ok(new_account_state)
     }
 
+    /// Returns a stream of account updates if the transport supports push notifications.
+    ///
+    /// Each update must be passed to [`ContractSubscription::handle_account_update`]
+    pub fn subscribe_updates(&self) -> Option<AccountUpdatesStream> {
+        self.transport
+            .subscribe_accounts(std::slice::from_ref(&self.address))
+    }
+
+    /// Applies an update received from [`ContractSubscription::subscribe_updates`]
+    pub fn handle_account_update(
+        &mut self,
+        update: AccountUpdate,
+        on_contract_state: OnContractState<'_>,
+        on_transactions_found: OnTransactionsFound<'_>,
+        on_message_sent: OnMessageSent<'_>,
+        on_message_expired: OnMessageExpired<'_>,
+    ) -> Result<()> {
+        if update.address != self.address {
+            return Ok(());
+        }
+
+        let new_contract_state = update.state.brief();
+        if new_contract_state.last_lt > self.contract_state.last_lt {
+            on_contract_state(&update.state);
+            self.contract_state = new_contract_state;
+            self.transactions_synced = false;
+        } else {
+            self.contract_state.gen_timings = new_contract_state.gen_timings;
+        }
+
+        let latest_known_lt = self.latest_known_lt;
+        let new_transactions = update
+            .transactions
+            .into_iter()
+            .filter(|tx| !matches!(latest_known_lt, Some(lt) if tx.data.lt <= lt))
+            .collect::<Vec<_>>();
+
+        if let (Some(first), Some(last)) = (new_transactions.first(), new_transactions.last()) {
+            // Transactions in update are in descending order
+            let max_lt = first.data.lt;
+            let min_lt = last.data.lt;
+
+            // Pushed transactions are skipped if there is a gap between them
+            // and the last known transaction. The next `refresh` will load them all
+            if last.data.prev_trans_lt != latest_known_lt.unwrap_or_default() {
+                self.transactions_synced = false;
+                return Ok(());
+            }
+
+            self.transactions_synced = matches!(
+                self.contract_state.last_transaction_id,
+                Some(LastTransactionId::Exact(id)) if id.lt == max_lt
+            );
+
+            self.add_gaps(find_gaps(&new_transactions, latest_known_lt));
+
+            self.check_executed_transactions(&new_transactions, on_message_sent);
+            self.latest_known_lt = Some(max_lt);
+
+            on_transactions_found(
+                new_transactions,
+                TransactionsBatchInfo {
+                    min_lt,
+                    max_lt,
+                    batch_type: TransactionsBatchType::New,
+                },
+            );
+        }
+
+        // Only check expired messages when we can guarantee, that
+        // all transactions until current state were received
+        if !self.pending_transactions.is_empty() && self.transactions_synced {
+            let current_utime = self
+                .contract_state
+                .gen_timings
+                .current_utime(self.clock.as_ref());
+            self.check_expired_transactions(current_utime, on_message_expired);
+        }
+
+        Ok(())
+    }
+
     pub async fn estimate_fees(&self, message: &ton_block::Message) -> Result<u128> {
+        let breakdown = self.estimate_fee_breakdown(message).await?;
+        Ok(breakdown.total_fees)
+    }
+
+    /// Executes the message locally and returns its detailed fees
+    pub async fn estimate_fee_breakdown(
+        &self,
+        message: &ton_block::Message,
+    ) -> Result<FeeBreakdown> {
         let transaction = self
             .execute_transaction_locally(
                 message,
@@ -232,9 +494,12 @@ impl ContractSubscription {
 
         Ok(
             if let ton_block::TransactionDescr::Ordinary(descr) = transaction.read_description()? {
-                compute_total_transaction_fees(&transaction, &descr)
+                compute_fee_breakdown(&transaction, &descr)?
             } else {
-                transaction.total_fees.grams.as_u128()
+                FeeBreakdown {
+                    total_fees: transaction.total_fees.grams.as_u128(),
+                    ..Default::default()
+                }
             },
         )
     }
@@ -328,6 +593,10 @@ impl ContractSubscription {
                 }
             };
 
+            // NOTE: the response could be incomplete if the transport
+            // returned an empty page before reaching the latest known transaction
+            self.add_gaps(find_gaps(&new_transactions, self.latest_known_lt));
+
             // requires `&mut self`, so `request_transactions` must use outer objects
             self.check_executed_transactions(&new_transactions, on_message_sent);
 
@@ -382,6 +651,93 @@ impl ContractSubscription {
         Ok(())
     }
 
+    /// Reports new gaps and loads missing transactions for all known gaps.
+    ///
+    /// Gaps which could not be loaded completely are kept and retried
+    /// on the next call. After [`MAX_GAP_BACKFILL_ATTEMPTS`] failed calls
+    /// the gap is reported as unrecoverable and forgotten
+    pub async fn backfill_gaps(
+        &mut self,
+        on_transactions_found: OnTransactionsFound<'_>,
+        on_message_sent: OnMessageSent<'_>,
+        on_transactions_gap: OnTransactionsGap<'_>,
+    ) -> Result<()> {
+        for gap in std::mem::take(&mut self.new_gaps) {
+            on_transactions_gap(gap, TransactionsGapStatus::Detected);
+        }
+
+        let mut i = 0;
+        while i < self.gaps.len() {
+            let gap = self.gaps[i];
+
+            let mut transactions = utils::request_transactions(
+                self.transport.as_ref(),
+                &self.address,
+                gap.until_lt,
+                Some(gap.after_lt),
+                self.transport.info().max_transactions_per_fetch,
+                None,
+            );
+
+            let mut missing_transactions = Vec::<RawTransaction>::new();
+            while let Some(transactions) = transactions.next().await {
+                missing_transactions.extend(transactions?.into_iter());
+            }
+            drop(transactions);
+
+            let complete = match missing_transactions.first() {
+                Some(first) => {
+                    first.data.lt == gap.until_lt
+                        && find_gaps(&missing_transactions, Some(gap.after_lt)).is_empty()
+                }
+                None => false,
+            };
+            if !complete {
+                let attempts = self.gap_attempts.entry(gap.until_lt).or_default();
+                *attempts += 1;
+                if *attempts < MAX_GAP_BACKFILL_ATTEMPTS {
+                    log::debug!(
+                        "Transactions gap {}..{} of {} is not available yet",
+                        gap.after_lt,
+                        gap.until_lt,
+                        self.address
+                    );
+                    i += 1;
+                    continue;
+                }
+
+                log::warn!(
+                    "Transactions gap {}..{} of {} is unrecoverable",
+                    gap.after_lt,
+                    gap.until_lt,
+                    self.address
+                );
+                self.gaps.remove(i);
+                self.gap_attempts.remove(&gap.until_lt);
+                on_transactions_gap(gap, TransactionsGapStatus::Unrecoverable);
+                continue;
+            }
+
+            self.gaps.remove(i);
+            self.gap_attempts.remove(&gap.until_lt);
+            self.check_executed_transactions(&missing_transactions, on_message_sent);
+
+            // Transactions in response are in descending order
+            let batch_info = TransactionsBatchInfo {
+                min_lt: missing_transactions
+                    .last()
+                    .map(|tx| tx.data.lt)
+                    .unwrap_or_default(),
+                max_lt: gap.until_lt,
+                batch_type: TransactionsBatchType::Old,
+            };
+            on_transactions_found(missing_transactions, batch_info);
+            on_transactions_gap(gap, TransactionsGapStatus::Repaired);
+        }
+
+        Ok(())
+    }
+
     async fn refresh_contract_state_impl(
         &mut self,
         prev_trans_lt: Option<u64>,
@@ -423,6 +779,21 @@ impl ContractSubscription {
         Ok(updated)
     }
 
+    fn add_gaps(&mut self, gaps: Vec<TransactionsGap>) {
+        for gap in gaps {
+            if !self.gaps.contains(&gap) {
+                log::debug!(
+                    "Found transactions gap {}..{} of {}",
+                    gap.after_lt,
+                    gap.until_lt,
+                    self.address
+                );
+                self.gaps.push(gap);
+                self.new_gaps.push(gap);
+            }
+        }
+    }
+
     /// Searches executed pending transactions and notifies the handler if some were found
     fn check_executed_transactions(
         &mut self,
@@ -459,11 +830,98 @@ impl ContractSubscription {
     }
 }
 
+struct SentMessage {
+    message_hash: UInt256,
+    message: ton_block::Message,
+    expire_at: u32,
+    sent_at: u32,
+    attempts: u32,
+}
+
 type OnContractState<'a> = &'a mut (dyn FnMut(&RawContractState) + Send + Sync);
 type OnTransactionsFound<'a> =
     &'a mut (dyn FnMut(Vec<RawTransaction>, TransactionsBatchInfo) + Send + Sync);
 type OnMessageSent<'a> = &'a mut (dyn FnMut(PendingTransaction, RawTransaction) + Send + Sync);
 type OnMessageExpired<'a> = &'a mut (dyn FnMut(PendingTransaction) + Send + Sync);
+type OnTransactionsGap<'a> =
+    &'a mut (dyn FnMut(TransactionsGap, TransactionsGapStatus) + Send + Sync);
+
+/// Number of failed [`ContractSubscription::backfill_gaps`] calls after which
+/// the gap is considered unrecoverable (e.g. pruned by the node)
+pub const MAX_GAP_BACKFILL_ATTEMPTS: u32 = 10;
+
+/// Finds missing transactions using `prev_trans_lt` links.
+///
+/// Transactions must be sorted in descending order
+fn find_gaps(
+    transactions: &[RawTransaction],
+    latest_known_lt: Option<u64>,
+) -> Vec<TransactionsGap> {
+    let mut gaps = Vec::new();
+    for pair in transactions.windows(2) {
+        let (newer, older) = (&pair[0].data, &pair[1].data);
+        if newer.prev_trans_lt > older.lt {
+            gaps.push(TransactionsGap {
+                after_lt: older.lt,
+                until_lt: newer.prev_trans_lt,
+            });
+        }
+    }
+
+    if let (Some(latest_known_lt), Some(oldest)) = (latest_known_lt, transactions.last()) {
+        if oldest.data.prev_trans_lt > latest_known_lt {
+            gaps.push(TransactionsGap {
+                after_lt: latest_known_lt,
+                until_lt: oldest.data.prev_trans_lt,
+            });
+        }
+    }
+
+    gaps
+}
+
+pub const CONTRACT_SUBSCRIPTION_STORAGE_KEY: &str = "__core__contract_subscription_";
+
+/// Serializable state of [`ContractSubscription`]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContractSubscriptionState {
+    #[serde(with = "serde_address")]
+    pub address: MsgAddressInt,
+    /// Lt of the latest known contract state
+    #[serde(with = "serde_u64")]
+    pub last_lt: u64,
+    /// Lt of the latest received transaction
+    #[serde(default, with = "serde_optional_u64")]
+    pub latest_known_lt: Option<u64>,
+    pub pending_transactions: Vec<PendingTransaction>,
+    pub transactions_synced: bool,
+    /// Ranges of missing transactions
+    #[serde(default)]
+    pub gaps: Vec<TransactionsGap>,
+}
+
+impl ContractSubscriptionState {
+    pub async fn load(storage: &dyn Storage, address: &MsgAddressInt) -> Result<Option<Self>> {
+        match storage.get(&make_key(address)).await? {
+            Some(data) => Ok(Some(serde_json::from_str(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn save(&self, storage: &dyn Storage) -> Result<()> {
+        let data = serde_json::to_string(self)?;
+        storage.set(&make_key(&self.address), &data).await
+    }
+
+    pub async fn remove(storage: &dyn Storage, address: &MsgAddressInt) -> Result<()> {
+        storage.remove(&make_key(address)).await
+    }
+}
+
+fn make_key(address: &MsgAddressInt) -> String {
+    format!("{CONTRACT_SUBSCRIPTION_STORAGE_KEY}{address}")
+}
 
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(default, rename_all = "camelCase")]
@@ -477,6 +935,442 @@ pub struct TransactionExecutionOptions {
 mod tests {
     use super::*;
 
+    #[cfg(feature = "local_transport")]
+    #[tokio::test]
+    async fn resumes_from_snapshot() -> Result<()> {
+        use ton_block::GetRepresentationHash;
+
+        use crate::transport::local::tests::{
+            dst_address, make_transfer, src_address, test_address,
+        };
+        use crate::transport::local::LocalTransport;
+
+        let clock = Arc::new(SimpleClock);
+        let transport = Arc::new(LocalTransport::new(clock.clone()));
+        let dst = dst_address();
+
+        transport
+            .send_message(&make_transfer(1_000_000_000))
+            .await?;
+
+        let mut subscription = ContractSubscription::subscribe(
+            clock.clone(),
+            transport.clone(),
+            dst.clone(),
+            &mut |_| {},
+            None,
+        )
+        .await?;
+
+        let message = make_transfer(2_000_000_000);
+        let now = clock.now_sec_u64() as u32;
+        subscription.add_pending_transaction(PendingTransaction {
+            message_hash: message.hash()?,
+            src: Some(src_address()),
+            latest_lt: subscription.contract_state().last_lt,
+            created_at: now,
+            expire_at: now + 60,
+        });
+
+        let snapshot: ContractSubscriptionState =
+            serde_json::from_str(&serde_json::to_string(&subscription.state())?)?;
+        assert_eq!(snapshot, subscription.state());
+        drop(subscription);
+
+        transport.send_message(&message).await?;
+
+        let mut states = 0;
+        let mut subscription = ContractSubscription::subscribe_from_snapshot(
+            clock.clone(),
+            transport.clone(),
+            dst.clone(),
+            snapshot,
+            &mut |_| states += 1,
+        )
+        .await?;
+        assert_eq!(states, 1);
+        assert_eq!(subscription.pending_transactions().len(), 1);
+
+        let mut found = Vec::new();
+        let mut sent = Vec::new();
+        subscription
+            .refresh(
+                &mut |_| {},
+                &mut |transactions, _| found.extend(transactions),
+                &mut |pending, transaction| sent.push((pending, transaction)),
+                &mut |_| panic!("message must not expire"),
+            )
+            .await?;
+
+        // Only the new transaction is received
+        assert_eq!(found.len(), 1);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].1, found[0]);
+        assert!(subscription.pending_transactions().is_empty());
+
+        assert!(ContractSubscription::subscribe_from_snapshot(
+            clock,
+            transport,
+            test_address(0x33),
+            subscription.state(),
+            &mut |_| {},
+        )
+        .await
+        .is_err());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "local_transport")]
+    #[tokio::test]
+    async fn backfills_gaps() -> Result<()> {
+        use crate::transport::local::tests::{dst_address, make_transfer};
+        use crate::transport::local::LocalTransport;
+
+        let clock = Arc::new(SimpleClock);
+        let transport = Arc::new(LocalTransport::new(clock.clone()));
+        let dst = dst_address();
+
+        transport
+            .send_message(&make_transfer(1_000_000_000))
+            .await?;
+
+        let mut subscription = ContractSubscription::subscribe(
+            clock.clone(),
+            transport.clone(),
+            dst.clone(),
+            &mut |_| {},
+            None,
+        )
+        .await?;
+        let first_lt = subscription.latest_known_lt.unwrap();
+
+        transport
+            .send_message(&make_transfer(2_000_000_000))
+            .await?;
+        transport
+            .send_message(&make_transfer(3_000_000_000))
+            .await?;
+
+        // Simulate a missed block with the second transaction
+        let transactions = transport.get_transactions(&dst, u64::MAX, 10).await?;
+        assert_eq!(transactions.len(), 3);
+        let (last, second) = (&transactions[0], &transactions[1]);
+
+        let gaps = find_gaps(std::slice::from_ref(last), subscription.latest_known_lt);
+        assert_eq!(
+            gaps,
+            [TransactionsGap {
+                after_lt: first_lt,
+                until_lt: second.data.lt,
+            }]
+        );
+        assert!(find_gaps(&transactions, None).is_empty());
+
+        subscription.add_gaps(gaps);
+        subscription.latest_known_lt = Some(last.data.lt);
+
+        let mut found = Vec::new();
+        let mut statuses = Vec::new();
+        subscription
+            .backfill_gaps(
+                &mut |transactions, _| found.extend(transactions),
+                &mut |_, _| {},
+                &mut |_, status| statuses.push(status),
+            )
+            .await?;
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].hash, second.hash);
+        assert_eq!(
+            statuses,
+            [
+                TransactionsGapStatus::Detected,
+                TransactionsGapStatus::Repaired
+            ]
+        );
+        assert!(subscription.gaps().is_empty());
+
+        // Gap which will never be filled
+        let gap = TransactionsGap {
+            after_lt: second.data.lt,
+            until_lt: last.data.lt - 1,
+        };
+        subscription.add_gaps(vec![gap]);
+
+        let mut statuses = Vec::new();
+        for _ in 0..MAX_GAP_BACKFILL_ATTEMPTS {
+            subscription
+                .backfill_gaps(&mut |_, _| {}, &mut |_, _| {}, &mut |gap, status| {
+                    statuses.push((gap, status))
+                })
+                .await?;
+        }
+
+        assert_eq!(
+            statuses,
+            [
+                (gap, TransactionsGapStatus::Detected),
+                (gap, TransactionsGapStatus::Unrecoverable)
+            ]
+        );
+        assert!(subscription.gaps().is_empty());
+
+        Ok(())
+    }
+
+    /// Subscription to an account which never receives sent messages
+    #[cfg(feature = "local_transport")]
+    async fn make_rebroadcast_subscription(
+        clock: Arc<ClockWithOffset>,
+    ) -> Result<(
+        ContractSubscription,
+        Arc<crate::transport::metrics::InMemoryTransportMetrics>,
+    )> {
+        use crate::transport::local::tests::{dst_address, make_transfer};
+        use crate::transport::local::LocalTransport;
+        use crate::transport::metrics::{InMemoryTransportMetrics, InstrumentedTransport};
+        use crate::transport::offline::{OfflineSnapshot, OfflineTransport};
+
+        let local = LocalTransport::new(clock.clone());
+
+        let dst = dst_address();
+        local.send_message(&make_transfer(1_000_000_000)).await?;
+
+        // Offline transport accepts messages but never executes them
+        let snapshot = OfflineSnapshot::export(&local, clock.as_ref(), &[dst.clone()]).await?;
+        let metrics = Arc::new(InMemoryTransportMetrics::new());
+        let transport = Arc::new(InstrumentedTransport::new(
+            OfflineTransport::new(snapshot)?,
+            metrics.clone(),
+        ));
+
+        let mut subscription =
+            ContractSubscription::subscribe(clock, transport, dst, &mut |_| {}, None).await?;
+        subscription.set_rebroadcast_policy(Some(RebroadcastPolicy {
+            interval_sec: 10,
+            max_attempts: Some(2),
+        }));
+
+        Ok((subscription, metrics))
+    }
+
+    #[cfg(feature = "local_transport")]
+    fn sent_count(metrics: &crate::transport::metrics::InMemoryTransportMetrics) -> u64 {
+        metrics
+            .methods()
+            .get(&crate::transport::retry::TransportMethod::SendMessage)
+            .map(|stats| stats.calls)
+            .unwrap_or_default()
+    }
+
+    #[cfg(feature = "local_transport")]
+    fn make_external_message(dst: &MsgAddressInt) -> ton_block::Message {
+        ton_block::Message::with_ext_in_header(ton_block::ExternalInboundMessageHeader {
+            dst: dst.clone(),
+            ..Default::default()
+        })
+    }
+
+    #[cfg(feature = "local_transport")]
+    #[tokio::test]
+    async fn rebroadcasts_pending_messages() -> Result<()> {
+        let clock = Arc::new(ClockWithOffset::new(0));
+        let (mut subscription, metrics) = make_rebroadcast_subscription(clock.clone()).await?;
+
+        let message = make_external_message(subscription.address());
+        let expire_at = clock.now_sec_u64() as u32 + 60;
+        subscription.send(&message, expire_at).await?;
+
+        // Duplicates are not tracked twice
+        subscription.send(&message, expire_at).await?;
+        assert_eq!(subscription.pending_transactions().len(), 1);
+        assert_eq!(sent_count(&metrics), 2);
+
+        assert_eq!(subscription.rebroadcast_messages().await, 0);
+        for _ in 0..3 {
+            clock.update_offset(clock.offset_ms() + 10_000);
+            subscription
+                .refresh(&mut |_| {}, &mut |_, _| {}, &mut |_, _| {}, &mut |_| {
+                    panic!("message must not expire")
+                })
+                .await?;
+        }
+
+        // Limited by `max_attempts`
+        assert_eq!(sent_count(&metrics), 4);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "local_transport")]
+    #[tokio::test]
+    async fn rebroadcasts_pending_messages_while_walking_blocks() -> Result<()> {
+        let clock = Arc::new(ClockWithOffset::new(0));
+        let (mut subscription, metrics) = make_rebroadcast_subscription(clock.clone()).await?;
+
+        let message = make_external_message(subscription.address());
+        let expire_at = clock.now_sec_u64() as u32 + 60;
+        subscription.send(&message, expire_at).await?;
+        assert_eq!(sent_count(&metrics), 1);
+
+        // Block without transactions of the account
+        let mut info = ton_block::BlockInfo::default();
+        info.set_shard(ton_block::ShardIdent::full(0));
+        info.set_seq_no(1)?;
+        info.set_gen_utime(ton_block::UnixTime32::new(clock.now_sec_u64() as u32));
+        let block = ton_block::Block::with_params(
+            0,
+            info,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        )?;
+
+        for _ in 0..3 {
+            clock.update_offset(clock.offset_ms() + 10_000);
+            subscription
+                .handle_block(&block, &mut |_, _| {}, &mut |_, _| {}, &mut |_| {
+                    panic!("message must not expire")
+                })
+                .await?;
+        }
+
+        // Limited by `max_attempts`
+        assert_eq!(sent_count(&metrics), 3);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "local_transport")]
+    #[tokio::test]
+    async fn estimates_fee_breakdown() -> Result<()> {
+        use anyhow::Context;
+        use ed25519_dalek::Signer;
+
+        use crate::core::models::{Expiration, PendingTransaction, Transaction};
+        use crate::core::ton_wallet::{
+            compute_address, Gift, TonWallet, TonWalletSubscriptionHandler, TransferAction,
+            WalletType,
+        };
+        use crate::transport::local::tests::{make_transfer_to, src_address};
+        use crate::transport::local::LocalTransport;
+
+        struct Handler;
+
+        impl TonWalletSubscriptionHandler for Handler {
+            fn on_message_sent(&self, _: PendingTransaction, _: Option<Transaction>) {}
+
+            fn on_message_expired(&self, _: PendingTransaction) {}
+        }
+
+        const AMOUNT: u64 = 100_000_000;
+
+        async fn prepare_transfer(
+            transport: &LocalTransport,
+            wallet: &TonWallet,
+            keypair: &ed25519_dalek::Keypair,
+        ) -> Result<crate::crypto::SignedMessage> {
+            let account = match transport.get_contract_state(wallet.address()).await? {
+                RawContractState::Exists(contract) => contract.account,
+                RawContractState::NotExists { .. } => anyhow::bail!("account not found"),
+            };
+            let gift = Gift {
+                flags: 3,
+                bounce: false,
+                destination: src_address(),
+                amount: AMOUNT,
+                body: None,
+                state_init: None,
+            };
+            match wallet.prepare_transfer(
+                &account,
+                &keypair.public,
+                gift,
+                Expiration::Timeout(60),
+            )? {
+                TransferAction::Sign(unsigned) => {
+                    unsigned.sign(&keypair.sign(unsigned.hash()).to_bytes())
+                }
+                TransferAction::DeployFirst => {
+                    anyhow::bail!("wallet must be deployed with transfer")
+                }
+            }
+        }
+
+        let clock = Arc::new(SimpleClock);
+        let transport = Arc::new(LocalTransport::new(clock.clone()));
+
+        let secret = ed25519_dalek::SecretKey::from_bytes(&[1; 32])?;
+        let public = ed25519_dalek::PublicKey::from(&secret);
+        let keypair = ed25519_dalek::Keypair { secret, public };
+        let address = compute_address(&public, WalletType::WalletV3, 0);
+
+        transport
+            .send_message(&make_transfer_to(&address, 1_000_000_000))
+            .await?;
+
+        let mut wallet = TonWallet::subscribe(
+            clock,
+            transport.clone(),
+            0,
+            public,
+            WalletType::WalletV3,
+            Arc::new(Handler),
+        )
+        .await?;
+
+        // Deploy the wallet with the first transfer
+        let deploy = prepare_transfer(&transport, &wallet, &keypair).await?;
+        transport.send_message(&deploy.message).await?;
+        wallet.refresh().await?;
+
+        let signed = prepare_transfer(&transport, &wallet, &keypair).await?;
+        let breakdown = wallet.estimate_fee_breakdown(&signed.message).await?;
+        assert_eq!(
+            breakdown.total_fees,
+            wallet.estimate_fees(&signed.message).await?
+        );
+
+        assert!(breakdown.gas_used > 0);
+        assert!(breakdown.gas_fees > 0);
+        assert_eq!(breakdown.out_messages.len(), 1);
+        assert_eq!(breakdown.out_messages[0].value, AMOUNT as u128);
+        assert!(breakdown.out_messages[0].fwd_fee > 0);
+
+        let transaction = wallet
+            .contract_subscription()
+            .execute_transaction_locally(&signed.message, Default::default())
+            .await?;
+        let action = match transaction.read_description()? {
+            ton_block::TransactionDescr::Ordinary(descr) => {
+                descr.action.context("action phase not found")?
+            }
+            _ => anyhow::bail!("unexpected transaction type"),
+        };
+        assert_eq!(
+            breakdown.total_fees,
+            transaction.total_fees.grams.as_u128()
+                + action
+                    .total_fwd_fees
+                    .map(|fees| fees.as_u128())
+                    .unwrap_or_default()
+                - action
+                    .total_action_fees
+                    .map(|fees| fees.as_u128())
+                    .unwrap_or_default()
+        );
+        assert_eq!(
+            breakdown.total_fwd_fees,
+            breakdown.action_fees + breakdown.out_messages[0].fwd_fee
+        );
+
+        let json = serde_json::to_string(&breakdown)?;
+        assert_eq!(serde_json::from_str::<FeeBreakdown>(&json)?, breakdown);
+
+        Ok(())
+    }
+
     #[test]
     fn executor_params_serialization() {
         assert_eq!(
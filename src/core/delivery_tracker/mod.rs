@@ -0,0 +1,289 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use ton_block::GetRepresentationHash;
+
+use nekoton_utils::*;
+
+use crate::crypto::SignedMessage;
+use crate::external::Timer;
+use crate::transport::models::RawTransaction;
+use crate::transport::retry::is_retryable_error;
+use crate::transport::{Transport, TransportError};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeliveryTrackerSettings {
+    /// Delay before the first check. Default: `1000`
+    #[serde(with = "serde_duration_ms")]
+    pub initial_backoff: Duration,
+    /// Max delay between checks. Default: `10000`
+    #[serde(with = "serde_duration_ms")]
+    pub max_backoff: Duration,
+    /// Backoff growth factor. Default: `1.5`
+    pub multiplier: f64,
+    /// How long the transaction is searched after the message expiration,
+    /// because the transport could index it with some delay. Default: `10000`
+    #[serde(with = "serde_duration_ms")]
+    pub expiration_grace_period: Duration,
+}
+
+impl Default for DeliveryTrackerSettings {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 1.5,
+            expiration_grace_period: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Final status of the sent message
+#[derive(Debug, Clone)]
+pub enum DeliveryStatus {
+    /// Message was included into the transaction
+    Delivered(RawTransaction),
+    /// No transaction was found until the message expiration
+    Expired,
+    /// Message was not accepted by the transport
+    Rejected(String),
+}
+
+/// Waits for the transactions of sent messages.
+///
+/// Unlike [`ContractSubscription`](super::ContractSubscription) it doesn't track
+/// the account state, only the destination transaction of the message.
+pub struct DeliveryTracker {
+    clock: Arc<dyn Clock>,
+    transport: Arc<dyn Transport>,
+    timer: Arc<dyn Timer>,
+    settings: DeliveryTrackerSettings,
+}
+
+impl DeliveryTracker {
+    pub fn new(
+        clock: Arc<dyn Clock>,
+        transport: Arc<dyn Transport>,
+        timer: Arc<dyn Timer>,
+    ) -> Self {
+        Self::with_settings(clock, transport, timer, Default::default())
+    }
+
+    pub fn with_settings(
+        clock: Arc<dyn Clock>,
+        transport: Arc<dyn Transport>,
+        timer: Arc<dyn Timer>,
+        settings: DeliveryTrackerSettings,
+    ) -> Self {
+        Self {
+            clock,
+            transport,
+            timer,
+            settings,
+        }
+    }
+
+    /// Sends the message and waits for its delivery.
+    ///
+    /// Messages refused by the node are reported as [`DeliveryStatus::Rejected`],
+    /// other send errors are returned as is.
+    pub async fn send(&self, message: &SignedMessage) -> Result<DeliveryStatus> {
+        if let Err(e) = self.transport.send_message(&message.message).await {
+            return match rejection_reason(&e) {
+                Some(reason) => Ok(DeliveryStatus::Rejected(reason)),
+                None => Err(e),
+            };
+        }
+        self.track(message).await
+    }
+
+    /// Waits for the delivery of the already sent message.
+    ///
+    /// Transport errors which are not retryable are returned immediately,
+    /// others are logged and the search continues.
+    pub async fn track(&self, message: &SignedMessage) -> Result<DeliveryStatus> {
+        let message_hash = message.message.hash()?;
+        let deadline = message.expire_at as u64 * 1000
+            + self.settings.expiration_grace_period.as_millis() as u64;
+
+        let mut retry = 0;
+        loop {
+            match self.transport.get_dst_transaction(&message_hash).await {
+                Ok(Some(transaction)) => return Ok(DeliveryStatus::Delivered(transaction)),
+                Ok(None) => {}
+                Err(e) if is_retryable_error(&e) => {
+                    log::debug!("Failed to find transaction for message {message_hash:x}: {e:?}");
+                }
+                Err(e) => return Err(e),
+            }
+
+            let now = self.clock.now_ms_u64();
+            if now > deadline {
+                return Ok(DeliveryStatus::Expired);
+            }
+
+            let backoff = self
+                .backoff(retry)
+                .min(Duration::from_millis(deadline - now + 1));
+            self.timer.sleep(backoff).await;
+            retry += 1;
+        }
+    }
+
+    fn backoff(&self, retry: u32) -> Duration {
+        let settings = &self.settings;
+        let backoff =
+            settings.initial_backoff.as_secs_f64() * settings.multiplier.powi(retry as i32);
+        if backoff.is_finite() {
+            Duration::from_secs_f64(backoff.max(0.0)).min(settings.max_backoff)
+        } else {
+            settings.max_backoff
+        }
+    }
+}
+
+fn rejection_reason(error: &anyhow::Error) -> Option<String> {
+    error
+        .chain()
+        .find_map(|cause| match cause.downcast_ref::<TransportError>() {
+            Some(TransportError::MessageRejected(reason)) => Some(reason.clone()),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+#[cfg(feature = "local_transport")]
+mod tests {
+    use super::*;
+    use crate::transport::local::tests::{make_transfer, test_address};
+    use crate::transport::local::LocalTransport;
+
+    /// Advances the clock instead of waiting
+    struct TestTimer(Arc<ClockWithOffset>);
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl Timer for TestTimer {
+        async fn sleep(&self, duration: Duration) {
+            let offset = self.0.offset_ms() + duration.as_millis() as i64;
+            self.0.update_offset(offset);
+        }
+    }
+
+    fn make_message(clock: &dyn Clock, value: u64) -> SignedMessage {
+        SignedMessage {
+            message: make_transfer(value),
+            expire_at: clock.now_sec_u64() as u32 + 60,
+        }
+    }
+
+    fn make_external_message(clock: &dyn Clock) -> SignedMessage {
+        SignedMessage {
+            message: ton_block::Message::with_ext_in_header(
+                ton_block::ExternalInboundMessageHeader {
+                    dst: test_address(0x33),
+                    ..Default::default()
+                },
+            ),
+            expire_at: clock.now_sec_u64() as u32 + 60,
+        }
+    }
+
+    #[tokio::test]
+    async fn tracks_message_delivery() -> Result<()> {
+        let clock = Arc::new(ClockWithOffset::new(0));
+        let tracker = DeliveryTracker::new(
+            clock.clone(),
+            Arc::new(LocalTransport::new(clock.clone())),
+            Arc::new(TestTimer(clock.clone())),
+        );
+
+        let message = make_message(clock.as_ref(), 1_000_000_000);
+        match tracker.send(&message).await? {
+            DeliveryStatus::Delivered(transaction) => {
+                let in_msg = transaction.data.in_msg.expect("no inbound message");
+                assert_eq!(in_msg.cell().repr_hash(), message.message.hash()?);
+            }
+            status => anyhow::bail!("unexpected status: {status:?}"),
+        }
+
+        // The message was never sent
+        let message = make_message(clock.as_ref(), 2_000_000_000);
+        let started_at = clock.now_sec_u64();
+        assert!(matches!(
+            tracker.track(&message).await?,
+            DeliveryStatus::Expired
+        ));
+        assert!(clock.now_sec_u64() > message.expire_at as u64);
+        assert!(clock.now_sec_u64() < started_at + 60 + 20);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reports_rejected_messages() -> Result<()> {
+        let clock = Arc::new(ClockWithOffset::new(0));
+        let tracker = DeliveryTracker::new(
+            clock.clone(),
+            Arc::new(LocalTransport::new(clock.clone())),
+            Arc::new(TestTimer(clock.clone())),
+        );
+
+        // External message to the account which doesn't exist
+        let message = make_external_message(clock.as_ref());
+        let started_at = clock.now_ms_u64();
+        assert!(matches!(
+            tracker.send(&message).await?,
+            DeliveryStatus::Rejected(_)
+        ));
+        // Rejected messages are not tracked
+        assert_eq!(clock.now_ms_u64(), started_at);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "jrpc_transport")]
+    #[tokio::test]
+    async fn reports_messages_rejected_over_jrpc() -> Result<()> {
+        use crate::external::{JrpcConnection, JrpcRequest};
+        use crate::transport::jrpc::server::JrpcServer;
+        use crate::transport::jrpc::JrpcTransport;
+
+        struct BrokenConnection;
+
+        #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+        #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+        impl JrpcConnection for BrokenConnection {
+            async fn post(&self, _: JrpcRequest) -> Result<String> {
+                anyhow::bail!("connection refused")
+            }
+        }
+
+        let clock = Arc::new(ClockWithOffset::new(0));
+        let backend = Arc::new(LocalTransport::new(clock.clone()));
+        let tracker = DeliveryTracker::new(
+            clock.clone(),
+            Arc::new(JrpcTransport::new(Arc::new(JrpcServer::new(backend)))),
+            Arc::new(TestTimer(clock.clone())),
+        );
+
+        let message = make_external_message(clock.as_ref());
+        match tracker.send(&message).await? {
+            DeliveryStatus::Rejected(reason) => assert!(!reason.is_empty()),
+            status => anyhow::bail!("unexpected status: {status:?}"),
+        }
+
+        // Network failures are not rejections
+        let tracker = DeliveryTracker::new(
+            clock.clone(),
+            Arc::new(JrpcTransport::new(Arc::new(BrokenConnection))),
+            Arc::new(TestTimer(clock.clone())),
+        );
+        assert!(tracker.send(&message).await.is_err());
+
+        Ok(())
+    }
+}
@@ -4,12 +4,17 @@ use std::sync::Arc;
 use anyhow::Result;
 use ton_block::{GetRepresentationHash, MsgAddressInt};
 
-use nekoton_utils::Clock;
+use nekoton_utils::{Clock, FeeBreakdown};
 
-use super::models::{ContractState, PendingTransaction, Transaction, TransactionsBatchInfo};
-use super::{ContractSubscription, PollingMethod, TransactionExecutionOptions};
+use super::models::{
+    ContractState, PendingTransaction, RebroadcastPolicy, Transaction, TransactionsBatchInfo,
+    TransactionsGap, TransactionsGapStatus,
+};
+use super::{
+    ContractSubscription, ContractSubscriptionState, PollingMethod, TransactionExecutionOptions,
+};
 use crate::core::utils;
-use crate::transport::models::{RawContractState, RawTransaction};
+use crate::transport::models::{AccountUpdate, RawContractState, RawTransaction};
 use crate::transport::Transport;
 
 pub struct GenericContract {
@@ -59,6 +64,33 @@ impl GenericContract {
         })
     }
 
+    /// Resumes the subscription from the saved state
+    pub async fn subscribe_from_snapshot(
+        clock: Arc<dyn Clock>,
+        transport: Arc<dyn Transport>,
+        snapshot: ContractSubscriptionState,
+        handler: Arc<dyn GenericContractSubscriptionHandler>,
+    ) -> Result<Self> {
+        let address = snapshot.address.clone();
+        let contract_subscription = ContractSubscription::subscribe_from_snapshot(
+            clock,
+            transport,
+            address,
+            snapshot,
+            &mut make_contract_state_handler(handler.as_ref()),
+        )
+        .await?;
+
+        Ok(Self {
+            contract_subscription,
+            handler,
+        })
+    }
+
+    pub fn contract_subscription(&self) -> &ContractSubscription {
+        &self.contract_subscription
+    }
+
     pub fn address(&self) -> &MsgAddressInt {
         self.contract_subscription.address()
     }
@@ -75,6 +107,11 @@ impl GenericContract {
         self.contract_subscription.polling_method()
     }
 
+    /// Enables resending of sent messages until they are delivered or expired
+    pub fn set_rebroadcast_policy(&mut self, policy: Option<RebroadcastPolicy>) {
+        self.contract_subscription.set_rebroadcast_policy(policy);
+    }
+
     pub async fn send(
         &mut self,
         message: &ton_block::Message,
@@ -94,17 +131,26 @@ impl GenericContract {
             )
             .await?;
 
-        Ok(())
+        self.contract_subscription
+            .backfill_gaps(
+                &mut make_transactions_handler(handler),
+                &mut make_message_sent_handler(handler),
+                &mut |gap, status| handler.on_transactions_gap(gap, status),
+            )
+            .await
     }
 
     pub async fn handle_block(&mut self, block: &ton_block::Block) -> Result<()> {
         let handler = self.handler.as_ref();
-        let new_account_state = self.contract_subscription.handle_block(
-            block,
-            &mut make_transactions_handler(handler),
-            &mut make_message_sent_handler(handler),
-            &mut make_message_expired_handler(handler),
-        )?;
+        let new_account_state = self
+            .contract_subscription
+            .handle_block(
+                block,
+                &mut make_transactions_handler(handler),
+                &mut make_message_sent_handler(handler),
+                &mut make_message_expired_handler(handler),
+            )
+            .await?;
 
         if let Some(account_state) = new_account_state {
             handler.on_state_changed(account_state);
@@ -113,6 +159,17 @@ impl GenericContract {
         Ok(())
     }
 
+    pub fn handle_account_update(&mut self, update: AccountUpdate) -> Result<()> {
+        let handler = self.handler.as_ref();
+        self.contract_subscription.handle_account_update(
+            update,
+            &mut make_contract_state_handler(handler),
+            &mut make_transactions_handler(handler),
+            &mut make_message_sent_handler(handler),
+            &mut make_message_expired_handler(handler),
+        )
+    }
+
     pub async fn preload_transactions(&mut self, from_lt: u64) -> Result<()> {
         let handler = self.handler.as_ref();
         self.contract_subscription
@@ -124,6 +181,15 @@ impl GenericContract {
         self.contract_subscription.estimate_fees(message).await
     }
 
+    pub async fn estimate_fee_breakdown(
+        &mut self,
+        message: &ton_block::Message,
+    ) -> Result<FeeBreakdown> {
+        self.contract_subscription
+            .estimate_fee_breakdown(message)
+            .await
+    }
+
     pub async fn execute_transaction_locally(
         &mut self,
         message: &ton_block::Message,
@@ -192,4 +258,10 @@ pub trait GenericContractSubscriptionHandler: Send + Sync {
         transactions: Vec<Transaction>,
         batch_info: TransactionsBatchInfo,
     );
+
+    /// Called when missing transactions are detected or loaded
+    fn on_transactions_gap(&self, gap: TransactionsGap, status: TransactionsGapStatus) {
+        let _ = gap;
+        let _ = status;
+    }
 }
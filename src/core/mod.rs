@@ -2,12 +2,15 @@ use anyhow::Result;
 use nekoton_utils::*;
 use serde::{Deserialize, Serialize};
 
-pub use self::contract_subscription::{ContractSubscription, TransactionExecutionOptions};
+pub use self::contract_subscription::{
+    ContractSubscription, ContractSubscriptionState, TransactionExecutionOptions,
+};
 use self::models::PollingMethod;
 use crate::transport::Transport;
 
 pub mod accounts_storage;
 pub mod contract_subscription;
+pub mod delivery_tracker;
 pub mod dens;
 pub mod generic_contract;
 pub mod keystore;
@@ -15,6 +18,8 @@ pub mod models;
 pub mod nft_wallet;
 pub mod owners_cache;
 pub mod parsing;
+pub mod subscription_events;
+pub mod subscription_manager;
 pub mod token_wallet;
 pub mod ton_wallet;
 pub mod transactions_tree;
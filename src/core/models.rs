@@ -363,7 +363,7 @@ define_string_enum!(
     pub enum ReliableBehavior {
         /// Used for transports which doesn't support getting blocks directly (ADNL)
         IntensivePolling,
-        /// Used for transports which support getting blocks directly (GQL)
+        /// Used for transports which support getting blocks directly (GQL, JRPC, protobuf)
         BlockWalking,
     }
 );
@@ -585,6 +585,26 @@ impl PartialEq<Transaction> for PendingTransaction {
     }
 }
 
+/// Resending of external messages until they are included into a transaction
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RebroadcastPolicy {
+    /// Delay between broadcasts in seconds. Default: `10`
+    pub interval_sec: u32,
+    /// Max number of additional broadcasts, unlimited until
+    /// the message expiration if `None`. Default: `None`
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for RebroadcastPolicy {
+    fn default() -> Self {
+        Self {
+            interval_sec: 10,
+            max_attempts: None,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum MessageType {
@@ -619,6 +639,29 @@ pub enum TransactionsBatchType {
     New,
 }
 
+/// Range of transactions which were not received, (after_lt; until_lt]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionsGap {
+    /// Lt of the received transaction before the gap
+    #[serde(with = "serde_string")]
+    pub after_lt: u64,
+    /// Lt of the latest missing transaction
+    #[serde(with = "serde_string")]
+    pub until_lt: u64,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionsGapStatus {
+    /// Gap was found, missing transactions will be loaded during refresh
+    Detected,
+    /// All missing transactions were loaded
+    Repaired,
+    /// Missing transactions could not be loaded, the gap is no longer tracked
+    Unrecoverable,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Transaction {
@@ -974,6 +1017,8 @@ pub(super) enum AccountSubscriptionError {
     InvalidMessageDestination,
     #[error("Invalid message type")]
     InvalidMessageType,
+    #[error("Snapshot belongs to another account")]
+    SnapshotAddressMismatch,
 }
 
 #[derive(Default, Debug, Copy, Clone, Eq, PartialEq)]
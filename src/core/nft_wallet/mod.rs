@@ -11,12 +11,12 @@ use ton_block::{MsgAddressInt, Serializable};
 use ton_types::{BuilderData, Cell, UInt256};
 
 use crate::core::models::{
-    NftTransaction, NftVersion, PendingTransaction, Transaction, TransactionWithData,
-    TransactionsBatchInfo,
+    NftTransaction, NftVersion, PendingTransaction, RebroadcastPolicy, Transaction,
+    TransactionWithData, TransactionsBatchInfo, TransactionsGap, TransactionsGapStatus,
 };
 use crate::core::parsing::parse_nft_transaction;
-use crate::core::{ContractSubscription, InternalMessage};
-use crate::transport::models::{ExistingContract, RawContractState, RawTransaction};
+use crate::core::{ContractSubscription, ContractSubscriptionState, InternalMessage};
+use crate::transport::models::{AccountUpdate, ExistingContract, RawContractState, RawTransaction};
 use crate::transport::Transport;
 
 const NFT_STAMP: &[u8; 3] = b"nft";
@@ -119,7 +119,7 @@ impl Nft {
         let index_state = IndexContractState(&state);
 
         let info = index_state.get_info(clock.as_ref()).await?;
-        Nft::subscribe(&info.nft, transport, clock, handler).await
+        Nft::subscribe(&info.nft, transport, clock, None, handler).await
     }
 
     pub async fn subscribe_by_nft_address(
@@ -128,13 +128,25 @@ impl Nft {
         address: &MsgAddressInt,
         handler: Arc<dyn NftSubscriptionHandler>,
     ) -> Result<Nft> {
-        Nft::subscribe(address, transport, clock, handler).await
+        Nft::subscribe(address, transport, clock, None, handler).await
+    }
+
+    /// Resumes the subscription from the saved state
+    pub async fn subscribe_from_snapshot(
+        clock: Arc<dyn Clock>,
+        transport: Arc<dyn Transport>,
+        snapshot: ContractSubscriptionState,
+        handler: Arc<dyn NftSubscriptionHandler>,
+    ) -> Result<Nft> {
+        let address = snapshot.address.clone();
+        Nft::subscribe(&address, transport, clock, Some(snapshot), handler).await
     }
 
     async fn subscribe(
         nft_address: &MsgAddressInt,
         transport: Arc<dyn Transport>,
         clock: Arc<dyn Clock>,
+        snapshot: Option<ContractSubscriptionState>,
         handler: Arc<dyn NftSubscriptionHandler>,
     ) -> Result<Nft> {
         let state = match transport.get_contract_state(nft_address).await? {
@@ -156,19 +168,34 @@ impl Nft {
             NftVersion::Tip4_1 => (nft_state.get_info(clock.as_ref())?, None),
         };
 
-        let contract_subscription = ContractSubscription::subscribe(
-            clock.clone(),
-            transport,
-            nft_address.clone(),
-            &mut make_contract_state_handler(
-                clock.as_ref(),
-                &mut info.owner,
-                &mut info.manager,
-                None,
-            ),
-            Some(&mut make_transactions_handler(handler.as_ref())),
-        )
-        .await?;
+        let on_contract_state = &mut make_contract_state_handler(
+            clock.as_ref(),
+            &mut info.owner,
+            &mut info.manager,
+            None,
+        );
+        let contract_subscription = match snapshot {
+            Some(snapshot) => {
+                ContractSubscription::subscribe_from_snapshot(
+                    clock.clone(),
+                    transport,
+                    nft_address.clone(),
+                    snapshot,
+                    on_contract_state,
+                )
+                .await?
+            }
+            None => {
+                ContractSubscription::subscribe(
+                    clock.clone(),
+                    transport,
+                    nft_address.clone(),
+                    on_contract_state,
+                    Some(&mut make_transactions_handler(handler.as_ref())),
+                )
+                .await?
+            }
+        };
 
         handler.on_manager_changed(info.manager.clone());
         handler.on_owner_changed(info.owner.clone());
@@ -292,6 +319,11 @@ impl Nft {
         })
     }
 
+    /// Enables resending of sent messages until they are delivered or expired
+    pub fn set_rebroadcast_policy(&mut self, policy: Option<RebroadcastPolicy>) {
+        self.contract_subscription.set_rebroadcast_policy(policy);
+    }
+
     pub async fn send(
         &mut self,
         message: &ton_block::Message,
@@ -315,9 +347,49 @@ impl Nft {
                 &mut make_message_sent_handler(handler),
                 &mut make_message_expired_handler(handler),
             )
+            .await?;
+
+        self.contract_subscription
+            .backfill_gaps(
+                &mut make_transactions_handler(handler),
+                &mut make_message_sent_handler(handler),
+                &mut |gap, status| handler.on_transactions_gap(gap, status),
+            )
             .await
     }
 
+    /// NOTE: owner and manager are updated only by [`Nft::refresh`]
+    /// and [`Nft::handle_account_update`]
+    pub async fn handle_block(&mut self, block: &ton_block::Block) -> Result<()> {
+        let handler = self.handler.as_ref();
+        self.contract_subscription
+            .handle_block(
+                block,
+                &mut make_transactions_handler(handler),
+                &mut make_message_sent_handler(handler),
+                &mut make_message_expired_handler(handler),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub fn handle_account_update(&mut self, update: AccountUpdate) -> Result<()> {
+        let handler = self.handler.as_ref();
+        self.contract_subscription.handle_account_update(
+            update,
+            &mut make_contract_state_handler(
+                self.clock.as_ref(),
+                &mut self.owner,
+                &mut self.manager,
+                Some(handler),
+            ),
+            &mut make_transactions_handler(handler),
+            &mut make_message_sent_handler(handler),
+            &mut make_message_expired_handler(handler),
+        )
+    }
+
     pub async fn preload_transactions(&mut self, from_lt: u64) -> Result<()> {
         let handler = self.handler.as_ref();
         self.contract_subscription
@@ -357,6 +429,12 @@ pub trait NftSubscriptionHandler: Send + Sync {
         let _ = transactions;
         let _ = batch_info;
     }
+
+    /// Called when missing transactions are detected or loaded
+    fn on_transactions_gap(&self, gap: TransactionsGap, status: TransactionsGapStatus) {
+        let _ = gap;
+        let _ = status;
+    }
 }
 
 fn make_contract_state_handler<'a>(
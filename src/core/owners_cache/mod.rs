@@ -4,7 +4,7 @@ use std::str::FromStr;
 use std::sync::Arc;
 
 use anyhow::Result;
-use futures_util::stream::{FuturesUnordered, StreamExt};
+use futures_util::stream::{FuturesOrdered, FuturesUnordered, StreamExt};
 use serde::Deserialize;
 use tokio::sync::{RwLock, Semaphore};
 use ton_block::MsgAddressInt;
@@ -28,6 +28,7 @@ pub struct OwnersCache {
     owners: RwLock<HashMap<MsgAddressInt, MsgAddressInt>>,
     token_contract_states: RwLock<HashMap<MsgAddressInt, (ExistingContract, TokenWalletVersion)>>,
     resolver_semaphore: Semaphore,
+    concurrent_resolvers: usize,
 }
 
 impl OwnersCache {
@@ -66,6 +67,7 @@ impl OwnersCache {
             owners: RwLock::new(data),
             token_contract_states: Default::default(),
             resolver_semaphore: Semaphore::new(concurrent_resolvers),
+            concurrent_resolvers,
         })
     }
 
@@ -92,6 +94,7 @@ impl OwnersCache {
             owners: Default::default(),
             token_contract_states: Default::default(),
             resolver_semaphore: Semaphore::new(concurrent_resolvers),
+            concurrent_resolvers,
         })
     }
 
@@ -146,43 +149,85 @@ impl OwnersCache {
         &self,
         token_wallets: &[MsgAddressInt],
     ) -> HashMap<MsgAddressInt, MsgAddressInt> {
+        // Max number of token wallets requested at once
+        const BATCH_SIZE: usize = 20;
+
         let semaphore = &self.resolver_semaphore;
+        let max_permits = self.concurrent_resolvers;
         let clock = self.clock.as_ref();
         let transport = self.transport.as_ref();
-        let owners = &self.owners;
-
-        let token_wallets = token_wallets.iter().collect::<HashSet<_>>();
 
-        token_wallets
-            .into_iter()
-            .map(|token_wallet| async move {
-                if let Some(owner) = owners.read().await.get(token_wallet) {
-                    return Some((token_wallet.clone(), owner.clone()));
+        let mut result = HashMap::new();
+        let mut unknown = Vec::new();
+        {
+            let owners = self.owners.read().await;
+            for token_wallet in token_wallets.iter().collect::<HashSet<_>>() {
+                match owners.get(token_wallet) {
+                    Some(owner) => {
+                        result.insert(token_wallet.clone(), owner.clone());
+                    }
+                    None => unknown.push(token_wallet.clone()),
                 }
+            }
+        }
 
-                let contract_state = {
-                    let _permit = semaphore.acquire().await.ok()?;
-                    match transport.get_contract_state(token_wallet).await.ok()? {
-                        RawContractState::Exists(state) => state,
-                        RawContractState::NotExists { .. } => return None,
-                    }
+        let resolved = unknown
+            .chunks(BATCH_SIZE)
+            .map(|token_wallets| async move {
+                // NOTE: transports without batch requests fetch each state separately,
+                // so the batch takes a permit for each wallet
+                let permits = std::cmp::min(token_wallets.len(), max_permits) as u32;
+                let states = {
+                    let _permits = semaphore.acquire_many(permits).await.ok()?;
+                    transport.get_contract_states(token_wallets).await
                 };
 
-                let state = TokenWalletContractState(&contract_state);
-                let version = state.get_version(clock).ok()?;
-                let details = state.get_details(clock, version).ok()?;
-
-                owners
-                    .write()
-                    .await
-                    .insert(token_wallet.clone(), details.owner_address.clone());
+                let states = match states {
+                    Ok(states) => states.into_iter().map(Some).collect::<Vec<_>>(),
+                    // Resolve wallets one by one to not lose the whole chunk
+                    Err(e) => {
+                        log::warn!("Failed to get token wallet states: {e:?}");
+                        token_wallets
+                            .iter()
+                            .map(|token_wallet| async move {
+                                let _permit = semaphore.acquire().await.ok()?;
+                                transport.get_contract_state(token_wallet).await.ok()
+                            })
+                            .collect::<FuturesOrdered<_>>()
+                            .collect::<Vec<_>>()
+                            .await
+                    }
+                };
 
-                Some((token_wallet.clone(), details.owner_address))
+                let owners = token_wallets
+                    .iter()
+                    .zip(states)
+                    .filter_map(|(token_wallet, state)| {
+                        let contract_state = match state? {
+                            RawContractState::Exists(state) => state,
+                            RawContractState::NotExists { .. } => return None,
+                        };
+
+                        let state = TokenWalletContractState(&contract_state);
+                        let version = state.get_version(clock).ok()?;
+                        let details = state.get_details(clock, version).ok()?;
+                        Some((token_wallet.clone(), details.owner_address))
+                    })
+                    .collect::<Vec<_>>();
+                Some(owners)
             })
             .collect::<FuturesUnordered<_>>()
             .filter_map(|value| async move { value })
-            .collect()
-            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut owners = self.owners.write().await;
+        for (token_wallet, owner) in resolved.into_iter().flatten() {
+            owners.insert(token_wallet.clone(), owner.clone());
+            result.insert(token_wallet, owner);
+        }
+
+        result
     }
 
     pub async fn get_owner(&self, token_wallet: &MsgAddressInt) -> Option<MsgAddressInt> {
@@ -0,0 +1,454 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_util::task::AtomicWaker;
+use futures_util::Stream;
+use num_bigint::BigUint;
+use parking_lot::Mutex;
+use ton_block::MsgAddressInt;
+use ton_types::UInt256;
+
+use super::generic_contract::GenericContractSubscriptionHandler;
+use super::models::{
+    ContractState, MultisigPendingTransaction, MultisigPendingUpdate, NftTransaction,
+    PendingTransaction, TokenWalletTransaction, Transaction, TransactionAdditionalInfo,
+    TransactionWithData, TransactionsBatchInfo, TransactionsGap, TransactionsGapStatus,
+};
+use super::nft_wallet::NftSubscriptionHandler;
+use super::token_wallet::TokenWalletSubscriptionHandler;
+use super::ton_wallet::{TonWalletDetails, TonWalletSubscriptionHandler};
+
+/// Creates a handler which can be passed to the subscription
+/// and a stream of its events.
+///
+/// ```ignore
+/// let (handler, mut events) = subscription_events::<TonWalletEvent>(64);
+/// let mut wallet = TonWallet::subscribe(clock, transport, 0, public_key, wallet_type, handler.clone()).await?;
+///
+/// tokio::spawn(async move {
+///     while let Some(event) = events.next().await {
+///         // ...
+///     }
+/// });
+///
+/// loop {
+///     handler.ready().await;
+///     wallet.refresh().await?;
+/// }
+/// ```
+pub fn subscription_events<E>(capacity: usize) -> (Arc<EventsHandler<E>>, EventsStream<E>) {
+    let shared = Arc::new(Shared {
+        queue: Default::default(),
+        capacity: capacity.max(1),
+        handler_closed: AtomicBool::new(false),
+        stream_closed: AtomicBool::new(false),
+        stream_waker: Default::default(),
+        space_available: Default::default(),
+    });
+
+    (
+        Arc::new(EventsHandler {
+            shared: shared.clone(),
+        }),
+        EventsStream { shared },
+    )
+}
+
+#[derive(Debug, Clone)]
+pub enum TonWalletEvent {
+    MessageSent {
+        pending_transaction: PendingTransaction,
+        transaction: Option<Transaction>,
+    },
+    MessageExpired(PendingTransaction),
+    StateChanged(ContractState),
+    TransactionsFound {
+        transactions: Vec<TransactionWithData<TransactionAdditionalInfo>>,
+        batch_info: TransactionsBatchInfo,
+    },
+    DetailsChanged(TonWalletDetails),
+    CustodiansChanged(Vec<UInt256>),
+    UnconfirmedTransactionsChanged(Vec<MultisigPendingTransaction>),
+    UnconfirmedUpdatesChanged(Vec<MultisigPendingUpdate>),
+    TransactionsGap {
+        gap: TransactionsGap,
+        status: TransactionsGapStatus,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum TokenWalletEvent {
+    BalanceChanged(BigUint),
+    TransactionsFound {
+        transactions: Vec<TransactionWithData<TokenWalletTransaction>>,
+        batch_info: TransactionsBatchInfo,
+    },
+    TransactionsGap {
+        gap: TransactionsGap,
+        status: TransactionsGapStatus,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum NftEvent {
+    MessageSent {
+        pending_transaction: PendingTransaction,
+        transaction: Option<Transaction>,
+    },
+    MessageExpired(PendingTransaction),
+    ManagerChanged(MsgAddressInt),
+    OwnerChanged(MsgAddressInt),
+    TransactionsFound {
+        transactions: Vec<TransactionWithData<NftTransaction>>,
+        batch_info: TransactionsBatchInfo,
+    },
+    TransactionsGap {
+        gap: TransactionsGap,
+        status: TransactionsGapStatus,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum GenericContractEvent {
+    MessageSent {
+        pending_transaction: PendingTransaction,
+        transaction: Option<Transaction>,
+    },
+    MessageExpired(PendingTransaction),
+    StateChanged(ContractState),
+    TransactionsFound {
+        transactions: Vec<Transaction>,
+        batch_info: TransactionsBatchInfo,
+    },
+    TransactionsGap {
+        gap: TransactionsGap,
+        status: TransactionsGapStatus,
+    },
+}
+
+/// Subscription handler which forwards all events into the [`EventsStream`].
+///
+/// Callbacks can't wait, so events are never dropped and the queue can
+/// exceed its capacity during a single refresh. Backpressure is applied
+/// by waiting for [`EventsHandler::ready`] before each refresh.
+pub struct EventsHandler<E> {
+    shared: Arc<Shared<E>>,
+}
+
+impl<E> EventsHandler<E> {
+    /// Waits until the queue has free space or the stream is dropped
+    pub async fn ready(&self) {
+        loop {
+            let space_available = self.shared.space_available.notified();
+            if self.shared.stream_closed.load(Ordering::Acquire)
+                || self.shared.queue.lock().len() < self.shared.capacity
+            {
+                return;
+            }
+            space_available.await;
+        }
+    }
+
+    /// Returns `true` if the stream was dropped
+    pub fn is_closed(&self) -> bool {
+        self.shared.stream_closed.load(Ordering::Acquire)
+    }
+
+    fn push(&self, event: E) {
+        if self.is_closed() {
+            return;
+        }
+        self.shared.queue.lock().push_back(event);
+        self.shared.stream_waker.wake();
+    }
+}
+
+impl<E> Drop for EventsHandler<E> {
+    fn drop(&mut self) {
+        self.shared.handler_closed.store(true, Ordering::Release);
+        self.shared.stream_waker.wake();
+    }
+}
+
+/// Stream of subscription events. Ends when the subscription is dropped
+pub struct EventsStream<E> {
+    shared: Arc<Shared<E>>,
+}
+
+impl<E> EventsStream<E> {
+    /// Number of buffered events
+    pub fn len(&self) -> usize {
+        self.shared.queue.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shared.queue.lock().is_empty()
+    }
+
+    fn pop(&self) -> Option<E> {
+        let event = self.shared.queue.lock().pop_front()?;
+        self.shared.space_available.notify_waiters();
+        Some(event)
+    }
+}
+
+impl<E> Stream for EventsStream<E> {
+    type Item = E;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(event) = self.pop() {
+            return Poll::Ready(Some(event));
+        }
+
+        self.shared.stream_waker.register(cx.waker());
+
+        // NOTE: check again after the waker registration to avoid lost wakeups
+        if let Some(event) = self.pop() {
+            return Poll::Ready(Some(event));
+        }
+
+        if self.shared.handler_closed.load(Ordering::Acquire) {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<E> Drop for EventsStream<E> {
+    fn drop(&mut self) {
+        self.shared.stream_closed.store(true, Ordering::Release);
+        self.shared.queue.lock().clear();
+        self.shared.space_available.notify_waiters();
+    }
+}
+
+struct Shared<E> {
+    queue: Mutex<VecDeque<E>>,
+    capacity: usize,
+    handler_closed: AtomicBool,
+    stream_closed: AtomicBool,
+    stream_waker: AtomicWaker,
+    space_available: tokio::sync::Notify,
+}
+
+impl TonWalletSubscriptionHandler for EventsHandler<TonWalletEvent> {
+    fn on_message_sent(
+        &self,
+        pending_transaction: PendingTransaction,
+        transaction: Option<Transaction>,
+    ) {
+        self.push(TonWalletEvent::MessageSent {
+            pending_transaction,
+            transaction,
+        });
+    }
+
+    fn on_message_expired(&self, pending_transaction: PendingTransaction) {
+        self.push(TonWalletEvent::MessageExpired(pending_transaction));
+    }
+
+    fn on_state_changed(&self, new_state: ContractState) {
+        self.push(TonWalletEvent::StateChanged(new_state));
+    }
+
+    fn on_transactions_found(
+        &self,
+        transactions: Vec<TransactionWithData<TransactionAdditionalInfo>>,
+        batch_info: TransactionsBatchInfo,
+    ) {
+        self.push(TonWalletEvent::TransactionsFound {
+            transactions,
+            batch_info,
+        });
+    }
+
+    fn on_details_changed(&self, details: TonWalletDetails) {
+        self.push(TonWalletEvent::DetailsChanged(details));
+    }
+
+    fn on_custodians_changed(&self, custodians: &[UInt256]) {
+        self.push(TonWalletEvent::CustodiansChanged(custodians.to_vec()));
+    }
+
+    fn on_unconfirmed_transactions_changed(
+        &self,
+        unconfirmed_transactions: &[MultisigPendingTransaction],
+    ) {
+        self.push(TonWalletEvent::UnconfirmedTransactionsChanged(
+            unconfirmed_transactions.to_vec(),
+        ));
+    }
+
+    fn on_unconfirmed_updates_changed(&self, unconfirmed_updates: &[MultisigPendingUpdate]) {
+        self.push(TonWalletEvent::UnconfirmedUpdatesChanged(
+            unconfirmed_updates.to_vec(),
+        ));
+    }
+
+    fn on_transactions_gap(&self, gap: TransactionsGap, status: TransactionsGapStatus) {
+        self.push(TonWalletEvent::TransactionsGap { gap, status });
+    }
+}
+
+impl TokenWalletSubscriptionHandler for EventsHandler<TokenWalletEvent> {
+    fn on_balance_changed(&self, balance: BigUint) {
+        self.push(TokenWalletEvent::BalanceChanged(balance));
+    }
+
+    fn on_transactions_found(
+        &self,
+        transactions: Vec<TransactionWithData<TokenWalletTransaction>>,
+        batch_info: TransactionsBatchInfo,
+    ) {
+        self.push(TokenWalletEvent::TransactionsFound {
+            transactions,
+            batch_info,
+        });
+    }
+
+    fn on_transactions_gap(&self, gap: TransactionsGap, status: TransactionsGapStatus) {
+        self.push(TokenWalletEvent::TransactionsGap { gap, status });
+    }
+}
+
+impl NftSubscriptionHandler for EventsHandler<NftEvent> {
+    fn on_message_sent(
+        &self,
+        pending_transaction: PendingTransaction,
+        transaction: Option<Transaction>,
+    ) {
+        self.push(NftEvent::MessageSent {
+            pending_transaction,
+            transaction,
+        });
+    }
+
+    fn on_message_expired(&self, pending_transaction: PendingTransaction) {
+        self.push(NftEvent::MessageExpired(pending_transaction));
+    }
+
+    fn on_manager_changed(&self, manager: MsgAddressInt) {
+        self.push(NftEvent::ManagerChanged(manager));
+    }
+
+    fn on_owner_changed(&self, owner: MsgAddressInt) {
+        self.push(NftEvent::OwnerChanged(owner));
+    }
+
+    fn on_transactions_found(
+        &self,
+        transactions: Vec<TransactionWithData<NftTransaction>>,
+        batch_info: TransactionsBatchInfo,
+    ) {
+        self.push(NftEvent::TransactionsFound {
+            transactions,
+            batch_info,
+        });
+    }
+
+    fn on_transactions_gap(&self, gap: TransactionsGap, status: TransactionsGapStatus) {
+        self.push(NftEvent::TransactionsGap { gap, status });
+    }
+}
+
+impl GenericContractSubscriptionHandler for EventsHandler<GenericContractEvent> {
+    fn on_message_sent(
+        &self,
+        pending_transaction: PendingTransaction,
+        transaction: Option<Transaction>,
+    ) {
+        self.push(GenericContractEvent::MessageSent {
+            pending_transaction,
+            transaction,
+        });
+    }
+
+    fn on_message_expired(&self, pending_transaction: PendingTransaction) {
+        self.push(GenericContractEvent::MessageExpired(pending_transaction));
+    }
+
+    fn on_state_changed(&self, new_state: ContractState) {
+        self.push(GenericContractEvent::StateChanged(new_state));
+    }
+
+    fn on_transactions_found(
+        &self,
+        transactions: Vec<Transaction>,
+        batch_info: TransactionsBatchInfo,
+    ) {
+        self.push(GenericContractEvent::TransactionsFound {
+            transactions,
+            batch_info,
+        });
+    }
+
+    fn on_transactions_gap(&self, gap: TransactionsGap, status: TransactionsGapStatus) {
+        self.push(GenericContractEvent::TransactionsGap { gap, status });
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "local_transport")]
+mod tests {
+    use anyhow::Result;
+    use futures_util::{FutureExt, StreamExt};
+    use nekoton_utils::ClockWithOffset;
+
+    use super::*;
+    use crate::core::generic_contract::GenericContract;
+    use crate::transport::local::tests::{dst_address, make_transfer};
+    use crate::transport::local::LocalTransport;
+    use crate::transport::Transport;
+
+    #[tokio::test]
+    async fn streams_subscription_events() -> Result<()> {
+        let clock = Arc::new(ClockWithOffset::new(0));
+        let transport = Arc::new(LocalTransport::new(clock.clone()));
+
+        let (handler, mut events) = subscription_events::<GenericContractEvent>(1);
+        let mut contract = GenericContract::subscribe(
+            clock.clone(),
+            transport.clone(),
+            dst_address(),
+            handler.clone(),
+            false,
+        )
+        .await?;
+
+        // Initial state is reported
+        assert!(matches!(
+            events.next().await,
+            Some(GenericContractEvent::StateChanged(_))
+        ));
+        assert!(handler.ready().now_or_never().is_some());
+
+        transport
+            .send_message(&make_transfer(1_000_000_000))
+            .await?;
+        clock.update_offset(1000);
+        contract.refresh().await?;
+
+        // The queue is full until the consumer reads events
+        assert!(handler.ready().now_or_never().is_none());
+
+        let mut found = 0;
+        while let Some(event) = events.next().now_or_never().flatten() {
+            if let GenericContractEvent::TransactionsFound { transactions, .. } = event {
+                found += transactions.len();
+            }
+        }
+        assert_eq!(found, 1);
+        assert!(handler.ready().now_or_never().is_some());
+
+        // Stream ends when the subscription is dropped
+        drop(contract);
+        drop(handler);
+        assert!(events.next().await.is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,801 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use futures_util::StreamExt;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use ton_block::MsgAddressInt;
+
+use nekoton_utils::*;
+
+use super::contract_subscription::ContractSubscription;
+use super::generic_contract::GenericContract;
+use super::models::{PollingMethod, ReliableBehavior};
+use super::nft_wallet::Nft;
+use super::token_wallet::TokenWallet;
+use super::ton_wallet::TonWallet;
+use super::utils;
+use crate::external::Timer;
+use crate::transport::models::AccountUpdate;
+use crate::transport::Transport;
+
+/// Subscription which can be scheduled by the [`SubscriptionManager`]
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+pub trait ManagedSubscription: Send + Sync {
+    fn address(&self) -> &MsgAddressInt;
+
+    fn polling_method(&self) -> PollingMethod;
+
+    fn contract_subscription(&self) -> &ContractSubscription;
+
+    async fn refresh(&mut self) -> Result<()>;
+
+    async fn handle_block(&mut self, block: &ton_block::Block) -> Result<()>;
+
+    fn handle_account_update(&mut self, update: AccountUpdate) -> Result<()>;
+}
+
+macro_rules! impl_managed_subscription {
+    ($($ty:ty),*$(,)?) => {$(
+        #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+        #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+        impl ManagedSubscription for $ty {
+            fn address(&self) -> &MsgAddressInt {
+                <$ty>::address(self)
+            }
+
+            fn polling_method(&self) -> PollingMethod {
+                self.contract_subscription().polling_method()
+            }
+
+            fn contract_subscription(&self) -> &ContractSubscription {
+                <$ty>::contract_subscription(self)
+            }
+
+            async fn refresh(&mut self) -> Result<()> {
+                <$ty>::refresh(self).await
+            }
+
+            async fn handle_block(&mut self, block: &ton_block::Block) -> Result<()> {
+                <$ty>::handle_block(self, block).await
+            }
+
+            fn handle_account_update(&mut self, update: AccountUpdate) -> Result<()> {
+                <$ty>::handle_account_update(self, update)
+            }
+        }
+    )*};
+}
+
+impl_managed_subscription!(TonWallet, TokenWallet, Nft, GenericContract);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubscriptionManagerSettings {
+    /// Refresh interval for subscriptions without pending transactions. Default: `60000`
+    #[serde(with = "serde_duration_ms")]
+    pub manual_polling_interval: Duration,
+    /// Refresh interval for subscriptions with pending transactions. Default: `2000`
+    #[serde(with = "serde_duration_ms")]
+    pub reliable_polling_interval: Duration,
+    /// Max number of addresses refreshed at once. Default: `32`
+    pub max_concurrent_refreshes: usize,
+}
+
+impl Default for SubscriptionManagerSettings {
+    fn default() -> Self {
+        Self {
+            manual_polling_interval: Duration::from_secs(60),
+            reliable_polling_interval: Duration::from_secs(2),
+            max_concurrent_refreshes: 32,
+        }
+    }
+}
+
+/// Schedules polling for many subscriptions.
+///
+/// Subscriptions are grouped by address, so the refresh of the same
+/// address is never performed twice at once. All subscriptions must
+/// use the same transport as the manager.
+pub struct SubscriptionManager {
+    clock: Arc<dyn Clock>,
+    transport: Arc<dyn Transport>,
+    timer: Arc<dyn Timer>,
+    settings: SubscriptionManagerSettings,
+    entries: Mutex<HashMap<MsgAddressInt, Arc<SubscriptionEntry>>>,
+    /// Latest handled blocks of the walked shards
+    shards: tokio::sync::Mutex<Vec<ShardCursor>>,
+}
+
+impl SubscriptionManager {
+    pub fn new(
+        clock: Arc<dyn Clock>,
+        transport: Arc<dyn Transport>,
+        timer: Arc<dyn Timer>,
+    ) -> Self {
+        Self::with_settings(clock, transport, timer, Default::default())
+    }
+
+    pub fn with_settings(
+        clock: Arc<dyn Clock>,
+        transport: Arc<dyn Transport>,
+        timer: Arc<dyn Timer>,
+        settings: SubscriptionManagerSettings,
+    ) -> Self {
+        Self {
+            clock,
+            transport,
+            timer,
+            settings,
+            entries: Default::default(),
+            shards: Default::default(),
+        }
+    }
+
+    /// Adds a new subscription. It will be refreshed on the next poll
+    pub fn add<S>(&self, subscription: S)
+    where
+        S: ManagedSubscription + 'static,
+    {
+        self.add_boxed(Box::new(subscription))
+    }
+
+    pub fn add_boxed(&self, subscription: Box<dyn ManagedSubscription>) {
+        let address = subscription.address().clone();
+        let entry = self.entries.lock().entry(address).or_default().clone();
+
+        // NOTE: the subscription is attached on the next refresh of this address
+        entry.pending.lock().push(subscription);
+        entry.next_refresh_at.store(0, Ordering::Release);
+    }
+
+    /// Removes all subscriptions for the specified address
+    pub fn remove(&self, address: &MsgAddressInt) -> bool {
+        self.entries.lock().remove(address).is_some()
+    }
+
+    pub fn contains(&self, address: &MsgAddressInt) -> bool {
+        self.entries.lock().contains_key(address)
+    }
+
+    /// Number of tracked addresses
+    pub fn len(&self) -> usize {
+        self.entries.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().is_empty()
+    }
+
+    /// Refreshes all subscriptions for the specified address.
+    ///
+    /// If this address is already being refreshed, waits for it instead
+    /// of starting a new refresh.
+    pub async fn refresh(&self, address: &MsgAddressInt) -> Result<()> {
+        let entry = match self.entries.lock().get(address) {
+            Some(entry) => entry.clone(),
+            None => return Ok(()),
+        };
+        self.refresh_entry(address, &entry).await
+    }
+
+    /// Refreshes all subscriptions which are due, returns the number of refreshed addresses.
+    ///
+    /// The account state and new transactions are fetched once per address and
+    /// shared by all its subscriptions without pending messages or gaps.
+    ///
+    /// Errors are logged and the failed address is retried on the next poll.
+    pub async fn poll(&self) -> usize {
+        let now = self.clock.now_ms_u64();
+        let due = self
+            .entries
+            .lock()
+            .iter()
+            .filter(|(_, entry)| entry.next_refresh_at.load(Ordering::Acquire) <= now)
+            .map(|(address, entry)| (address.clone(), entry.clone()))
+            .collect::<Vec<_>>();
+
+        let count = due.len();
+        futures_util::stream::iter(due)
+            .for_each_concurrent(self.concurrency(), |(address, entry)| async move {
+                if let Err(e) = self.refresh_entry(&address, &entry).await {
+                    log::warn!("Failed to refresh subscription {address}: {e:?}");
+                }
+            })
+            .await;
+
+        count
+    }
+
+    /// Polls subscriptions forever.
+    ///
+    /// Transports with [`ReliableBehavior::BlockWalking`] also walk blocks
+    /// between polls.
+    pub async fn run(&self) {
+        let interval = self
+            .settings
+            .reliable_polling_interval
+            .min(self.settings.manual_polling_interval);
+
+        loop {
+            self.poll().await;
+
+            if self.walks_blocks() {
+                match self.walk_blocks(interval).await {
+                    Ok(count) if count > 0 => continue,
+                    Ok(_) => {}
+                    Err(e) => log::warn!("Failed to walk blocks: {e:?}"),
+                }
+            }
+            self.timer.sleep(interval).await;
+        }
+    }
+
+    /// Fetches the next block of each shard with subscriptions and passes it to
+    /// all subscriptions in that shard. Returns the number of handled blocks.
+    ///
+    /// Each block is requested once, no matter how many addresses are in the shard.
+    /// Requires a transport with [`ReliableBehavior::BlockWalking`].
+    pub async fn walk_blocks(&self, timeout: Duration) -> Result<usize> {
+        // NOTE: cursors are copied to not hold the lock while waiting for blocks
+        let mut shards = self.shards.lock().await.clone();
+
+        let addresses = self.entries.lock().keys().cloned().collect::<Vec<_>>();
+        shards.retain(|cursor| {
+            addresses
+                .iter()
+                .any(|address| contains_address(&cursor.shard, address))
+        });
+
+        // Start walking new shards from their latest blocks
+        for address in &addresses {
+            if shards
+                .iter()
+                .any(|cursor| contains_address(&cursor.shard, address))
+            {
+                continue;
+            }
+
+            let latest = self.transport.get_latest_block(address).await?;
+            let block = self.transport.get_block(&latest.id).await?;
+            shards.push(ShardCursor {
+                shard: *block.read_info()?.shard(),
+                block_id: latest.id,
+            });
+        }
+
+        let next_blocks = futures_util::future::join_all(shards.iter().map(|cursor| {
+            let addresses = &addresses;
+            async move {
+                // NOTE: there is always an address in the shard after `retain`
+                let address = addresses
+                    .iter()
+                    .find(|address| contains_address(&cursor.shard, address))
+                    .trust_me();
+
+                let id = self
+                    .transport
+                    .wait_for_next_block(&cursor.block_id, address, timeout)
+                    .await?;
+                let block = self.transport.get_block(&id).await?;
+                Ok::<_, anyhow::Error>((id, block))
+            }
+        }))
+        .await;
+
+        let mut count = 0;
+        for (cursor, next_block) in shards.iter_mut().zip(next_blocks) {
+            let (id, block) = match next_block {
+                Ok(next_block) => next_block,
+                Err(e) => {
+                    log::debug!("Failed to fetch next block of {}: {e:?}", cursor.shard);
+                    continue;
+                }
+            };
+
+            self.handle_block(&block).await?;
+
+            // NOTE: shard could be split or merged
+            cursor.shard = *block.read_info()?.shard();
+            cursor.block_id = id;
+            count += 1;
+        }
+
+        // Merged shards are walked once
+        shards.dedup_by(|a, b| a.block_id == b.block_id);
+
+        // Cursors are advanced only when all blocks were handled
+        *self.shards.lock().await = shards;
+
+        Ok(count)
+    }
+
+    /// Passes the fetched block to all subscriptions in its shard
+    pub async fn handle_block(&self, block: &ton_block::Block) -> Result<()> {
+        let shard = *block.read_info()?.shard();
+        let entries = self
+            .entries
+            .lock()
+            .iter()
+            .filter(|(address, _)| contains_address(&shard, address))
+            .map(|(address, entry)| (address.clone(), entry.clone()))
+            .collect::<Vec<_>>();
+
+        futures_util::stream::iter(entries)
+            .for_each_concurrent(self.concurrency(), |(address, entry)| async move {
+                let mut subscriptions = entry.subscriptions.lock().await;
+                entry.attach_pending(&mut subscriptions);
+                for subscription in subscriptions.iter_mut() {
+                    if let Err(e) = subscription.handle_block(block).await {
+                        log::warn!("Failed to handle block for {address}: {e:?}");
+                    }
+                }
+            })
+            .await;
+
+        Ok(())
+    }
+
+    async fn refresh_entry(
+        &self,
+        address: &MsgAddressInt,
+        entry: &SubscriptionEntry,
+    ) -> Result<()> {
+        let mut subscriptions = match entry.subscriptions.try_lock() {
+            Ok(subscriptions) => subscriptions,
+            Err(_) => {
+                // Coalesce with the refresh in progress
+                drop(entry.subscriptions.lock().await);
+                return Ok(());
+            }
+        };
+        entry.attach_pending(&mut subscriptions);
+
+        // NOTE: subscriptions with pending messages or gaps need the full refresh
+        let (shared, mut separate): (Vec<_>, Vec<_>) = subscriptions
+            .iter_mut()
+            .partition(|subscription| is_idle(subscription.contract_subscription()));
+
+        let mut result = Ok(());
+        if !shared.is_empty() {
+            match self.fetch_account_update(address, &shared).await {
+                Ok(None) => {}
+                Ok(Some(update)) => {
+                    for subscription in shared {
+                        if let Err(e) = subscription.handle_account_update(update.clone()) {
+                            result = Err(e);
+                        } else if !is_idle(subscription.contract_subscription()) {
+                            // Shared transactions were not enough to sync it
+                            separate.push(subscription);
+                        }
+                    }
+                }
+                Err(e) => result = Err(e),
+            }
+        }
+
+        for subscription in separate {
+            if let Err(e) = subscription.refresh().await {
+                result = Err(e);
+            }
+        }
+
+        let polling_method = match subscriptions
+            .iter()
+            .any(|subscription| subscription.polling_method() == PollingMethod::Reliable)
+        {
+            true => PollingMethod::Reliable,
+            false => PollingMethod::Manual,
+        };
+
+        let interval = match (&result, polling_method) {
+            (Err(_), _) => self.settings.reliable_polling_interval,
+            // New blocks are passed to these subscriptions by `walk_blocks`
+            (_, PollingMethod::Reliable) if self.walks_blocks() => {
+                self.settings.manual_polling_interval
+            }
+            (_, PollingMethod::Reliable) => self.settings.reliable_polling_interval,
+            (_, PollingMethod::Manual) => self.settings.manual_polling_interval,
+        };
+        entry.next_refresh_at.store(
+            self.clock.now_ms_u64() + interval.as_millis() as u64,
+            Ordering::Release,
+        );
+
+        result
+    }
+
+    /// Fetches the account state and its new transactions once for all subscriptions.
+    ///
+    /// Returns `None` if the account was not changed since the oldest known state
+    async fn fetch_account_update(
+        &self,
+        address: &MsgAddressInt,
+        subscriptions: &[&mut Box<dyn ManagedSubscription>],
+    ) -> Result<Option<AccountUpdate>> {
+        let last_lt = subscriptions
+            .iter()
+            .map(|subscription| {
+                subscription
+                    .contract_subscription()
+                    .contract_state()
+                    .last_lt
+            })
+            .min()
+            .unwrap_or_default();
+
+        let state = match self
+            .transport
+            .poll_contract_state(address, last_lt)
+            .await?
+            .to_changed()
+        {
+            Ok(state) => state,
+            Err(_) => return Ok(None),
+        };
+        let new_state = state.brief();
+
+        let mut transactions = Vec::new();
+        if let Some(id) = new_state.last_transaction_id {
+            if new_state.last_lt > last_lt {
+                // NOTE: `None` is less than any known lt
+                let latest_known_lt = subscriptions
+                    .iter()
+                    .map(|subscription| subscription.contract_subscription().latest_known_lt())
+                    .min()
+                    .flatten();
+                let count = self.transport.info().max_transactions_per_fetch;
+
+                let mut pages = utils::request_transactions(
+                    self.transport.as_ref(),
+                    address,
+                    id.lt(),
+                    latest_known_lt,
+                    count,
+                    Some(count as usize),
+                );
+                while let Some(page) = pages.next().await {
+                    transactions.extend(page?);
+                }
+            }
+        }
+
+        Ok(Some(AccountUpdate {
+            address: address.clone(),
+            state,
+            transactions,
+        }))
+    }
+
+    fn walks_blocks(&self) -> bool {
+        self.transport.info().reliable_behavior == ReliableBehavior::BlockWalking
+    }
+
+    fn concurrency(&self) -> usize {
+        self.settings.max_concurrent_refreshes.max(1)
+    }
+}
+
+#[derive(Clone)]
+struct ShardCursor {
+    shard: ton_block::ShardIdent,
+    block_id: String,
+}
+
+#[derive(Default)]
+struct SubscriptionEntry {
+    subscriptions: tokio::sync::Mutex<Vec<Box<dyn ManagedSubscription>>>,
+    /// Subscriptions added while the entry was locked
+    pending: Mutex<Vec<Box<dyn ManagedSubscription>>>,
+    next_refresh_at: AtomicU64,
+}
+
+impl SubscriptionEntry {
+    fn attach_pending(&self, subscriptions: &mut Vec<Box<dyn ManagedSubscription>>) {
+        subscriptions.append(&mut self.pending.lock());
+    }
+}
+
+/// Whether the subscription can be updated from the shared account state
+fn is_idle(subscription: &ContractSubscription) -> bool {
+    subscription.pending_transactions().is_empty()
+        && subscription.gaps().is_empty()
+        && subscription.transactions_synced()
+}
+
+fn contains_address(shard: &ton_block::ShardIdent, address: &MsgAddressInt) -> bool {
+    match ton_block::AccountIdPrefixFull::prefix(address) {
+        Ok(prefix) => shard.contains_full_prefix(&prefix),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "local_transport")]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::core::generic_contract::GenericContractSubscriptionHandler;
+    use crate::core::models::{
+        ContractState, NetworkCapabilities, PendingTransaction, Transaction, TransactionsBatchInfo,
+    };
+    use crate::transport::local::tests::{dst_address, make_transfer, src_address};
+    use crate::transport::local::LocalTransport;
+    use crate::transport::metrics::{InMemoryTransportMetrics, InstrumentedTransport};
+    use crate::transport::retry::TransportMethod;
+
+    struct TestTimer;
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl Timer for TestTimer {
+        async fn sleep(&self, _: Duration) {}
+    }
+
+    #[derive(Default)]
+    struct TestHandler {
+        transactions: AtomicUsize,
+    }
+
+    impl GenericContractSubscriptionHandler for TestHandler {
+        fn on_message_sent(&self, _: PendingTransaction, _: Option<Transaction>) {}
+
+        fn on_message_expired(&self, _: PendingTransaction) {}
+
+        fn on_state_changed(&self, _: ContractState) {}
+
+        fn on_transactions_found(&self, transactions: Vec<Transaction>, _: TransactionsBatchInfo) {
+            self.transactions
+                .fetch_add(transactions.len(), Ordering::Relaxed);
+        }
+    }
+
+    #[tokio::test]
+    async fn polls_subscriptions() -> Result<()> {
+        let clock = Arc::new(ClockWithOffset::new(0));
+        let metrics = Arc::new(InMemoryTransportMetrics::new());
+        let transport = Arc::new(InstrumentedTransport::new(
+            LocalTransport::new(clock.clone()),
+            metrics.clone(),
+        ));
+        // NOTE: messages are sent bypassing metrics
+        let local = transport.inner();
+        let calls = |method| {
+            metrics
+                .methods()
+                .get(&method)
+                .map(|stats| stats.calls)
+                .unwrap_or_default()
+        };
+
+        let dst = dst_address();
+
+        let manager =
+            SubscriptionManager::new(clock.clone(), transport.clone(), Arc::new(TestTimer));
+
+        let handlers = [
+            Arc::new(TestHandler::default()),
+            Arc::new(TestHandler::default()),
+        ];
+        for handler in &handlers {
+            let contract = GenericContract::subscribe(
+                clock.clone(),
+                transport.clone(),
+                dst.clone(),
+                handler.clone(),
+                false,
+            )
+            .await?;
+            manager.add(contract);
+        }
+        assert_eq!(manager.len(), 1);
+
+        // New subscriptions are refreshed immediately
+        assert_eq!(manager.poll().await, 1);
+        assert_eq!(manager.poll().await, 0);
+
+        local.send_message(&make_transfer(1_000_000_000)).await?;
+
+        // Both subscriptions for the same address are refreshed together
+        // from the same state and transactions
+        let (full_states, states, transactions) = (
+            calls(TransportMethod::GetContractState),
+            calls(TransportMethod::PollContractState),
+            calls(TransportMethod::GetTransactions),
+        );
+        clock.update_offset(60_000);
+        assert_eq!(manager.poll().await, 1);
+        for handler in &handlers {
+            assert_eq!(handler.transactions.load(Ordering::Relaxed), 1);
+        }
+        assert_eq!(calls(TransportMethod::GetContractState), full_states);
+        assert_eq!(calls(TransportMethod::PollContractState), states + 1);
+        assert_eq!(calls(TransportMethod::GetTransactions), transactions + 1);
+
+        // Unchanged accounts are only polled
+        clock.update_offset(120_000);
+        assert_eq!(manager.poll().await, 1);
+        for handler in &handlers {
+            assert_eq!(handler.transactions.load(Ordering::Relaxed), 1);
+        }
+        assert_eq!(calls(TransportMethod::GetContractState), full_states);
+        assert_eq!(calls(TransportMethod::PollContractState), states + 2);
+        assert_eq!(calls(TransportMethod::GetTransactions), transactions + 1);
+
+        // Explicit refresh ignores the schedule
+        local.send_message(&make_transfer(2_000_000_000)).await?;
+        manager.refresh(&dst).await?;
+        for handler in &handlers {
+            assert_eq!(handler.transactions.load(Ordering::Relaxed), 2);
+        }
+
+        assert!(manager.remove(&dst));
+        assert!(manager.is_empty());
+
+        Ok(())
+    }
+
+    /// Serves empty blocks of the single shard, counts block requests
+    struct BlockWalkingTransport {
+        inner: LocalTransport,
+        block_requests: AtomicUsize,
+    }
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl Transport for BlockWalkingTransport {
+        fn info(&self) -> crate::transport::TransportInfo {
+            crate::transport::TransportInfo {
+                reliable_behavior: ReliableBehavior::BlockWalking,
+                ..self.inner.info()
+            }
+        }
+
+        async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
+            self.inner.send_message(message).await
+        }
+
+        async fn get_contract_state(
+            &self,
+            address: &MsgAddressInt,
+        ) -> Result<crate::transport::models::RawContractState> {
+            self.inner.get_contract_state(address).await
+        }
+
+        async fn poll_contract_state(
+            &self,
+            address: &MsgAddressInt,
+            last_trans_lt: u64,
+        ) -> Result<crate::transport::models::PollContractState> {
+            self.inner.poll_contract_state(address, last_trans_lt).await
+        }
+
+        async fn get_accounts_by_code_hash(
+            &self,
+            code_hash: &ton_types::UInt256,
+            limit: u8,
+            continuation: &Option<MsgAddressInt>,
+        ) -> Result<Vec<MsgAddressInt>> {
+            self.inner
+                .get_accounts_by_code_hash(code_hash, limit, continuation)
+                .await
+        }
+
+        async fn get_transactions(
+            &self,
+            address: &MsgAddressInt,
+            from_lt: u64,
+            count: u8,
+        ) -> Result<Vec<crate::transport::models::RawTransaction>> {
+            self.inner.get_transactions(address, from_lt, count).await
+        }
+
+        async fn get_transaction(
+            &self,
+            id: &ton_types::UInt256,
+        ) -> Result<Option<crate::transport::models::RawTransaction>> {
+            self.inner.get_transaction(id).await
+        }
+
+        async fn get_dst_transaction(
+            &self,
+            message_hash: &ton_types::UInt256,
+        ) -> Result<Option<crate::transport::models::RawTransaction>> {
+            self.inner.get_dst_transaction(message_hash).await
+        }
+
+        async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+            self.inner.get_latest_key_block().await
+        }
+
+        async fn get_latest_block(
+            &self,
+            _: &MsgAddressInt,
+        ) -> Result<crate::transport::models::LatestBlock> {
+            Ok(crate::transport::models::LatestBlock {
+                id: format!("{:064x}", 1),
+                end_lt: 0,
+                gen_utime: 0,
+            })
+        }
+
+        async fn get_block(&self, id: &str) -> Result<ton_block::Block> {
+            self.block_requests.fetch_add(1, Ordering::Relaxed);
+
+            let mut info = ton_block::BlockInfo::default();
+            info.set_shard(ton_block::ShardIdent::full(0));
+            info.set_seq_no(u32::from_str_radix(id, 16)?)?;
+
+            ton_block::Block::with_params(
+                0,
+                info,
+                Default::default(),
+                Default::default(),
+                Default::default(),
+            )
+        }
+
+        async fn wait_for_next_block(
+            &self,
+            current: &str,
+            _: &MsgAddressInt,
+            _: Duration,
+        ) -> Result<String> {
+            Ok(format!("{:064x}", u32::from_str_radix(current, 16)? + 1))
+        }
+
+        async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
+            self.inner.get_capabilities(clock).await
+        }
+
+        async fn get_blockchain_config(
+            &self,
+            clock: &dyn Clock,
+            force: bool,
+        ) -> Result<ton_executor::BlockchainConfig> {
+            self.inner.get_blockchain_config(clock, force).await
+        }
+    }
+
+    #[tokio::test]
+    async fn shares_blocks_between_subscriptions() -> Result<()> {
+        let clock = Arc::new(ClockWithOffset::new(0));
+        let transport = Arc::new(BlockWalkingTransport {
+            inner: LocalTransport::new(clock.clone()),
+            block_requests: AtomicUsize::new(0),
+        });
+        let manager =
+            SubscriptionManager::new(clock.clone(), transport.clone(), Arc::new(TestTimer));
+
+        // Nothing to walk without subscriptions
+        assert_eq!(manager.walk_blocks(Duration::from_secs(1)).await?, 0);
+
+        for address in [src_address(), dst_address()] {
+            let contract = GenericContract::subscribe(
+                clock.clone(),
+                transport.clone(),
+                address,
+                Arc::new(TestHandler::default()),
+                false,
+            )
+            .await?;
+            manager.add(contract);
+        }
+        assert_eq!(manager.poll().await, 2);
+
+        // The shard of both addresses is walked once: the latest block
+        // is requested to find the shard, then the next block
+        assert_eq!(manager.walk_blocks(Duration::from_secs(1)).await?, 1);
+        assert_eq!(transport.block_requests.load(Ordering::Relaxed), 2);
+
+        assert_eq!(manager.walk_blocks(Duration::from_secs(1)).await?, 1);
+        assert_eq!(transport.block_requests.load(Ordering::Relaxed), 3);
+
+        Ok(())
+    }
+}
@@ -12,10 +12,10 @@ use nekoton_utils::*;
 use crate::core::models::*;
 use crate::core::parsing::*;
 use crate::core::transactions_tree::*;
-use crate::transport::models::{ExistingContract, RawContractState, RawTransaction};
+use crate::transport::models::{AccountUpdate, ExistingContract, RawContractState, RawTransaction};
 use crate::transport::Transport;
 
-use super::{ContractSubscription, InternalMessage};
+use super::{ContractSubscription, ContractSubscriptionState, InternalMessage};
 
 pub struct TokenWallet {
     clock: Arc<dyn Clock>,
@@ -34,6 +34,37 @@ impl TokenWallet {
         owner: MsgAddressInt,
         root_token_contract: MsgAddressInt,
         handler: Arc<dyn TokenWalletSubscriptionHandler>,
+    ) -> Result<TokenWallet> {
+        Self::subscribe_impl(clock, transport, owner, root_token_contract, None, handler).await
+    }
+
+    /// Resumes the subscription from the saved state
+    pub async fn subscribe_from_snapshot(
+        clock: Arc<dyn Clock>,
+        transport: Arc<dyn Transport>,
+        owner: MsgAddressInt,
+        root_token_contract: MsgAddressInt,
+        snapshot: ContractSubscriptionState,
+        handler: Arc<dyn TokenWalletSubscriptionHandler>,
+    ) -> Result<TokenWallet> {
+        Self::subscribe_impl(
+            clock,
+            transport,
+            owner,
+            root_token_contract,
+            Some(snapshot),
+            handler,
+        )
+        .await
+    }
+
+    async fn subscribe_impl(
+        clock: Arc<dyn Clock>,
+        transport: Arc<dyn Transport>,
+        owner: MsgAddressInt,
+        root_token_contract: MsgAddressInt,
+        snapshot: Option<ContractSubscriptionState>,
+        handler: Arc<dyn TokenWalletSubscriptionHandler>,
     ) -> Result<TokenWallet> {
         let state = match transport.get_contract_state(&root_token_contract).await? {
             RawContractState::Exists(state) => state,
@@ -53,14 +84,30 @@ impl TokenWallet {
         let address = state.get_wallet_address(clock.as_ref(), version, &owner)?;
 
         let mut balance = Default::default();
-        let contract_subscription = ContractSubscription::subscribe(
-            clock.clone(),
-            transport,
-            address,
-            &mut make_contract_state_handler(clock.clone(), version, &mut balance),
-            Some(&mut make_transactions_handler(handler.as_ref(), version)),
-        )
-        .await?;
+        let on_contract_state =
+            &mut make_contract_state_handler(clock.clone(), version, &mut balance);
+        let contract_subscription = match snapshot {
+            Some(snapshot) => {
+                ContractSubscription::subscribe_from_snapshot(
+                    clock.clone(),
+                    transport,
+                    address,
+                    snapshot,
+                    on_contract_state,
+                )
+                .await?
+            }
+            None => {
+                ContractSubscription::subscribe(
+                    clock.clone(),
+                    transport,
+                    address,
+                    on_contract_state,
+                    Some(&mut make_transactions_handler(handler.as_ref(), version)),
+                )
+                .await?
+            }
+        };
 
         handler.on_balance_changed(balance.clone());
 
@@ -281,6 +328,14 @@ impl TokenWallet {
             )
             .await?;
 
+        self.contract_subscription
+            .backfill_gaps(
+                &mut make_transactions_handler(handler, self.version),
+                &mut |_, _| {},
+                &mut |gap, status| handler.on_transactions_gap(gap, status),
+            )
+            .await?;
+
         if balance != self.balance {
             self.balance = balance;
             handler.on_balance_changed(self.balance.clone());
@@ -294,55 +349,81 @@ impl TokenWallet {
         let mut balance: BigInt = self.balance.clone().into();
 
         let handler = self.handler.as_ref();
-        self.contract_subscription.handle_block(
-            block,
-            &mut |transactions, batch_info| {
-                let transactions = transactions
-                    .into_iter()
-                    .filter_map(|transaction| {
-                        let description = match transaction.data.description.read_struct().ok()? {
-                            ton_block::TransactionDescr::Ordinary(description) => description,
-                            _ => return None,
-                        };
-
-                        let data =
-                            parse_token_transaction(&transaction.data, &description, version);
-
-                        if let Some(data) = &data {
-                            match data {
-                                TokenWalletTransaction::IncomingTransfer(
-                                    TokenIncomingTransfer { tokens, .. },
-                                )
-                                | TokenWalletTransaction::Accept(tokens)
-                                | TokenWalletTransaction::SwapBackBounced(tokens)
-                                | TokenWalletTransaction::TransferBounced(tokens) => {
-                                    balance += tokens.clone().to_bigint().trust_me();
-                                }
-                                TokenWalletTransaction::OutgoingTransfer(
-                                    TokenOutgoingTransfer { tokens, .. },
-                                )
-                                | TokenWalletTransaction::SwapBack(TokenSwapBack {
-                                    tokens, ..
-                                }) => {
-                                    balance -= tokens.clone().to_bigint().trust_me();
+        self.contract_subscription
+            .handle_block(
+                block,
+                &mut |transactions, batch_info| {
+                    let transactions = transactions
+                        .into_iter()
+                        .filter_map(|transaction| {
+                            let description =
+                                match transaction.data.description.read_struct().ok()? {
+                                    ton_block::TransactionDescr::Ordinary(description) => {
+                                        description
+                                    }
+                                    _ => return None,
+                                };
+
+                            let data =
+                                parse_token_transaction(&transaction.data, &description, version);
+
+                            if let Some(data) = &data {
+                                match data {
+                                    TokenWalletTransaction::IncomingTransfer(
+                                        TokenIncomingTransfer { tokens, .. },
+                                    )
+                                    | TokenWalletTransaction::Accept(tokens)
+                                    | TokenWalletTransaction::SwapBackBounced(tokens)
+                                    | TokenWalletTransaction::TransferBounced(tokens) => {
+                                        balance += tokens.clone().to_bigint().trust_me();
+                                    }
+                                    TokenWalletTransaction::OutgoingTransfer(
+                                        TokenOutgoingTransfer { tokens, .. },
+                                    )
+                                    | TokenWalletTransaction::SwapBack(TokenSwapBack {
+                                        tokens,
+                                        ..
+                                    }) => {
+                                        balance -= tokens.clone().to_bigint().trust_me();
+                                    }
                                 }
                             }
-                        }
 
-                        let transaction =
-                            Transaction::try_from((transaction.hash, transaction.data)).ok()?;
+                            let transaction =
+                                Transaction::try_from((transaction.hash, transaction.data)).ok()?;
 
-                        Some(TransactionWithData { transaction, data })
-                    })
-                    .collect();
+                            Some(TransactionWithData { transaction, data })
+                        })
+                        .collect();
+
+                    handler.on_transactions_found(transactions, batch_info)
+                },
+                &mut |_, _| {},
+                &mut |_| {},
+            )
+            .await?;
+
+        let balance = balance.to_biguint().unwrap_or_default();
+        if balance != self.balance {
+            self.balance = balance;
+            handler.on_balance_changed(self.balance.clone());
+        }
+
+        Ok(())
+    }
+
+    pub fn handle_account_update(&mut self, update: AccountUpdate) -> Result<()> {
+        let mut balance = self.balance.clone();
 
-                handler.on_transactions_found(transactions, batch_info)
-            },
+        let handler = self.handler.as_ref();
+        self.contract_subscription.handle_account_update(
+            update,
+            &mut make_contract_state_handler(self.clock.clone(), self.version, &mut balance),
+            &mut make_transactions_handler(handler, self.version),
             &mut |_, _| {},
             &mut |_| {},
         )?;
 
-        let balance = balance.to_biguint().unwrap_or_default();
         if balance != self.balance {
             self.balance = balance;
             handler.on_balance_changed(self.balance.clone());
@@ -374,6 +455,12 @@ pub trait TokenWalletSubscriptionHandler: Send + Sync {
         transactions: Vec<TransactionWithData<TokenWalletTransaction>>,
         batch_info: TransactionsBatchInfo,
     );
+
+    /// Called when missing transactions are detected or loaded
+    fn on_transactions_gap(&self, gap: TransactionsGap, status: TransactionsGapStatus) {
+        let _ = gap;
+        let _ = status;
+    }
 }
 
 pub async fn get_token_root_details(
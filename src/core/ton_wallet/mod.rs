@@ -16,14 +16,14 @@ use nekoton_utils::*;
 pub use self::multisig::MultisigType;
 use super::models::{
     ContractState, Expiration, MessageFlags, MultisigPendingTransaction, MultisigPendingUpdate,
-    PendingTransaction, Transaction, TransactionAdditionalInfo, TransactionWithData,
-    TransactionsBatchInfo,
+    PendingTransaction, RebroadcastPolicy, Transaction, TransactionAdditionalInfo,
+    TransactionWithData, TransactionsBatchInfo, TransactionsGap, TransactionsGapStatus,
 };
-use super::{ContractSubscription, PollingMethod};
+use super::{ContractSubscription, ContractSubscriptionState, PollingMethod};
 use crate::core::parsing::*;
 use crate::core::InternalMessage;
 use crate::crypto::UnsignedMessage;
-use crate::transport::models::{ExistingContract, RawContractState, RawTransaction};
+use crate::transport::models::{AccountUpdate, ExistingContract, RawContractState, RawTransaction};
 use crate::transport::Transport;
 
 pub mod ever_wallet;
@@ -83,6 +83,45 @@ impl TonWallet {
         })
     }
 
+    /// Resumes the subscription from the saved state
+    pub async fn subscribe_from_snapshot(
+        clock: Arc<dyn Clock>,
+        transport: Arc<dyn Transport>,
+        workchain: i8,
+        public_key: PublicKey,
+        wallet_type: WalletType,
+        snapshot: ContractSubscriptionState,
+        handler: Arc<dyn TonWalletSubscriptionHandler>,
+    ) -> Result<Self> {
+        let address = compute_address(&public_key, wallet_type, workchain);
+
+        let mut wallet_data = WalletData::default();
+
+        let contract_subscription = ContractSubscription::subscribe_from_snapshot(
+            clock.clone(),
+            transport,
+            address,
+            snapshot,
+            &mut make_contract_state_handler(
+                clock.as_ref(),
+                handler.as_ref(),
+                &public_key,
+                wallet_type,
+                &mut wallet_data,
+            ),
+        )
+        .await?;
+
+        Ok(Self {
+            clock,
+            public_key,
+            wallet_type,
+            contract_subscription,
+            handler,
+            wallet_data,
+        })
+    }
+
     pub async fn subscribe_by_address(
         clock: Arc<dyn Clock>,
         transport: Arc<dyn Transport>,
@@ -460,6 +499,11 @@ impl TonWallet {
         }
     }
 
+    /// Enables resending of sent messages until they are delivered or expired
+    pub fn set_rebroadcast_policy(&mut self, policy: Option<RebroadcastPolicy>) {
+        self.contract_subscription.set_rebroadcast_policy(policy);
+    }
+
     pub async fn send(
         &mut self,
         message: &ton_block::Message,
@@ -483,6 +527,14 @@ impl TonWallet {
                 &mut make_message_sent_handler(handler),
                 &mut make_message_expired_handler(handler),
             )
+            .await?;
+
+        self.contract_subscription
+            .backfill_gaps(
+                &mut make_transactions_handler(handler, self.wallet_type),
+                &mut make_message_sent_handler(handler),
+                &mut |gap, status| handler.on_transactions_gap(gap, status),
+            )
             .await
     }
 
@@ -490,12 +542,15 @@ impl TonWallet {
         // TODO: update wallet data here
 
         let handler = self.handler.as_ref();
-        let new_account_state = self.contract_subscription.handle_block(
-            block,
-            &mut make_transactions_handler(handler, self.wallet_type),
-            &mut make_message_sent_handler(handler),
-            &mut make_message_expired_handler(handler),
-        )?;
+        let new_account_state = self
+            .contract_subscription
+            .handle_block(
+                block,
+                &mut make_transactions_handler(handler, self.wallet_type),
+                &mut make_message_sent_handler(handler),
+                &mut make_message_expired_handler(handler),
+            )
+            .await?;
 
         if let Some(account_state) = new_account_state {
             handler.on_state_changed(account_state);
@@ -504,6 +559,23 @@ impl TonWallet {
         Ok(())
     }
 
+    pub fn handle_account_update(&mut self, update: AccountUpdate) -> Result<()> {
+        let handler = self.handler.as_ref();
+        self.contract_subscription.handle_account_update(
+            update,
+            &mut make_contract_state_handler(
+                self.clock.as_ref(),
+                handler,
+                &self.public_key,
+                self.wallet_type,
+                &mut self.wallet_data,
+            ),
+            &mut make_transactions_handler(handler, self.wallet_type),
+            &mut make_message_sent_handler(handler),
+            &mut make_message_expired_handler(handler),
+        )
+    }
+
     pub async fn preload_transactions(&mut self, from_lt: u64) -> Result<()> {
         let handler = self.handler.as_ref();
         self.contract_subscription
@@ -517,6 +589,15 @@ impl TonWallet {
     pub async fn estimate_fees(&mut self, message: &ton_block::Message) -> Result<u128> {
         self.contract_subscription.estimate_fees(message).await
     }
+
+    pub async fn estimate_fee_breakdown(
+        &mut self,
+        message: &ton_block::Message,
+    ) -> Result<FeeBreakdown> {
+        self.contract_subscription
+            .estimate_fee_breakdown(message)
+            .await
+    }
 }
 
 #[derive(Default)]
@@ -680,25 +761,26 @@ pub async fn find_existing_wallets(
     workchain_id: i8,
     wallet_types: &[WalletType],
 ) -> Result<Vec<ExistingWalletInfo>> {
-    use futures_util::stream::{FuturesUnordered, TryStreamExt};
-
-    wallet_types
+    let addresses = wallet_types
         .iter()
-        .map(|&wallet_type| async move {
-            let address = compute_address(public_key, wallet_type, workchain_id);
+        .map(|&wallet_type| compute_address(public_key, wallet_type, workchain_id))
+        .collect::<Vec<_>>();
 
-            let contract_state = transport.get_contract_state(&address).await?;
+    let contract_states = transport.get_contract_states(&addresses).await?;
 
-            Ok(ExistingWalletInfo {
+    Ok(wallet_types
+        .iter()
+        .zip(addresses)
+        .zip(contract_states)
+        .map(
+            |((&wallet_type, address), contract_state)| ExistingWalletInfo {
                 address,
                 public_key: *public_key,
                 wallet_type,
                 contract_state: contract_state.brief(),
-            })
-        })
-        .collect::<FuturesUnordered<_>>()
-        .try_collect::<Vec<ExistingWalletInfo>>()
-        .await
+            },
+        )
+        .collect())
 }
 
 #[derive(Serialize, Deserialize)]
@@ -1024,4 +1106,10 @@ pub trait TonWalletSubscriptionHandler: Send + Sync {
     fn on_unconfirmed_updates_changed(&self, unconfirmed_updates: &[MultisigPendingUpdate]) {
         let _ = unconfirmed_updates;
     }
+
+    /// Called when missing transactions are detected or loaded
+    fn on_transactions_gap(&self, gap: TransactionsGap, status: TransactionsGapStatus) {
+        let _ = gap;
+        let _ = status;
+    }
 }
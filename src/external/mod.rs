@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use anyhow::Result;
 use nekoton_utils::serde_optional_hex_array;
 use serde::{Deserialize, Serialize};
@@ -21,6 +23,13 @@ pub trait Storage: Sync + Send {
     fn remove_unchecked(&self, key: &str);
 }
 
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+pub trait Timer: Send + Sync {
+    /// Wait until the specified duration passes
+    async fn sleep(&self, duration: Duration);
+}
+
 #[cfg(feature = "gql_transport")]
 #[derive(Debug, Clone)]
 pub struct GqlRequest {
@@ -49,6 +58,11 @@ pub struct JrpcRequest {
 #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
 pub trait JrpcConnection: Send + Sync {
     async fn post(&self, req: JrpcRequest) -> Result<String>;
+
+    /// Whether the endpoint accepts JSON-RPC 2.0 batches (arrays of requests)
+    fn supports_batch(&self) -> bool {
+        false
+    }
 }
 
 #[cfg(feature = "proto_transport")]
@@ -0,0 +1,463 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use quick_cache::sync::Cache as QuickCache;
+use serde::{Deserialize, Serialize};
+use ton_block::MsgAddressInt;
+
+use nekoton_utils::*;
+
+use crate::core::models::NetworkCapabilities;
+
+use super::models::{
+    ContractStateAt, LatestBlock, PollContractState, RawContractState, RawTransaction,
+};
+use super::proof::ContractStateWithProof;
+use super::{AccountUpdatesStream, Transport, TransportInfo};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CachedTransportSettings {
+    /// How long the contract state is reused. Default: `1000`
+    #[serde(with = "serde_duration_ms")]
+    pub contract_state_ttl: Duration,
+    /// How long the latest key block is reused. Default: `60000`
+    #[serde(with = "serde_duration_ms")]
+    pub key_block_ttl: Duration,
+    /// How long network capabilities and blockchain config are reused. Default: `60000`
+    #[serde(with = "serde_duration_ms")]
+    pub capabilities_ttl: Duration,
+    /// Max number of cached contract states. Default: `1000`
+    pub contract_states_capacity: usize,
+    /// Max number of cached transactions and transaction lists. Default: `10000`
+    pub transactions_capacity: usize,
+}
+
+impl Default for CachedTransportSettings {
+    fn default() -> Self {
+        Self {
+            contract_state_ttl: Duration::from_secs(1),
+            key_block_ttl: Duration::from_secs(60),
+            capabilities_ttl: Duration::from_secs(60),
+            contract_states_capacity: 1000,
+            transactions_capacity: 10000,
+        }
+    }
+}
+
+/// Transport which caches responses of the underlying transport.
+///
+/// Contract states, key blocks and network parameters are reused until their TTL
+/// expires. Transactions are immutable, so they stay in the cache until evicted.
+/// Sending a message invalidates the cached state of its destination.
+pub struct CachedTransport<T> {
+    transport: T,
+    clock: Arc<dyn Clock>,
+    contract_state_ttl_ms: u64,
+    key_block_ttl_ms: u64,
+    capabilities_ttl_ms: u64,
+    contract_states: QuickCache<MsgAddressInt, Expiring<Arc<RawContractState>>>,
+    transactions: QuickCache<ton_types::UInt256, RawTransaction>,
+    dst_transactions: QuickCache<ton_types::UInt256, RawTransaction>,
+    transaction_lists: QuickCache<(MsgAddressInt, u64, u8), Arc<Vec<RawTransaction>>>,
+    key_block: Mutex<Option<Expiring<Arc<ton_block::Block>>>>,
+    capabilities: Mutex<Option<Expiring<NetworkCapabilities>>>,
+    config: Mutex<Option<Expiring<ton_executor::BlockchainConfig>>>,
+}
+
+impl<T: Transport> CachedTransport<T> {
+    pub fn new(transport: T, clock: Arc<dyn Clock>) -> Self {
+        Self::with_settings(transport, clock, Default::default())
+    }
+
+    pub fn with_settings(
+        transport: T,
+        clock: Arc<dyn Clock>,
+        settings: CachedTransportSettings,
+    ) -> Self {
+        Self {
+            transport,
+            clock,
+            contract_state_ttl_ms: settings.contract_state_ttl.as_millis() as u64,
+            key_block_ttl_ms: settings.key_block_ttl.as_millis() as u64,
+            capabilities_ttl_ms: settings.capabilities_ttl.as_millis() as u64,
+            contract_states: QuickCache::new(settings.contract_states_capacity.max(1)),
+            transactions: QuickCache::new(settings.transactions_capacity.max(1)),
+            dst_transactions: QuickCache::new(settings.transactions_capacity.max(1)),
+            transaction_lists: QuickCache::new(settings.transactions_capacity.max(1)),
+            key_block: Default::default(),
+            capabilities: Default::default(),
+            config: Default::default(),
+        }
+    }
+
+    /// Returns the underlying transport
+    pub fn inner(&self) -> &T {
+        &self.transport
+    }
+
+    /// Removes the cached state of the specified contract
+    pub fn invalidate_contract_state(&self, address: &MsgAddressInt) {
+        self.contract_states.remove(address);
+    }
+
+    fn cached_contract_state(&self, address: &MsgAddressInt) -> Option<Arc<RawContractState>> {
+        let now = self.clock.now_ms_u64();
+        self.contract_states
+            .get(address)
+            .and_then(|entry| entry.get(now))
+    }
+
+    fn store_contract_state(&self, address: &MsgAddressInt, state: &RawContractState) {
+        let entry = Expiring::new(
+            Arc::new(state.clone()),
+            self.clock.now_ms_u64(),
+            self.contract_state_ttl_ms,
+        );
+        self.contract_states.insert(address.clone(), entry);
+    }
+
+    fn store_transaction(&self, transaction: &RawTransaction) {
+        self.transactions
+            .insert(transaction.hash, transaction.clone());
+    }
+}
+
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl<T: Transport> Transport for CachedTransport<T> {
+    fn info(&self) -> TransportInfo {
+        self.transport.info()
+    }
+
+    async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
+        let dst = message.dst();
+        if let Some(dst) = &dst {
+            self.invalidate_contract_state(dst);
+        }
+        let result = self.transport.send_message(message).await;
+
+        // The state could have been requested while the message was being sent
+        if let Some(dst) = &dst {
+            self.invalidate_contract_state(dst);
+        }
+        result
+    }
+
+    async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
+        if let Some(state) = self.cached_contract_state(address) {
+            return Ok(state.as_ref().clone());
+        }
+
+        let state = self.transport.get_contract_state(address).await?;
+        self.store_contract_state(address, &state);
+        Ok(state)
+    }
+
+    async fn get_contract_states(
+        &self,
+        addresses: &[MsgAddressInt],
+    ) -> Result<Vec<RawContractState>> {
+        let cached = addresses
+            .iter()
+            .map(|address| self.cached_contract_state(address))
+            .collect::<Vec<_>>();
+
+        // Request all missing states at once
+        let missing = addresses
+            .iter()
+            .zip(&cached)
+            .filter(|(_, state)| state.is_none())
+            .map(|(address, _)| address.clone())
+            .collect::<Vec<_>>();
+        let loaded = if missing.is_empty() {
+            Vec::new()
+        } else {
+            self.transport.get_contract_states(&missing).await?
+        };
+        anyhow::ensure!(
+            loaded.len() == missing.len(),
+            "Invalid number of contract states"
+        );
+
+        let mut loaded = loaded.into_iter();
+        Ok(addresses
+            .iter()
+            .zip(cached)
+            .map(|(address, state)| match state {
+                Some(state) => state.as_ref().clone(),
+                None => {
+                    // NOTE: length is checked above
+                    let state = loaded.next().trust_me();
+                    self.store_contract_state(address, &state);
+                    state
+                }
+            })
+            .collect())
+    }
+
+    async fn poll_contract_state(
+        &self,
+        address: &MsgAddressInt,
+        last_trans_lt: u64,
+    ) -> Result<PollContractState> {
+        if let Some(state) = self.cached_contract_state(address) {
+            return Ok(match state.as_ref() {
+                RawContractState::Exists(contract)
+                    if contract.account.storage.last_trans_lt == last_trans_lt =>
+                {
+                    PollContractState::Unchanged {
+                        timings: contract.timings,
+                    }
+                }
+                state => state.clone().into(),
+            });
+        }
+
+        let state = self
+            .transport
+            .poll_contract_state(address, last_trans_lt)
+            .await?;
+        match &state {
+            PollContractState::Exists(contract) => {
+                self.store_contract_state(address, &RawContractState::Exists(contract.clone()))
+            }
+            PollContractState::NotExists { timings } => self
+                .store_contract_state(address, &RawContractState::NotExists { timings: *timings }),
+            PollContractState::Unchanged { .. } => {}
+        }
+        Ok(state)
+    }
+
+    async fn get_contract_state_at(
+        &self,
+        address: &MsgAddressInt,
+        at: ContractStateAt,
+    ) -> Result<RawContractState> {
+        self.transport.get_contract_state_at(address, at).await
+    }
+
+    async fn get_contract_state_with_proof(
+        &self,
+        address: &MsgAddressInt,
+    ) -> Result<ContractStateWithProof> {
+        // NOTE: proofs are not cached since they are requested to be checked
+        self.transport.get_contract_state_with_proof(address).await
+    }
+
+    async fn get_accounts_by_code_hash(
+        &self,
+        code_hash: &ton_types::UInt256,
+        limit: u8,
+        continuation: &Option<MsgAddressInt>,
+    ) -> Result<Vec<MsgAddressInt>> {
+        self.transport
+            .get_accounts_by_code_hash(code_hash, limit, continuation)
+            .await
+    }
+
+    async fn get_transactions(
+        &self,
+        address: &MsgAddressInt,
+        from_lt: u64,
+        count: u8,
+    ) -> Result<Vec<RawTransaction>> {
+        let key = (address.clone(), from_lt, count);
+        if let Some(transactions) = self.transaction_lists.get(&key) {
+            return Ok(transactions.as_ref().clone());
+        }
+
+        let transactions = self
+            .transport
+            .get_transactions(address, from_lt, count)
+            .await?;
+        transactions
+            .iter()
+            .for_each(|tx| self.store_transaction(tx));
+
+        // The list can only be reused when it starts exactly at `from_lt`,
+        // otherwise new transactions could appear in this range
+        if matches!(transactions.first(), Some(tx) if tx.data.lt == from_lt) {
+            self.transaction_lists
+                .insert(key, Arc::new(transactions.clone()));
+        }
+
+        Ok(transactions)
+    }
+
+    async fn get_transaction(&self, id: &ton_types::UInt256) -> Result<Option<RawTransaction>> {
+        if let Some(transaction) = self.transactions.get(id) {
+            return Ok(Some(transaction));
+        }
+
+        let transaction = self.transport.get_transaction(id).await?;
+        if let Some(transaction) = &transaction {
+            self.store_transaction(transaction);
+        }
+        Ok(transaction)
+    }
+
+    async fn get_dst_transaction(
+        &self,
+        message_hash: &ton_types::UInt256,
+    ) -> Result<Option<RawTransaction>> {
+        if let Some(transaction) = self.dst_transactions.get(message_hash) {
+            return Ok(Some(transaction));
+        }
+
+        // NOTE: missing transactions are not cached, because they could appear later
+        let transaction = self.transport.get_dst_transaction(message_hash).await?;
+        if let Some(transaction) = &transaction {
+            self.store_transaction(transaction);
+            self.dst_transactions
+                .insert(*message_hash, transaction.clone());
+        }
+        Ok(transaction)
+    }
+
+    async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+        let now = self.clock.now_ms_u64();
+        if let Some(block) = self.key_block.lock().as_ref().and_then(|e| e.get(now)) {
+            return Ok(block.as_ref().clone());
+        }
+
+        let block = self.transport.get_latest_key_block().await?;
+        *self.key_block.lock() = Some(Expiring::new(
+            Arc::new(block.clone()),
+            self.clock.now_ms_u64(),
+            self.key_block_ttl_ms,
+        ));
+        Ok(block)
+    }
+
+    async fn get_latest_block(&self, address: &MsgAddressInt) -> Result<LatestBlock> {
+        self.transport.get_latest_block(address).await
+    }
+
+    async fn get_block(&self, id: &str) -> Result<ton_block::Block> {
+        self.transport.get_block(id).await
+    }
+
+    async fn get_block_by_seqno(
+        &self,
+        shard: &ton_block::ShardIdent,
+        seqno: u32,
+    ) -> Result<ton_block::Block> {
+        self.transport.get_block_by_seqno(shard, seqno).await
+    }
+
+    async fn wait_for_next_block(
+        &self,
+        current: &str,
+        address: &MsgAddressInt,
+        timeout: Duration,
+    ) -> Result<String> {
+        self.transport
+            .wait_for_next_block(current, address, timeout)
+            .await
+    }
+
+    async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
+        let now = self.clock.now_ms_u64();
+        if let Some(capabilities) = self.capabilities.lock().as_ref().and_then(|e| e.get(now)) {
+            return Ok(capabilities);
+        }
+
+        let capabilities = self.transport.get_capabilities(clock).await?;
+        *self.capabilities.lock() = Some(Expiring::new(
+            capabilities,
+            self.clock.now_ms_u64(),
+            self.capabilities_ttl_ms,
+        ));
+        Ok(capabilities)
+    }
+
+    async fn get_blockchain_config(
+        &self,
+        clock: &dyn Clock,
+        force: bool,
+    ) -> Result<ton_executor::BlockchainConfig> {
+        if !force {
+            let now = self.clock.now_ms_u64();
+            if let Some(config) = self.config.lock().as_ref().and_then(|e| e.get(now)) {
+                return Ok(config);
+            }
+        }
+
+        let config = self.transport.get_blockchain_config(clock, force).await?;
+        *self.config.lock() = Some(Expiring::new(
+            config.clone(),
+            self.clock.now_ms_u64(),
+            self.capabilities_ttl_ms,
+        ));
+        Ok(config)
+    }
+
+    fn subscribe_accounts(&self, addresses: &[MsgAddressInt]) -> Option<AccountUpdatesStream> {
+        self.transport.subscribe_accounts(addresses)
+    }
+}
+
+#[derive(Clone)]
+struct Expiring<V> {
+    value: V,
+    expires_at: u64,
+}
+
+impl<V: Clone> Expiring<V> {
+    fn new(value: V, now: u64, ttl_ms: u64) -> Self {
+        Self {
+            value,
+            expires_at: now.saturating_add(ttl_ms),
+        }
+    }
+
+    fn get(&self, now: u64) -> Option<V> {
+        (now < self.expires_at).then(|| self.value.clone())
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "local_transport")]
+mod tests {
+    use super::*;
+    use crate::transport::local::tests::{dst_address, make_transfer};
+    use crate::transport::local::LocalTransport;
+
+    #[tokio::test]
+    async fn cached_transport_invalidates_state_on_send() -> Result<()> {
+        let clock = Arc::new(ConstClock::from_secs(1700000000));
+        let transport = CachedTransport::new(LocalTransport::new(clock.clone()), clock);
+
+        let dst = dst_address();
+        let message = make_transfer(1_000_000_000);
+
+        assert!(matches!(
+            transport.get_contract_state(&dst).await?,
+            RawContractState::NotExists { .. }
+        ));
+
+        // Changes made bypassing the cache are not visible until the TTL expires
+        transport.inner().send_message(&message).await?;
+        assert!(matches!(
+            transport.get_contract_state(&dst).await?,
+            RawContractState::NotExists { .. }
+        ));
+
+        transport.send_message(&message).await?;
+        let state = match transport.get_contract_state(&dst).await? {
+            RawContractState::Exists(state) => state,
+            RawContractState::NotExists { .. } => panic!("state must be invalidated"),
+        };
+
+        let last_trans_lt = state.account.storage.last_trans_lt;
+        assert!(matches!(
+            transport.poll_contract_state(&dst, last_trans_lt).await?,
+            PollContractState::Unchanged { .. }
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,572 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use futures_util::future::Either;
+use serde::{Deserialize, Serialize};
+use ton_block::MsgAddressInt;
+
+use nekoton_utils::*;
+
+use crate::core::models::NetworkCapabilities;
+use crate::external::Timer;
+
+use super::models::{
+    ContractStateAt, LatestBlock, PollContractState, RawContractState, RawTransaction,
+};
+use super::proof::ContractStateWithProof;
+use super::retry::is_retryable_error;
+use super::{AccountUpdatesStream, Transport, TransportInfo};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FallbackTransportSettings {
+    /// Number of consecutive failures after which the transport is
+    /// moved to the end of the queue. Default: `3`
+    pub max_failures: u32,
+    /// How long the failed transport stays at the end of the queue. Default: `30000`
+    #[serde(with = "serde_duration_ms")]
+    pub cooldown: Duration,
+    /// How long to wait for a response before trying the next transport. Default: `30000`
+    #[serde(with = "serde_duration_ms")]
+    pub request_timeout: Duration,
+}
+
+impl Default for FallbackTransportSettings {
+    fn default() -> Self {
+        Self {
+            max_failures: 3,
+            cooldown: Duration::from_secs(30),
+            request_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Transport which routes requests through an ordered list of transports.
+///
+/// Each request is sent to the first healthy transport. On retryable error
+/// the next one is used. Requests which take longer than the configured timeout
+/// are treated as failures. Transports which failed too many times in a row are only used
+/// as a last resort until the cooldown passes.
+pub struct FallbackTransport {
+    transports: Vec<Backend>,
+    clock: Arc<dyn Clock>,
+    timer: Arc<dyn Timer>,
+    max_failures: u32,
+    cooldown_ms: u64,
+    request_timeout: Duration,
+}
+
+impl FallbackTransport {
+    pub fn new(
+        transports: Vec<Arc<dyn Transport>>,
+        clock: Arc<dyn Clock>,
+        timer: Arc<dyn Timer>,
+    ) -> Result<Self> {
+        Self::with_settings(transports, clock, timer, Default::default())
+    }
+
+    pub fn with_settings(
+        transports: Vec<Arc<dyn Transport>>,
+        clock: Arc<dyn Clock>,
+        timer: Arc<dyn Timer>,
+        settings: FallbackTransportSettings,
+    ) -> Result<Self> {
+        if transports.is_empty() {
+            return Err(FallbackTransportError::NoTransportsSpecified.into());
+        }
+
+        Ok(Self {
+            transports: transports
+                .into_iter()
+                .map(|transport| Backend {
+                    transport,
+                    failures: Default::default(),
+                    disabled_until: Default::default(),
+                })
+                .collect(),
+            clock,
+            timer,
+            max_failures: settings.max_failures,
+            cooldown_ms: settings.cooldown.as_millis() as u64,
+            request_timeout: settings.request_timeout,
+        })
+    }
+
+    /// Returns underlying transports in their original order
+    pub fn transports(&self) -> impl Iterator<Item = &Arc<dyn Transport>> {
+        self.transports.iter().map(|backend| &backend.transport)
+    }
+
+    /// Returns the transport which will be used for the next request
+    pub fn current_transport(&self) -> &Arc<dyn Transport> {
+        // NOTE: there is always at least one transport
+        &self.transports[self.ordered_backends()[0]].transport
+    }
+
+    fn ordered_backends(&self) -> Vec<usize> {
+        let now = self.clock.now_ms_u64();
+
+        let (mut available, disabled): (Vec<usize>, Vec<usize>) = (0..self.transports.len())
+            .partition(|&i| self.transports[i].disabled_until.load(Ordering::Acquire) <= now);
+
+        // Use disabled transports only as a last resort
+        available.extend(disabled);
+        available
+    }
+
+    async fn call<'a, T, F, Fut>(&'a self, f: F) -> Result<T>
+    where
+        F: Fn(&'a dyn Transport) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        self.call_with_timeout(self.request_timeout, f).await
+    }
+
+    async fn call_with_timeout<'a, T, F, Fut>(&'a self, timeout: Duration, f: F) -> Result<T>
+    where
+        F: Fn(&'a dyn Transport) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut last_error = None;
+        for i in self.ordered_backends() {
+            let backend = &self.transports[i];
+            let request = f(backend.transport.as_ref());
+            futures_util::pin_mut!(request);
+
+            let result =
+                match futures_util::future::select(request, self.timer.sleep(timeout)).await {
+                    Either::Left((result, _)) => result,
+                    Either::Right(_) => Err(FallbackTransportError::RequestTimeout.into()),
+                };
+
+            match result {
+                Ok(result) => {
+                    backend.on_success();
+                    return Ok(result);
+                }
+                Err(e) if !is_retryable_error(&e) => {
+                    // Other transports would return the same error,
+                    // and this one is still reachable
+                    backend.on_success();
+                    return Err(e);
+                }
+                Err(e) => {
+                    log::warn!("Transport {i} failed: {e:?}");
+                    backend.on_failure(self.clock.as_ref(), self.max_failures, self.cooldown_ms);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        // NOTE: there is always at least one transport
+        Err(last_error.unwrap_or_else(|| FallbackTransportError::NoTransportsSpecified.into()))
+    }
+}
+
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl Transport for FallbackTransport {
+    fn info(&self) -> TransportInfo {
+        // Use the most restrictive combination, because any transport could be used
+        TransportInfo::most_restrictive(
+            self.transports
+                .iter()
+                .map(|backend| backend.transport.info()),
+        )
+    }
+
+    async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
+        self.call(|transport| transport.send_message(message)).await
+    }
+
+    async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
+        self.call(|transport| transport.get_contract_state(address))
+            .await
+    }
+
+    async fn get_contract_states(
+        &self,
+        addresses: &[MsgAddressInt],
+    ) -> Result<Vec<RawContractState>> {
+        self.call(|transport| transport.get_contract_states(addresses))
+            .await
+    }
+
+    async fn poll_contract_state(
+        &self,
+        address: &MsgAddressInt,
+        last_trans_lt: u64,
+    ) -> Result<PollContractState> {
+        self.call(|transport| transport.poll_contract_state(address, last_trans_lt))
+            .await
+    }
+
+    async fn get_contract_state_at(
+        &self,
+        address: &MsgAddressInt,
+        at: ContractStateAt,
+    ) -> Result<RawContractState> {
+        self.call(|transport| transport.get_contract_state_at(address, at))
+            .await
+    }
+
+    async fn get_contract_state_with_proof(
+        &self,
+        address: &MsgAddressInt,
+    ) -> Result<ContractStateWithProof> {
+        self.call(|transport| transport.get_contract_state_with_proof(address))
+            .await
+    }
+
+    async fn get_accounts_by_code_hash(
+        &self,
+        code_hash: &ton_types::UInt256,
+        limit: u8,
+        continuation: &Option<MsgAddressInt>,
+    ) -> Result<Vec<MsgAddressInt>> {
+        self.call(|transport| transport.get_accounts_by_code_hash(code_hash, limit, continuation))
+            .await
+    }
+
+    async fn get_transactions(
+        &self,
+        address: &MsgAddressInt,
+        from_lt: u64,
+        count: u8,
+    ) -> Result<Vec<RawTransaction>> {
+        self.call(|transport| transport.get_transactions(address, from_lt, count))
+            .await
+    }
+
+    async fn get_transaction(&self, id: &ton_types::UInt256) -> Result<Option<RawTransaction>> {
+        self.call(|transport| transport.get_transaction(id)).await
+    }
+
+    async fn get_dst_transaction(
+        &self,
+        message_hash: &ton_types::UInt256,
+    ) -> Result<Option<RawTransaction>> {
+        self.call(|transport| transport.get_dst_transaction(message_hash))
+            .await
+    }
+
+    async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+        self.call(|transport| transport.get_latest_key_block())
+            .await
+    }
+
+    async fn get_latest_block(&self, address: &MsgAddressInt) -> Result<LatestBlock> {
+        self.call(|transport| transport.get_latest_block(address))
+            .await
+    }
+
+    async fn get_block(&self, id: &str) -> Result<ton_block::Block> {
+        self.call(|transport| transport.get_block(id)).await
+    }
+
+    async fn get_block_by_seqno(
+        &self,
+        shard: &ton_block::ShardIdent,
+        seqno: u32,
+    ) -> Result<ton_block::Block> {
+        self.call(|transport| transport.get_block_by_seqno(shard, seqno))
+            .await
+    }
+
+    async fn wait_for_next_block(
+        &self,
+        current: &str,
+        address: &MsgAddressInt,
+        timeout: Duration,
+    ) -> Result<String> {
+        // NOTE: long polling must not be interrupted by the request timeout
+        self.call_with_timeout(self.request_timeout + timeout, |transport| {
+            transport.wait_for_next_block(current, address, timeout)
+        })
+        .await
+    }
+
+    async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
+        self.call(|transport| transport.get_capabilities(clock))
+            .await
+    }
+
+    async fn get_blockchain_config(
+        &self,
+        clock: &dyn Clock,
+        force: bool,
+    ) -> Result<ton_executor::BlockchainConfig> {
+        self.call(|transport| transport.get_blockchain_config(clock, force))
+            .await
+    }
+
+    fn subscribe_accounts(&self, addresses: &[MsgAddressInt]) -> Option<AccountUpdatesStream> {
+        self.current_transport().subscribe_accounts(addresses)
+    }
+}
+
+struct Backend {
+    transport: Arc<dyn Transport>,
+    failures: AtomicU32,
+    disabled_until: AtomicU64,
+}
+
+impl Backend {
+    fn on_success(&self) {
+        self.failures.store(0, Ordering::Release);
+        self.disabled_until.store(0, Ordering::Release);
+    }
+
+    fn on_failure(&self, clock: &dyn Clock, max_failures: u32, cooldown_ms: u64) {
+        let failures = self.failures.fetch_add(1, Ordering::AcqRel) + 1;
+        if failures >= max_failures {
+            self.disabled_until
+                .store(clock.now_ms_u64() + cooldown_ms, Ordering::Release);
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, Copy, Clone)]
+pub enum FallbackTransportError {
+    #[error("No transports specified")]
+    NoTransportsSpecified,
+    #[error("Request timed out")]
+    RequestTimeout,
+}
+
+impl FallbackTransportError {
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::RequestTimeout)
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "local_transport")]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::core::models::ReliableBehavior;
+    use crate::transport::local::LocalTransport;
+
+    struct UnreachableTransport {
+        hang: bool,
+    }
+
+    impl UnreachableTransport {
+        async fn fail<T>(&self) -> Result<T> {
+            if self.hang {
+                futures_util::future::pending::<()>().await;
+            }
+            anyhow::bail!("unreachable")
+        }
+    }
+
+    struct InstantTimer;
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl Timer for InstantTimer {
+        async fn sleep(&self, _: Duration) {}
+    }
+
+    fn test_address() -> MsgAddressInt {
+        MsgAddressInt::from_str(
+            "0:3333333333333333333333333333333333333333333333333333333333333333",
+        )
+        .unwrap()
+    }
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl Transport for UnreachableTransport {
+        fn info(&self) -> TransportInfo {
+            TransportInfo {
+                max_transactions_per_fetch: 10,
+                max_accounts_per_fetch: 10,
+                reliable_behavior: ReliableBehavior::BlockWalking,
+                has_key_blocks: true,
+            }
+        }
+
+        async fn send_message(&self, _: &ton_block::Message) -> Result<()> {
+            self.fail().await
+        }
+
+        async fn get_contract_state(&self, _: &MsgAddressInt) -> Result<RawContractState> {
+            self.fail().await
+        }
+
+        async fn poll_contract_state(
+            &self,
+            _: &MsgAddressInt,
+            _: u64,
+        ) -> Result<PollContractState> {
+            self.fail().await
+        }
+
+        async fn get_accounts_by_code_hash(
+            &self,
+            _: &ton_types::UInt256,
+            _: u8,
+            _: &Option<MsgAddressInt>,
+        ) -> Result<Vec<MsgAddressInt>> {
+            self.fail().await
+        }
+
+        async fn get_transactions(
+            &self,
+            _: &MsgAddressInt,
+            _: u64,
+            _: u8,
+        ) -> Result<Vec<RawTransaction>> {
+            self.fail().await
+        }
+
+        async fn get_transaction(&self, _: &ton_types::UInt256) -> Result<Option<RawTransaction>> {
+            self.fail().await
+        }
+
+        async fn get_dst_transaction(
+            &self,
+            _: &ton_types::UInt256,
+        ) -> Result<Option<RawTransaction>> {
+            self.fail().await
+        }
+
+        async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+            self.fail().await
+        }
+
+        async fn get_capabilities(&self, _: &dyn Clock) -> Result<NetworkCapabilities> {
+            self.fail().await
+        }
+
+        async fn get_blockchain_config(
+            &self,
+            _: &dyn Clock,
+            _: bool,
+        ) -> Result<ton_executor::BlockchainConfig> {
+            self.fail().await
+        }
+    }
+
+    #[tokio::test]
+    async fn fallback_transport_switches_on_errors() -> Result<()> {
+        let clock = Arc::new(ClockWithOffset::new(0));
+        let unreachable: Arc<dyn Transport> = Arc::new(UnreachableTransport { hang: false });
+        let local: Arc<dyn Transport> = Arc::new(LocalTransport::new(clock.clone()));
+
+        let transport = FallbackTransport::with_settings(
+            vec![unreachable.clone(), local.clone()],
+            clock.clone(),
+            Arc::new(InstantTimer),
+            FallbackTransportSettings {
+                max_failures: 1,
+                cooldown: Duration::from_secs(60),
+                ..Default::default()
+            },
+        )?;
+
+        let info = transport.info();
+        assert_eq!(info.max_transactions_per_fetch, 10);
+        assert_eq!(info.reliable_behavior, ReliableBehavior::IntensivePolling);
+        assert!(!info.has_key_blocks);
+
+        assert!(Arc::ptr_eq(transport.current_transport(), &unreachable));
+
+        transport.get_contract_state(&test_address()).await?;
+
+        assert!(Arc::ptr_eq(transport.current_transport(), &local));
+
+        // The failed transport is used again after the cooldown
+        clock.update_offset(61_000);
+        assert!(Arc::ptr_eq(transport.current_transport(), &unreachable));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fallback_transport_returns_deterministic_errors() -> Result<()> {
+        use crate::transport::metrics::{InMemoryTransportMetrics, InstrumentedTransport};
+        use crate::transport::TransportError;
+
+        let clock = Arc::new(ClockWithOffset::new(0));
+        let local: Arc<dyn Transport> = Arc::new(LocalTransport::new(clock.clone()));
+        let metrics = Arc::new(InMemoryTransportMetrics::new());
+        let unreachable: Arc<dyn Transport> = Arc::new(InstrumentedTransport::new(
+            UnreachableTransport { hang: false },
+            metrics.clone(),
+        ));
+
+        let transport = FallbackTransport::with_settings(
+            vec![local.clone(), unreachable],
+            clock,
+            Arc::new(InstantTimer),
+            FallbackTransportSettings {
+                max_failures: 1,
+                ..Default::default()
+            },
+        )?;
+
+        // Rejected messages are not sent to other transports
+        let message =
+            ton_block::Message::with_ext_in_header(ton_block::ExternalInboundMessageHeader {
+                dst: test_address(),
+                ..Default::default()
+            });
+        let error = transport.send_message(&message).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<TransportError>(),
+            Some(TransportError::MessageRejected(_))
+        ));
+
+        // Unsupported methods don't put the transport into cooldown
+        let error = transport.get_block("00").await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<TransportError>(),
+            Some(TransportError::MethodNotSupported)
+        ));
+
+        assert!(Arc::ptr_eq(transport.current_transport(), &local));
+        assert!(metrics.methods().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fallback_transport_switches_on_timeout() -> Result<()> {
+        let clock = Arc::new(ClockWithOffset::new(0));
+        let hanging: Arc<dyn Transport> = Arc::new(UnreachableTransport { hang: true });
+        let local: Arc<dyn Transport> = Arc::new(LocalTransport::new(clock.clone()));
+
+        let transport = FallbackTransport::with_settings(
+            vec![hanging.clone(), local.clone()],
+            clock,
+            Arc::new(InstantTimer),
+            FallbackTransportSettings {
+                max_failures: 1,
+                ..Default::default()
+            },
+        )?;
+
+        transport.get_contract_state(&test_address()).await?;
+        assert!(Arc::ptr_eq(transport.current_transport(), &local));
+
+        let transport =
+            FallbackTransport::new(vec![hanging], Arc::new(SimpleClock), Arc::new(InstantTimer))?;
+        let error = transport
+            .get_contract_state(&test_address())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<FallbackTransportError>(),
+            Some(FallbackTransportError::RequestTimeout)
+        ));
+
+        Ok(())
+    }
+}
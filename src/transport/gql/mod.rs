@@ -13,12 +13,15 @@ use crate::core::models::{NetworkCapabilities, ReliableBehavior};
 use crate::external::{GqlConnection, GqlRequest};
 
 use self::queries::*;
+use super::long_poll::long_poll_accounts;
 use super::models::*;
 use super::utils::ConfigCache;
-use super::{Transport, TransportInfo};
+use super::{AccountUpdatesStream, Transport, TransportError, TransportInfo};
 
 mod queries;
 
+pub use super::models::LatestBlock;
+
 pub struct GqlTransport {
     connection: Arc<dyn GqlConnection>,
     config_cache: ConfigCache,
@@ -34,36 +37,8 @@ impl GqlTransport {
         }
     }
 
-    async fn fetch<T>(&self, params: T::Variables) -> Result<T::ResponseData>
-    where
-        T: GqlQuery,
-    {
-        let request_body = serde_json::to_string(&T::build_query(&params)).trust_me();
-        let response = self
-            .connection
-            .post(GqlRequest {
-                data: request_body,
-                long_query: T::LONG_QUERY,
-            })
-            .await
-            .map_err(api_failure)?;
-
-        #[derive(Deserialize)]
-        pub struct Response<T> {
-            pub data: Option<T>,
-        }
-
-        match serde_json::from_str::<Response<T::ResponseData>>(&response) {
-            Ok(response) => response.data.ok_or_else(|| invalid_response().into()),
-            Err(e) => Err(api_failure(format!(
-                "Failed parsing api response: {e}. Response data: {response}"
-            ))
-            .into()),
-        }
-    }
-
-    pub async fn get_latest_block(&self, addr: &MsgAddressInt) -> Result<LatestBlock> {
-        let workchain_id = addr.get_workchain_id();
+    pub async fn get_latest_block(&self, address: &MsgAddressInt) -> Result<LatestBlock> {
+        let workchain_id = address.get_workchain_id();
 
         let block = self
             .fetch::<QueryLatestMasterchainBlock>(())
@@ -85,7 +60,7 @@ impl GqlTransport {
 
                 // Find matching shard
                 for item in block.master.shard_hashes {
-                    if check_shard_match(item.workchain_id, &item.shard, addr)? {
+                    if check_shard_match(item.workchain_id, &item.shard, address)? {
                         return Ok(LatestBlock {
                             id: item.descr.root_hash,
                             end_lt: parse_lt(&item.descr.end_lt)?,
@@ -142,7 +117,7 @@ impl GqlTransport {
     pub async fn wait_for_next_block(
         &self,
         current: &str,
-        addr: &MsgAddressInt,
+        address: &MsgAddressInt,
         timeout: Duration,
     ) -> Result<String> {
         let timeout_ms = timeout.as_secs_f64() * 1000.0;
@@ -156,23 +131,31 @@ impl GqlTransport {
             .blocks;
         let block = blocks.into_iter().next().ok_or_else(no_blocks_found)?;
 
-        let block_id =
-            if block.after_split && !check_shard_match(block.workchain_id, &block.shard, addr)? {
-                let blocks = self
-                    .fetch::<QueryBlockAfterSplit>(query_block_after_split::Variables {
-                        block_id: block.id,
-                        prev_id: current.to_owned(),
-                        timeout: timeout_ms,
-                    })
-                    .await?
-                    .blocks;
-                blocks.into_iter().next().ok_or_else(no_blocks_found)?.id
-            } else {
-                block.id
-            };
+        let block_id = if block.after_split
+            && !check_shard_match(block.workchain_id, &block.shard, address)?
+        {
+            let blocks = self
+                .fetch::<QueryBlockAfterSplit>(query_block_after_split::Variables {
+                    block_id: block.id,
+                    prev_id: current.to_owned(),
+                    timeout: timeout_ms,
+                })
+                .await?
+                .blocks;
+            blocks.into_iter().next().ok_or_else(no_blocks_found)?.id
+        } else {
+            block.id
+        };
 
         Ok(block_id)
     }
+
+    async fn fetch<T>(&self, params: T::Variables) -> Result<T::ResponseData>
+    where
+        T: GqlQuery,
+    {
+        fetch::<T>(self.connection.as_ref(), params).await
+    }
 }
 
 #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
@@ -181,6 +164,7 @@ impl Transport for GqlTransport {
     fn info(&self) -> TransportInfo {
         TransportInfo {
             max_transactions_per_fetch: 50,
+            max_accounts_per_fetch: 50,
             reliable_behavior: ReliableBehavior::BlockWalking,
             has_key_blocks: !self.connection.is_local(),
         }
@@ -197,49 +181,27 @@ impl Transport for GqlTransport {
         );
         let id = base64::encode(cell.repr_hash());
 
-        let _ = self
-            .fetch::<MutationSendMessage>(mutation_send_message::Variables { id, boc })
-            .await?;
+        let variables = mutation_send_message::Variables { id, boc };
+        let response = self
+            .connection
+            .post(GqlRequest {
+                data: serde_json::to_string(&MutationSendMessage::build_query(&variables))
+                    .trust_me(),
+                long_query: MutationSendMessage::LONG_QUERY,
+            })
+            .await
+            .map_err(api_failure)?;
+
+        if let Some(reason) = parse_errors(&response) {
+            return Err(TransportError::MessageRejected(reason).into());
+        }
+        parse_response::<MutationSendMessage>(&response)?;
 
         Ok(())
     }
 
     async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
-        let account_state = match self
-            .fetch::<QueryAccountState>(query_account_state::Variables {
-                address: address.to_string(),
-            })
-            .await?
-            .accounts
-            .into_iter()
-            .next()
-            .and_then(|state| state.boc)
-        {
-            Some(boc) => boc,
-            None => {
-                return Ok(RawContractState::NotExists {
-                    timings: GenTimings::Unknown,
-                })
-            }
-        };
-
-        match Account::construct_from_base64(&account_state) {
-            Ok(Account::Account(account)) => {
-                let last_transaction_id = LastTransactionId::Inexact {
-                    latest_lt: account.storage.last_trans_lt,
-                };
-
-                Ok(RawContractState::Exists(ExistingContract {
-                    account,
-                    timings: GenTimings::Unknown,
-                    last_transaction_id,
-                }))
-            }
-            Ok(_) => Ok(RawContractState::NotExists {
-                timings: GenTimings::Unknown,
-            }),
-            Err(_) => Err(NodeClientError::InvalidAccountState.into()),
-        }
+        fetch_contract_state(self.connection.as_ref(), address).await
     }
 
     async fn poll_contract_state(
@@ -252,6 +214,75 @@ impl Transport for GqlTransport {
         Ok(PollContractState::from(state))
     }
 
+    async fn get_contract_state_at(
+        &self,
+        address: &MsgAddressInt,
+        at: ContractStateAt,
+    ) -> Result<RawContractState> {
+        let block_id = match at {
+            ContractStateAt::Lt(lt) => {
+                let transactions = self
+                    .fetch::<QueryAccountTransactionsAround>(
+                        query_account_transactions_around::Variables {
+                            address: address.to_string(),
+                            lt: lt.to_string(),
+                        },
+                    )
+                    .await?;
+
+                let block_id = match transactions.before.into_iter().next() {
+                    Some(transaction) => transaction.block_id,
+                    // There were no transactions yet
+                    None => {
+                        return Ok(RawContractState::NotExists {
+                            timings: GenTimings::Unknown,
+                        })
+                    }
+                };
+
+                // Only states at the end of blocks are stored, so the state between
+                // transactions of the same block can't be restored
+                if matches!(transactions.after.first(), Some(next) if next.block_id == block_id) {
+                    return Err(NodeClientError::StateNotAvailable.into());
+                }
+
+                block_id
+            }
+            ContractStateAt::BlockSeqno(seqno) => {
+                let workchain_id = address.get_workchain_id();
+                let blocks = self
+                    .fetch::<QueryShardBlocksBySeqno>(query_shard_blocks_by_seqno::Variables {
+                        workchain: workchain_id,
+                        seqno: seqno as f64,
+                    })
+                    .await?
+                    .blocks;
+
+                let mut block_id = None;
+                for block in blocks {
+                    if check_shard_match(workchain_id, &block.shard, address)? {
+                        block_id = Some(block.id);
+                        break;
+                    }
+                }
+                block_id.ok_or_else(no_blocks_found)?
+            }
+        };
+
+        let boc = self
+            .fetch::<QueryAccountStateAtBlock>(query_account_state_at_block::Variables {
+                address: address.to_string(),
+                block_id,
+            })
+            .await?
+            .blockchain
+            .account
+            .info
+            .and_then(|info| info.boc);
+
+        parse_account_state(boc)
+    }
+
     async fn get_accounts_by_code_hash(
         &self,
         code_hash: &ton_types::UInt256,
@@ -353,6 +384,42 @@ impl Transport for GqlTransport {
             .map_err(|_| NodeClientError::InvalidBlock.into())
     }
 
+    async fn get_latest_block(&self, address: &MsgAddressInt) -> Result<LatestBlock> {
+        GqlTransport::get_latest_block(self, address).await
+    }
+
+    async fn get_block(&self, id: &str) -> Result<ton_block::Block> {
+        GqlTransport::get_block(self, id).await
+    }
+
+    async fn get_block_by_seqno(
+        &self,
+        shard: &ton_block::ShardIdent,
+        seqno: u32,
+    ) -> Result<ton_block::Block> {
+        let blocks = self
+            .fetch::<QueryBlockBySeqno>(query_block_by_seqno::Variables {
+                workchain: shard.workchain_id(),
+                shard: format!("{:016x}", shard.shard_prefix_with_tag()),
+                seqno: seqno as f64,
+            })
+            .await?
+            .blocks;
+        let boc = blocks.into_iter().next().ok_or_else(no_blocks_found)?.boc;
+
+        ton_block::Block::construct_from_base64(&boc)
+            .map_err(|_| NodeClientError::InvalidBlock.into())
+    }
+
+    async fn wait_for_next_block(
+        &self,
+        current: &str,
+        address: &MsgAddressInt,
+        timeout: Duration,
+    ) -> Result<String> {
+        GqlTransport::wait_for_next_block(self, current, address, timeout).await
+    }
+
     async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
         let (capabilities, _) = self
             .config_cache
@@ -372,13 +439,122 @@ impl Transport for GqlTransport {
             .await?;
         Ok(config)
     }
+
+    fn subscribe_accounts(&self, addresses: &[MsgAddressInt]) -> Option<AccountUpdatesStream> {
+        // NOTE: the stream must own its transport
+        let transport = GqlTransport::new(self.connection.clone());
+        Some(long_poll_accounts(transport, addresses))
+    }
+}
+
+async fn fetch<T>(connection: &dyn GqlConnection, params: T::Variables) -> Result<T::ResponseData>
+where
+    T: GqlQuery,
+{
+    let request_body = serde_json::to_string(&T::build_query(&params)).trust_me();
+    let response = connection
+        .post(GqlRequest {
+            data: request_body,
+            long_query: T::LONG_QUERY,
+        })
+        .await
+        .map_err(api_failure)?;
+
+    parse_response::<T>(&response)
+}
+
+fn parse_response<T>(response: &str) -> Result<T::ResponseData>
+where
+    T: GqlQuery,
+{
+    #[derive(Deserialize)]
+    pub struct Response<T> {
+        pub data: Option<T>,
+    }
+
+    match serde_json::from_str::<Response<T::ResponseData>>(response) {
+        Ok(response) => response.data.ok_or_else(|| invalid_response().into()),
+        Err(e) => Err(api_failure(format!(
+            "Failed parsing api response: {e}. Response data: {response}"
+        ))
+        .into()),
+    }
+}
+
+/// Joins messages of the GraphQL `errors` array, if any
+fn parse_errors(response: &str) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Response {
+        #[serde(default)]
+        errors: Vec<Error>,
+    }
+
+    #[derive(Deserialize)]
+    struct Error {
+        message: String,
+    }
+
+    let response = serde_json::from_str::<Response>(response).ok()?;
+    if response.errors.is_empty() {
+        return None;
+    }
+
+    Some(
+        response
+            .errors
+            .into_iter()
+            .map(|error| error.message)
+            .collect::<Vec<_>>()
+            .join("; "),
+    )
 }
 
-#[derive(Clone, Debug)]
-pub struct LatestBlock {
-    pub id: String,
-    pub end_lt: u64,
-    pub gen_utime: u32,
+async fn fetch_contract_state(
+    connection: &dyn GqlConnection,
+    address: &MsgAddressInt,
+) -> Result<RawContractState> {
+    let boc = fetch::<QueryAccountState>(
+        connection,
+        query_account_state::Variables {
+            address: address.to_string(),
+        },
+    )
+    .await?
+    .accounts
+    .into_iter()
+    .next()
+    .and_then(|state| state.boc);
+
+    parse_account_state(boc)
+}
+
+fn parse_account_state(boc: Option<String>) -> Result<RawContractState> {
+    let account_state = match boc {
+        Some(boc) => boc,
+        None => {
+            return Ok(RawContractState::NotExists {
+                timings: GenTimings::Unknown,
+            })
+        }
+    };
+
+    match Account::construct_from_base64(&account_state) {
+        Ok(Account::Account(account)) => {
+            let last_transaction_id = LastTransactionId::Inexact {
+                latest_lt: account.storage.last_trans_lt,
+            };
+
+            Ok(RawContractState::Exists(ExistingContract {
+                account,
+                timings: GenTimings::Unknown,
+                last_transaction_id,
+            }))
+        }
+        Ok(_) => Ok(RawContractState::NotExists {
+            timings: GenTimings::Unknown,
+        }),
+        Err(_) => Err(NodeClientError::InvalidAccountState.into()),
+    }
 }
 
 fn check_shard_match(workchain_id: i32, shard: &str, addr: &MsgAddressInt) -> Result<bool> {
@@ -435,6 +611,18 @@ pub enum NodeClientError {
     InvalidBlock,
     #[error("Invalid config")]
     InvalidConfig,
+    #[error("Account state is not available")]
+    StateNotAvailable,
+}
+
+impl NodeClientError {
+    /// Whether the error could be caused by a temporary API failure
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::ApiFailure { .. } | Self::InvalidResponse | Self::NoBlocksFound
+        )
+    }
 }
 
 #[cfg(test)]
@@ -465,6 +653,116 @@ mod tests {
         }
     }
 
+    const TEST_ADDRESS: &str = "0:3333333333333333333333333333333333333333333333333333333333333333";
+
+    /// Answers queries which contain the specified substring
+    struct QueryConnection {
+        responses: Vec<(&'static str, String)>,
+        requests: parking_lot::Mutex<Vec<serde_json::Value>>,
+    }
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl GqlConnection for QueryConnection {
+        fn is_local(&self) -> bool {
+            true
+        }
+
+        async fn post(&self, req: GqlRequest) -> Result<String> {
+            let request: serde_json::Value = serde_json::from_str(&req.data)?;
+            let query = request["query"].as_str().unwrap_or_default().to_owned();
+            self.requests.lock().push(request);
+
+            self.responses
+                .iter()
+                .find(|(pattern, _)| query.contains(pattern))
+                .map(|(_, response)| response.clone())
+                .ok_or_else(|| anyhow::anyhow!("unexpected query: {query}"))
+        }
+    }
+
+    #[tokio::test]
+    async fn builds_historical_state_queries() -> Result<()> {
+        let address = MsgAddressInt::from_str(TEST_ADDRESS)?;
+
+        let account = Account::with_address_and_ballance(
+            &address,
+            &ton_block::CurrencyCollection::with_grams(1_000_000_000),
+        );
+        let boc = base64::encode(account.write_to_bytes()?);
+        let account_response =
+            format!(r#"{{"data":{{"blockchain":{{"account":{{"info":{{"boc":"{boc}"}}}}}}}}}}"#);
+
+        // By lt
+        let connection = Arc::new(QueryConnection {
+            responses: vec![
+                (
+                    "before:transactions",
+                    r#"{"data":{"before":[{"block_id":"aa"}],"after":[{"block_id":"bb"}]}}"#
+                        .to_owned(),
+                ),
+                ("byBlock", account_response.clone()),
+            ],
+            requests: Default::default(),
+        });
+        let transport = GqlTransport::new(connection.clone());
+
+        let state = transport
+            .get_contract_state_at(&address, ContractStateAt::Lt(123))
+            .await?;
+        assert_eq!(state.brief().balance, 1_000_000_000);
+
+        {
+            let requests = connection.requests.lock();
+            assert_eq!(requests.len(), 2);
+            assert_eq!(requests[0]["variables"]["a"], TEST_ADDRESS);
+            assert_eq!(requests[0]["variables"]["lt"], "123");
+            assert_eq!(requests[1]["variables"]["b"], "aa");
+        }
+
+        // Transactions in the same block
+        let connection = Arc::new(QueryConnection {
+            responses: vec![(
+                "before:transactions",
+                r#"{"data":{"before":[{"block_id":"aa"}],"after":[{"block_id":"aa"}]}}"#.to_owned(),
+            )],
+            requests: Default::default(),
+        });
+        let error = GqlTransport::new(connection)
+            .get_contract_state_at(&address, ContractStateAt::Lt(123))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<NodeClientError>(),
+            Some(NodeClientError::StateNotAvailable)
+        ));
+
+        // By block seqno
+        let connection = Arc::new(QueryConnection {
+            responses: vec![
+                (
+                    "seq_no",
+                    r#"{"data":{"blocks":[{"id":"cc","shard":"8000000000000000"}]}}"#.to_owned(),
+                ),
+                ("byBlock", account_response),
+            ],
+            requests: Default::default(),
+        });
+        let transport = GqlTransport::new(connection.clone());
+
+        let state = transport
+            .get_contract_state_at(&address, ContractStateAt::BlockSeqno(10))
+            .await?;
+        assert!(matches!(state, RawContractState::Exists(_)));
+
+        let requests = connection.requests.lock();
+        assert_eq!(requests[0]["variables"]["w"], 0);
+        assert_eq!(requests[0]["variables"]["n"], 10.0);
+        assert_eq!(requests[1]["variables"]["b"], "cc");
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_connection() {
         let transport = GqlTransport::new(Arc::new(reqwest::Client::new()));
@@ -37,9 +37,13 @@ macro_rules! declare_queries {
 
 declare_queries! {
     QueryBlock => query_block,
+    QueryBlockBySeqno => query_block_by_seqno,
     QueryNextBlock => query_next_block (LONG_QUERY = true),
     QueryBlockAfterSplit => query_block_after_split (LONG_QUERY = true),
     QueryAccountState => query_account_state,
+    QueryAccountStateAtBlock => query_account_state_at_block,
+    QueryAccountTransactionsAround => query_account_transactions_around,
+    QueryShardBlocksBySeqno => query_shard_blocks_by_seqno,
     QueryAccountTransactions => query_account_transactions,
     QueryTransaction => query_transaction,
     QueryDstTransaction => query_dst_transaction,
@@ -72,6 +76,58 @@ pub mod query_block {
     }
 }
 
+pub mod query_shard_blocks_by_seqno {
+    use super::*;
+
+    pub const QUERY: &str =
+        "query($w:Int!,$n:Float!){blocks(filter:{workchain_id:{eq:$w},seq_no:{eq:$n}}){id shard}}";
+
+    #[derive(Serialize)]
+    pub struct Variables {
+        #[serde(rename = "w")]
+        pub workchain: i32,
+        #[serde(rename = "n")]
+        pub seqno: f64,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ResponseData {
+        pub blocks: Vec<QueryShardBlocksBySeqnoBlocks>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct QueryShardBlocksBySeqnoBlocks {
+        pub id: String,
+        pub shard: String,
+    }
+}
+
+pub mod query_block_by_seqno {
+    use super::*;
+
+    pub const QUERY: &str = "query($w:Int!,$s:String!,$n:Float!){blocks(filter:{workchain_id:{eq:$w},shard:{eq:$s},seq_no:{eq:$n}},limit:1){boc}}";
+
+    #[derive(Serialize)]
+    pub struct Variables {
+        #[serde(rename = "w")]
+        pub workchain: i32,
+        #[serde(rename = "s")]
+        pub shard: String,
+        #[serde(rename = "n")]
+        pub seqno: f64,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ResponseData {
+        pub blocks: Vec<QueryBlockBySeqnoBlocks>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct QueryBlockBySeqnoBlocks {
+        pub boc: String,
+    }
+}
+
 pub mod query_next_block {
     use super::*;
 
@@ -143,6 +199,65 @@ pub mod query_account_state {
     }
 }
 
+pub mod query_account_state_at_block {
+    use super::*;
+
+    pub const QUERY: &str =
+        "query($a:String!,$b:String!){blockchain{account(address:$a){info(byBlock:$b){boc}}}}";
+
+    #[derive(Serialize)]
+    pub struct Variables {
+        #[serde(rename = "a")]
+        pub address: String,
+        #[serde(rename = "b")]
+        pub block_id: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ResponseData {
+        pub blockchain: QueryAccountStateAtBlockBlockchain,
+    }
+
+    #[derive(Deserialize)]
+    pub struct QueryAccountStateAtBlockBlockchain {
+        pub account: QueryAccountStateAtBlockAccount,
+    }
+
+    #[derive(Deserialize)]
+    pub struct QueryAccountStateAtBlockAccount {
+        pub info: Option<QueryAccountStateAtBlockInfo>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct QueryAccountStateAtBlockInfo {
+        pub boc: Option<String>,
+    }
+}
+
+pub mod query_account_transactions_around {
+    use super::*;
+
+    pub const QUERY: &str = "query($a:String!,$lt:String!){before:transactions(filter:{account_addr:{eq:$a},lt:{le:$lt}},orderBy:[{path:\"lt\",direction:DESC}],limit:1){block_id} after:transactions(filter:{account_addr:{eq:$a},lt:{gt:$lt}},orderBy:[{path:\"lt\",direction:ASC}],limit:1){block_id}}";
+
+    #[derive(Serialize)]
+    pub struct Variables {
+        #[serde(rename = "a")]
+        pub address: String,
+        pub lt: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ResponseData {
+        pub before: Vec<QueryAccountTransactionsAroundTransactions>,
+        pub after: Vec<QueryAccountTransactionsAroundTransactions>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct QueryAccountTransactionsAroundTransactions {
+        pub block_id: String,
+    }
+}
+
 pub mod query_account_transactions {
     use super::*;
 
@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
@@ -9,13 +10,16 @@ use nekoton_utils::*;
 use crate::core::models::{NetworkCapabilities, ReliableBehavior};
 use crate::external::{self, JrpcConnection};
 
-use super::models::{PollContractState, RawContractState, RawTransaction};
+use super::long_poll::long_poll_accounts;
+use super::models::{LatestBlock, PollContractState, RawContractState, RawTransaction};
+use super::proof::ContractStateWithProof;
 use super::utils::*;
-use super::{Transport, TransportInfo};
+use super::{AccountUpdatesStream, Transport, TransportError, TransportInfo};
 
 use self::models::*;
 
 mod models;
+pub mod server;
 
 pub struct JrpcTransport {
     connection: Arc<dyn JrpcConnection>,
@@ -39,7 +43,8 @@ impl Transport for JrpcTransport {
     fn info(&self) -> TransportInfo {
         TransportInfo {
             max_transactions_per_fetch: 50,
-            reliable_behavior: ReliableBehavior::IntensivePolling,
+            max_accounts_per_fetch: 50,
+            reliable_behavior: ReliableBehavior::BlockWalking,
             has_key_blocks: true,
         }
     }
@@ -49,7 +54,13 @@ impl Transport for JrpcTransport {
             data: make_jrpc_request("sendMessage", &SendMessage { message }),
             requires_db: false,
         };
-        self.connection.post(req).await.map(|_| ())
+        let response = self.connection.post(req).await?;
+        match serde_json::from_str::<SendMessageResponse>(&response) {
+            Ok(SendMessageResponse { error: Some(error) }) => {
+                Err(TransportError::MessageRejected(error.message).into())
+            }
+            _ => Ok(()),
+        }
     }
 
     async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
@@ -86,6 +97,66 @@ impl Transport for JrpcTransport {
         Ok(response)
     }
 
+    async fn get_contract_states(
+        &self,
+        addresses: &[MsgAddressInt],
+    ) -> Result<Vec<RawContractState>> {
+        if !self.connection.supports_batch() {
+            let mut result = Vec::with_capacity(addresses.len());
+            for address in addresses {
+                result.push(self.get_contract_state(address).await?);
+            }
+            return Ok(result);
+        }
+
+        let mut result = Vec::with_capacity(addresses.len());
+        for addresses in addresses.chunks(MAX_BATCH_SIZE) {
+            let known_states = addresses
+                .iter()
+                .map(|address| self.accounts_cache.get_account_state(address))
+                .collect::<Vec<_>>();
+
+            // Only poll states which are already known
+            let params = addresses
+                .iter()
+                .zip(&known_states)
+                .map(|(address, known_state)| GetContractState {
+                    address,
+                    last_transaction_lt: known_state
+                        .as_ref()
+                        .and_then(|state| state.last_known_trans_lt()),
+                })
+                .collect::<Vec<_>>();
+
+            let req = external::JrpcRequest {
+                data: make_jrpc_batch_request("getContractState", &params),
+                requires_db: false,
+            };
+            let data = self.connection.post(req).await?;
+            let responses = parse_jrpc_batch_response::<PollContractState>(&data, params.len())?;
+
+            for ((address, known_state), response) in
+                addresses.iter().zip(known_states).zip(responses)
+            {
+                let state = match (response.to_changed(), known_state) {
+                    (Ok(state), _) => {
+                        self.accounts_cache.update_account_state(address, &state);
+                        state
+                    }
+                    (Err(timings), Some(known_state)) => {
+                        let mut known_state = known_state.as_ref().clone();
+                        known_state.update_timings(timings);
+                        known_state
+                    }
+                    (Err(_), None) => return Err(JrpcClientError::InvalidResponse.into()),
+                };
+                result.push(state);
+            }
+        }
+
+        Ok(result)
+    }
+
     async fn poll_contract_state(
         &self,
         address: &MsgAddressInt,
@@ -110,6 +181,21 @@ impl Transport for JrpcTransport {
         Ok(response)
     }
 
+    async fn get_contract_state_with_proof(
+        &self,
+        address: &MsgAddressInt,
+    ) -> Result<ContractStateWithProof> {
+        let req = external::JrpcRequest {
+            data: make_jrpc_request(
+                "getContractStateWithProof",
+                &GetContractStateWithProof { address },
+            ),
+            requires_db: false,
+        };
+        let data = self.connection.post(req).await?;
+        tiny_jsonrpc::parse_response::<ContractStateWithProofResponse>(&data)?.parse()
+    }
+
     async fn get_accounts_by_code_hash(
         &self,
         code_hash: &ton_types::UInt256,
@@ -195,6 +281,67 @@ impl Transport for JrpcTransport {
             .map(|block: GetBlockResponse| block.block)
     }
 
+    async fn get_latest_block(&self, address: &MsgAddressInt) -> Result<LatestBlock> {
+        let req = external::JrpcRequest {
+            data: make_jrpc_request("getLatestBlock", &GetLatestBlock { address }),
+            requires_db: false,
+        };
+        let response = self.connection.post(req).await?;
+        tiny_jsonrpc::parse_response(&response)
+    }
+
+    async fn get_block(&self, id: &str) -> Result<Block> {
+        let req = external::JrpcRequest {
+            data: make_jrpc_request("getBlock", &GetBlock { id }),
+            requires_db: true,
+        };
+        self.connection
+            .post(req)
+            .await
+            .map(|data| tiny_jsonrpc::parse_response(&data))?
+            .map(|block: GetBlockResponse| block.block)
+    }
+
+    async fn get_block_by_seqno(&self, shard: &ton_block::ShardIdent, seqno: u32) -> Result<Block> {
+        let req = external::JrpcRequest {
+            data: make_jrpc_request(
+                "getBlockBySeqno",
+                &GetBlockBySeqno {
+                    workchain: shard.workchain_id(),
+                    shard: format!("{:016x}", shard.shard_prefix_with_tag()),
+                    seqno,
+                },
+            ),
+            requires_db: true,
+        };
+        self.connection
+            .post(req)
+            .await
+            .map(|data| tiny_jsonrpc::parse_response(&data))?
+            .map(|block: GetBlockResponse| block.block)
+    }
+
+    async fn wait_for_next_block(
+        &self,
+        current: &str,
+        address: &MsgAddressInt,
+        timeout: Duration,
+    ) -> Result<String> {
+        let req = external::JrpcRequest {
+            data: make_jrpc_request(
+                "waitForNextBlock",
+                &WaitForNextBlock {
+                    current,
+                    address,
+                    timeout_ms: timeout.as_millis() as u64,
+                },
+            ),
+            requires_db: false,
+        };
+        let response = self.connection.post(req).await?;
+        tiny_jsonrpc::parse_response(&response)
+    }
+
     async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
         let (capabilities, _) = self
             .config_cache
@@ -214,16 +361,81 @@ impl Transport for JrpcTransport {
             .await?;
         Ok(config)
     }
+
+    fn subscribe_accounts(&self, addresses: &[MsgAddressInt]) -> Option<AccountUpdatesStream> {
+        // NOTE: the stream must own its transport
+        let transport = JrpcTransport::new(self.connection.clone());
+        Some(long_poll_accounts(transport, addresses))
+    }
 }
 
 pub fn make_jrpc_request<S>(method: &str, params: &S) -> String
 where
     S: Serialize,
 {
-    serde_json::to_string(&JrpcRequest { method, params }).trust_me()
+    serde_json::to_string(&JrpcRequest {
+        id: 1,
+        method,
+        params,
+    })
+    .trust_me()
+}
+
+/// Builds a JSON-RPC batch with one request for each params item.
+///
+/// Request ids are equal to the params indices
+pub fn make_jrpc_batch_request<S>(method: &str, params: &[S]) -> String
+where
+    S: Serialize,
+{
+    serde_json::to_string(
+        &params
+            .iter()
+            .enumerate()
+            .map(|(id, params)| JrpcRequest {
+                id: id as u32,
+                method,
+                params,
+            })
+            .collect::<Vec<_>>(),
+    )
+    .trust_me()
+}
+
+/// Parses responses of the batch built with [`make_jrpc_batch_request`].
+///
+/// Responses are returned in the order of requests
+pub fn parse_jrpc_batch_response<T>(data: &str, count: usize) -> Result<Vec<T>>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    struct ResponseId {
+        id: usize,
+    }
+
+    let responses = serde_json::from_str::<Vec<serde_json::Value>>(data)?;
+    if responses.len() != count {
+        return Err(JrpcClientError::InvalidResponse.into());
+    }
+
+    let mut result = std::iter::repeat_with(|| None)
+        .take(count)
+        .collect::<Vec<Option<T>>>();
+    for response in responses {
+        let ResponseId { id } = serde_json::from_value(response.clone())?;
+        let item = result.get_mut(id).ok_or(JrpcClientError::InvalidResponse)?;
+        *item = Some(tiny_jsonrpc::parse_response(&response.to_string())?);
+    }
+
+    result
+        .into_iter()
+        .map(|item| item.ok_or_else(|| JrpcClientError::InvalidResponse.into()))
+        .collect()
 }
 
 pub struct JrpcRequest<'a, T> {
+    id: u32,
     method: &'a str,
     params: &'a T,
 }
@@ -240,7 +452,7 @@ where
 
         let mut ser = serializer.serialize_struct("JrpcRequest", 4)?;
         ser.serialize_field("jsonrpc", "2.0")?;
-        ser.serialize_field("id", &1)?;
+        ser.serialize_field("id", &self.id)?;
         ser.serialize_field("method", self.method)?;
         ser.serialize_field("params", self.params)?;
         ser.end()
@@ -255,11 +467,22 @@ fn decode_raw_transaction(boc: &str) -> Result<RawTransaction> {
     Ok(RawTransaction { hash, data })
 }
 
+const MAX_BATCH_SIZE: usize = 100;
+
+#[derive(thiserror::Error, Copy, Clone, Debug)]
+pub enum JrpcClientError {
+    #[error("Failed to parse response")]
+    InvalidResponse,
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
 
     use futures_util::StreamExt;
+    use parking_lot::Mutex;
+    use serde_json::json;
+    use ton_block::Serializable;
 
     use super::*;
 
@@ -310,6 +533,109 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn batch_request_roundtrip() -> Result<()> {
+        let request = make_jrpc_batch_request("getTransaction", &[1u32, 2]);
+        assert_eq!(
+            request,
+            r#"[{"jsonrpc":"2.0","id":0,"method":"getTransaction","params":1},{"jsonrpc":"2.0","id":1,"method":"getTransaction","params":2}]"#
+        );
+
+        let response =
+            r#"[{"jsonrpc":"2.0","id":1,"result":20},{"jsonrpc":"2.0","id":0,"result":10}]"#;
+        assert_eq!(parse_jrpc_batch_response::<u32>(response, 2)?, vec![10, 20]);
+        assert!(parse_jrpc_batch_response::<u32>(response, 3).is_err());
+
+        Ok(())
+    }
+
+    /// Returns the result for each method and records requests
+    struct MethodConnection {
+        results: Vec<(&'static str, serde_json::Value)>,
+        requests: Mutex<Vec<serde_json::Value>>,
+    }
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl JrpcConnection for MethodConnection {
+        async fn post(&self, req: external::JrpcRequest) -> Result<String> {
+            let request: serde_json::Value = serde_json::from_str(&req.data)?;
+            let method = request["method"].as_str().unwrap_or_default().to_owned();
+            self.requests.lock().push(request);
+
+            let (_, result) = self
+                .results
+                .iter()
+                .find(|(name, _)| *name == method)
+                .ok_or_else(|| anyhow::anyhow!("unexpected method: {method}"))?;
+            Ok(json!({ "jsonrpc": "2.0", "id": 1, "result": result }).to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn encodes_block_requests() -> Result<()> {
+        let address = MsgAddressInt::from_str(
+            "0:3333333333333333333333333333333333333333333333333333333333333333",
+        )?;
+        let block = Block::default();
+        let block_hash = block.serialize()?.repr_hash();
+        let block_result = serde_json::to_value(GetBlockResponse {
+            block: block.clone(),
+        })?;
+
+        let connection = Arc::new(MethodConnection {
+            results: vec![
+                (
+                    "getLatestBlock",
+                    json!({ "id": "aa", "endLt": "123", "genUtime": 456 }),
+                ),
+                ("getBlock", block_result.clone()),
+                ("getBlockBySeqno", block_result),
+                ("waitForNextBlock", json!("bb")),
+            ],
+            requests: Default::default(),
+        });
+        let transport = JrpcTransport::new(connection.clone());
+        assert_eq!(
+            transport.info().reliable_behavior,
+            ReliableBehavior::BlockWalking
+        );
+
+        let latest = transport.get_latest_block(&address).await?;
+        assert_eq!(latest.id, "aa");
+        assert_eq!(latest.end_lt, 123);
+        assert_eq!(latest.gen_utime, 456);
+
+        let block = transport.get_block("aa").await?;
+        assert_eq!(block.serialize()?.repr_hash(), block_hash);
+
+        let shard = ton_block::ShardIdent::with_tagged_prefix(0, 0x8000_0000_0000_0000)?;
+        let block = transport.get_block_by_seqno(&shard, 10).await?;
+        assert_eq!(block.serialize()?.repr_hash(), block_hash);
+
+        let next = transport
+            .wait_for_next_block("aa", &address, Duration::from_secs(5))
+            .await?;
+        assert_eq!(next, "bb");
+
+        let requests = connection.requests.lock();
+        let params = requests
+            .iter()
+            .map(|request| request["params"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            params,
+            [
+                json!({ "address": address.to_string() }),
+                json!({ "id": "aa" }),
+                json!({ "workchain": 0, "shard": "8000000000000000", "seqno": 10 }),
+                json!({ "current": "aa", "address": address.to_string(), "timeoutMs": 5000 }),
+            ]
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_connection() -> Result<()> {
         let transport = JrpcTransport::new(Arc::new(reqwest::Client::new()));
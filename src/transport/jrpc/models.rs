@@ -1,7 +1,14 @@
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use ton_block::{Deserializable, Serializable};
 
 use nekoton_utils::*;
 
+use crate::transport::models::RawContractState;
+use crate::transport::proof::{ContractStateProof, ContractStateWithProof};
+
+use super::server::JrpcError;
+
 #[derive(Serialize)]
 pub struct GetContractState<'a> {
     #[serde(with = "serde_address")]
@@ -11,6 +18,74 @@ pub struct GetContractState<'a> {
     pub last_transaction_lt: Option<u64>,
 }
 
+#[derive(Serialize)]
+pub struct GetContractStateWithProof<'a> {
+    #[serde(with = "serde_address")]
+    pub address: &'a ton_block::MsgAddressInt,
+}
+
+/// Account state with the base64 encoded BOCs of its proof
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContractStateWithProofResponse {
+    pub state: RawContractState,
+    pub key_block_proofs: Vec<String>,
+    pub mc_block_proof: String,
+    #[serde(default)]
+    pub shard_block_proof: Option<String>,
+    pub state_proof: String,
+}
+
+impl ContractStateWithProofResponse {
+    pub fn new(state: &ContractStateWithProof) -> Result<Self> {
+        let proof = &state.proof;
+        Ok(Self {
+            state: state.state.clone(),
+            key_block_proofs: proof
+                .key_block_proofs
+                .iter()
+                .map(|proof| proof.write_to_bytes().map(base64::encode))
+                .collect::<Result<_>>()?,
+            mc_block_proof: base64::encode(proof.mc_block_proof.write_to_bytes()?),
+            shard_block_proof: proof
+                .shard_block_proof
+                .as_ref()
+                .map(encode_cell)
+                .transpose()?,
+            state_proof: encode_cell(&proof.state_proof)?,
+        })
+    }
+
+    pub fn parse(self) -> Result<ContractStateWithProof> {
+        Ok(ContractStateWithProof {
+            state: self.state,
+            proof: ContractStateProof {
+                key_block_proofs: self
+                    .key_block_proofs
+                    .iter()
+                    .map(|boc| ton_block::BlockProof::construct_from_base64(boc))
+                    .collect::<Result<_>>()?,
+                mc_block_proof: ton_block::BlockProof::construct_from_base64(&self.mc_block_proof)?,
+                shard_block_proof: self
+                    .shard_block_proof
+                    .as_deref()
+                    .map(decode_cell)
+                    .transpose()?,
+                state_proof: decode_cell(&self.state_proof)?,
+            },
+        })
+    }
+}
+
+fn encode_cell(cell: &ton_types::Cell) -> Result<String> {
+    Ok(base64::encode(ton_types::serialize_toc(cell)?))
+}
+
+fn decode_cell(boc: &str) -> Result<ton_types::Cell> {
+    let bytes = base64::decode(boc)?;
+    ton_types::deserialize_tree_of_cells(&mut bytes.as_slice())
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GetAccountsByCodeHash<'a> {
@@ -29,6 +104,13 @@ pub struct SendMessage<'a> {
     pub message: &'a ton_block::Message,
 }
 
+/// Response to `sendMessage`, only the error is interesting
+#[derive(Deserialize)]
+pub struct SendMessageResponse {
+    #[serde(default)]
+    pub error: Option<JrpcError>,
+}
+
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct GetTransactions<'a> {
@@ -60,3 +142,34 @@ pub struct GetBlockResponse {
     #[serde(with = "serde_ton_block")]
     pub block: ton_block::Block,
 }
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetLatestBlock<'a> {
+    #[serde(with = "serde_address")]
+    pub address: &'a ton_block::MsgAddressInt,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetBlock<'a> {
+    pub id: &'a str,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetBlockBySeqno {
+    pub workchain: i32,
+    /// Hex encoded shard prefix with tag
+    pub shard: String,
+    pub seqno: u32,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WaitForNextBlock<'a> {
+    pub current: &'a str,
+    #[serde(with = "serde_address")]
+    pub address: &'a ton_block::MsgAddressInt,
+    pub timeout_ms: u64,
+}
@@ -0,0 +1,519 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use ton_block::{MsgAddressInt, Serializable};
+
+use nekoton_utils::*;
+
+use crate::external::{self, JrpcConnection};
+use crate::transport::models::RawTransaction;
+use crate::transport::Transport;
+
+use super::models::{ContractStateWithProofResponse, GetBlockResponse};
+
+/// Server side of the JSON-RPC methods used by [`JrpcTransport`](super::JrpcTransport).
+///
+/// Supports single requests and batches.
+///
+/// Also implements [`JrpcConnection`], so the backend can be used through
+/// [`JrpcTransport`](super::JrpcTransport) without a network.
+pub struct JrpcServer {
+    backend: Arc<dyn Transport>,
+}
+
+impl JrpcServer {
+    pub fn new(backend: Arc<dyn Transport>) -> Self {
+        Self { backend }
+    }
+
+    /// Handles the request (or batch) and returns the response (or batch)
+    pub async fn handle(&self, data: &str) -> String {
+        let request = match serde_json::from_str::<serde_json::Value>(data) {
+            Ok(request) => request,
+            Err(e) => {
+                return to_string(&JrpcResponse::error(
+                    serde_json::Value::Null,
+                    make_error(JrpcServerError::ParseError, e),
+                ))
+            }
+        };
+
+        match request {
+            serde_json::Value::Array(requests) if !requests.is_empty() => {
+                let mut responses = Vec::with_capacity(requests.len());
+                for request in requests {
+                    responses.push(self.handle_value(request).await);
+                }
+                to_string(&responses)
+            }
+            request => to_string(&self.handle_value(request).await),
+        }
+    }
+
+    async fn handle_value(&self, request: serde_json::Value) -> JrpcResponse {
+        let request = match serde_json::from_value::<JrpcRequest>(request) {
+            Ok(request) => request,
+            Err(e) => {
+                return JrpcResponse::error(
+                    serde_json::Value::Null,
+                    make_error(JrpcServerError::InvalidRequest, e),
+                )
+            }
+        };
+
+        match self.dispatch(&request.method, request.params).await {
+            Ok(result) => JrpcResponse::result(request.id, result),
+            Err(error) => JrpcResponse::error(request.id, error),
+        }
+    }
+
+    pub async fn dispatch(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, JrpcError> {
+        match method {
+            "sendMessage" => {
+                let params: SendMessage = parse_params(params)?;
+                self.backend
+                    .send_message(&params.message)
+                    .await
+                    .map_err(backend_error)?;
+                Ok(serde_json::Value::Null)
+            }
+            "getContractState" => {
+                let params: GetContractState = parse_params(params)?;
+                match params.last_transaction_lt {
+                    Some(lt) => to_value(
+                        self.backend
+                            .poll_contract_state(&params.address, lt)
+                            .await
+                            .map_err(backend_error)?,
+                    ),
+                    None => to_value(
+                        self.backend
+                            .get_contract_state(&params.address)
+                            .await
+                            .map_err(backend_error)?,
+                    ),
+                }
+            }
+            "getContractStateWithProof" => {
+                let params: GetContractStateWithProof = parse_params(params)?;
+                let state = self
+                    .backend
+                    .get_contract_state_with_proof(&params.address)
+                    .await
+                    .map_err(backend_error)?;
+                to_value(ContractStateWithProofResponse::new(&state).map_err(backend_error)?)
+            }
+            "getAccountsByCodeHash" => {
+                let params: GetAccountsByCodeHash = parse_params(params)?;
+                let accounts = self
+                    .backend
+                    .get_accounts_by_code_hash(
+                        &params.code_hash,
+                        params.limit.min(u8::MAX as u32) as u8,
+                        &params.continuation,
+                    )
+                    .await
+                    .map_err(backend_error)?;
+                to_value(accounts.iter().map(ToString::to_string).collect::<Vec<_>>())
+            }
+            "getTransactionsList" => {
+                let params: GetTransactions = parse_params(params)?;
+                let transactions = self
+                    .backend
+                    .get_transactions(
+                        &params.account,
+                        params.last_transaction_lt.unwrap_or(u64::MAX),
+                        params.limit.min(u8::MAX as u64) as u8,
+                    )
+                    .await
+                    .map_err(backend_error)?;
+                to_value(
+                    transactions
+                        .iter()
+                        .map(encode_transaction)
+                        .collect::<Result<Vec<_>, _>>()?,
+                )
+            }
+            "getTransaction" => {
+                let params: GetTransaction = parse_params(params)?;
+                let transaction = self
+                    .backend
+                    .get_transaction(&params.id)
+                    .await
+                    .map_err(backend_error)?;
+                to_value(transaction.as_ref().map(encode_transaction).transpose()?)
+            }
+            "getDstTransaction" => {
+                let params: GetDstTransaction = parse_params(params)?;
+                let transaction = self
+                    .backend
+                    .get_dst_transaction(&params.message_hash)
+                    .await
+                    .map_err(backend_error)?;
+                to_value(transaction.as_ref().map(encode_transaction).transpose()?)
+            }
+            "getLatestKeyBlock" => {
+                let block = self
+                    .backend
+                    .get_latest_key_block()
+                    .await
+                    .map_err(backend_error)?;
+                to_value(GetBlockResponse { block })
+            }
+            "getLatestBlock" => {
+                let params: GetLatestBlock = parse_params(params)?;
+                to_value(
+                    self.backend
+                        .get_latest_block(&params.address)
+                        .await
+                        .map_err(backend_error)?,
+                )
+            }
+            "getBlock" => {
+                let params: GetBlock = parse_params(params)?;
+                let block = self
+                    .backend
+                    .get_block(&params.id)
+                    .await
+                    .map_err(backend_error)?;
+                to_value(GetBlockResponse { block })
+            }
+            "getBlockBySeqno" => {
+                let params: GetBlockBySeqno = parse_params(params)?;
+                let shard = u64::from_str_radix(&params.shard, 16)
+                    .map_err(|e| make_error(JrpcServerError::InvalidParams, e))
+                    .and_then(|shard| {
+                        ton_block::ShardIdent::with_tagged_prefix(params.workchain, shard)
+                            .map_err(|e| make_error(JrpcServerError::InvalidParams, e))
+                    })?;
+                let block = self
+                    .backend
+                    .get_block_by_seqno(&shard, params.seqno)
+                    .await
+                    .map_err(backend_error)?;
+                to_value(GetBlockResponse { block })
+            }
+            "waitForNextBlock" => {
+                let params: WaitForNextBlock = parse_params(params)?;
+                to_value(
+                    self.backend
+                        .wait_for_next_block(
+                            &params.current,
+                            &params.address,
+                            Duration::from_millis(params.timeout_ms),
+                        )
+                        .await
+                        .map_err(backend_error)?,
+                )
+            }
+            _ => Err(make_error(
+                JrpcServerError::MethodNotFound,
+                format!("unknown method: {method}"),
+            )),
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl JrpcConnection for JrpcServer {
+    async fn post(&self, req: external::JrpcRequest) -> Result<String> {
+        Ok(self.handle(&req.data).await)
+    }
+
+    fn supports_batch(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Deserialize)]
+struct JrpcRequest {
+    #[serde(default)]
+    id: serde_json::Value,
+    method: String,
+    #[serde(default)]
+    params: serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct JrpcResponse {
+    jsonrpc: &'static str,
+    id: serde_json::Value,
+    #[serde(flatten)]
+    result: JrpcResult,
+}
+
+impl JrpcResponse {
+    fn result(id: serde_json::Value, result: serde_json::Value) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result: JrpcResult::Result(result),
+        }
+    }
+
+    fn error(id: serde_json::Value, error: JrpcError) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result: JrpcResult::Error(error),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum JrpcResult {
+    Result(serde_json::Value),
+    Error(JrpcError),
+}
+
+/// JSON-RPC error object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JrpcError {
+    pub code: i32,
+    pub message: String,
+}
+
+/// Error codes of [`JrpcError`]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(i32)]
+pub enum JrpcServerError {
+    ParseError = -32700,
+    InvalidRequest = -32600,
+    MethodNotFound = -32601,
+    InvalidParams = -32602,
+    Backend = -32000,
+}
+
+#[derive(Deserialize)]
+struct SendMessage {
+    #[serde(with = "serde_ton_block")]
+    message: ton_block::Message,
+}
+
+#[derive(Deserialize)]
+struct GetContractState {
+    #[serde(with = "serde_address")]
+    address: MsgAddressInt,
+    #[serde(default, with = "serde_optional_string", alias = "lastTransactionLt")]
+    last_transaction_lt: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct GetContractStateWithProof {
+    #[serde(with = "serde_address")]
+    address: MsgAddressInt,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetAccountsByCodeHash {
+    limit: u32,
+    #[serde(default, with = "serde_optional_address")]
+    continuation: Option<MsgAddressInt>,
+    #[serde(with = "serde_uint256")]
+    code_hash: ton_types::UInt256,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetTransactions {
+    limit: u64,
+    #[serde(default, with = "serde_optional_string")]
+    last_transaction_lt: Option<u64>,
+    #[serde(with = "serde_address")]
+    account: MsgAddressInt,
+}
+
+#[derive(Deserialize)]
+struct GetTransaction {
+    #[serde(with = "serde_uint256")]
+    id: ton_types::UInt256,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetDstTransaction {
+    #[serde(with = "serde_uint256")]
+    message_hash: ton_types::UInt256,
+}
+
+#[derive(Deserialize)]
+struct GetLatestBlock {
+    #[serde(with = "serde_address")]
+    address: MsgAddressInt,
+}
+
+#[derive(Deserialize)]
+struct GetBlock {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct GetBlockBySeqno {
+    workchain: i32,
+    shard: String,
+    seqno: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WaitForNextBlock {
+    current: String,
+    #[serde(with = "serde_address")]
+    address: MsgAddressInt,
+    timeout_ms: u64,
+}
+
+fn parse_params<T: DeserializeOwned>(params: serde_json::Value) -> Result<T, JrpcError> {
+    serde_json::from_value(params).map_err(|e| make_error(JrpcServerError::InvalidParams, e))
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<serde_json::Value, JrpcError> {
+    serde_json::to_value(value).map_err(backend_error)
+}
+
+fn to_string<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).trust_me()
+}
+
+fn encode_transaction(transaction: &RawTransaction) -> Result<String, JrpcError> {
+    transaction
+        .data
+        .write_to_bytes()
+        .map(base64::encode)
+        .map_err(backend_error)
+}
+
+fn backend_error<E: std::fmt::Display>(error: E) -> JrpcError {
+    make_error(JrpcServerError::Backend, error)
+}
+
+fn make_error<E: std::fmt::Display>(code: JrpcServerError, error: E) -> JrpcError {
+    JrpcError {
+        code: code as i32,
+        message: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "local_transport")]
+mod tests {
+    use ton_block::GetRepresentationHash;
+
+    use super::*;
+    use crate::transport::jrpc::JrpcTransport;
+    use crate::transport::local::tests::{dst_address, make_transfer};
+    use crate::transport::local::LocalTransport;
+    use crate::transport::models::RawContractState;
+    use crate::transport::proof::tests::{make_state_with_proof, ProofTransport};
+
+    #[tokio::test]
+    async fn jrpc_transport_works_through_server() -> Result<()> {
+        let clock = Arc::new(ConstClock::from_secs(1700000000));
+        let backend = Arc::new(LocalTransport::new(clock));
+        let transport = JrpcTransport::new(Arc::new(JrpcServer::new(backend)));
+
+        let dst = dst_address();
+        let message = make_transfer(1_000_000_000);
+        transport.send_message(&message).await?;
+
+        let contract = match transport.get_contract_state(&dst).await? {
+            RawContractState::Exists(contract) => contract,
+            RawContractState::NotExists { .. } => anyhow::bail!("account not found"),
+        };
+
+        let transactions = transport.get_transactions(&dst, u64::MAX, 10).await?;
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].data.lt, contract.last_transaction_id.lt());
+
+        let transaction = transport
+            .get_dst_transaction(&message.hash()?)
+            .await?
+            .expect("transaction not found");
+        assert_eq!(transaction.hash, transactions[0].hash);
+
+        // Known states are polled in a batch
+        let states = transport.get_contract_states(&[dst.clone(), dst]).await?;
+        assert!(states
+            .iter()
+            .all(|state| matches!(state, RawContractState::Exists(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn returns_jrpc_errors() {
+        let clock = Arc::new(ConstClock::from_secs(1700000000));
+        let server = JrpcServer::new(Arc::new(LocalTransport::new(clock)));
+
+        let response = server
+            .handle(r#"{"jsonrpc":"2.0","id":5,"method":"unknown","params":{}}"#)
+            .await;
+        let response: serde_json::Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response["id"], 5);
+        assert_eq!(
+            response["error"]["code"],
+            JrpcServerError::MethodNotFound as i32
+        );
+
+        let response = server
+            .handle(r#"{"jsonrpc":"2.0","id":6,"method":"getTransaction","params":{}}"#)
+            .await;
+        let response: serde_json::Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(
+            response["error"]["code"],
+            JrpcServerError::InvalidParams as i32
+        );
+    }
+
+    #[tokio::test]
+    async fn jrpc_transport_returns_state_proofs() -> Result<()> {
+        let (verifier, address, state) = make_state_with_proof()?;
+        let backend = Arc::new(ProofTransport(state));
+        let transport = JrpcTransport::new(Arc::new(JrpcServer::new(backend)));
+
+        let state = transport.get_contract_state_with_proof(&address).await?;
+        assert!(matches!(state.state, RawContractState::Exists(_)));
+        verifier.verify(&address, &state.state, &state.proof)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn jrpc_transport_long_polls_accounts() -> Result<()> {
+        use futures_util::StreamExt;
+
+        let clock = Arc::new(ConstClock::from_secs(1700000000));
+        let backend = Arc::new(LocalTransport::new(clock.clone()));
+        let transport = JrpcTransport::new(Arc::new(JrpcServer::new(backend.clone())));
+
+        let dst = dst_address();
+        let message = make_transfer(1_000_000_000);
+
+        let mut updates = transport
+            .subscribe_accounts(std::slice::from_ref(&dst))
+            .unwrap();
+
+        // NOTE: the stream is polled first, so it remembers the initial state
+        // and waits for the next block before the message is sent
+        let (update, sent) =
+            futures_util::future::join(updates.next(), backend.send_message(&message)).await;
+        sent?;
+
+        let update = update.unwrap()?;
+        assert_eq!(update.address, dst);
+        assert_eq!(update.transactions.len(), 1);
+        assert!(matches!(update.state, RawContractState::Exists(_)));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,804 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use tokio::sync::{mpsc, Notify};
+use ton_block::{Account, GetRepresentationHash, MsgAddressInt};
+use ton_types::UInt256;
+
+use nekoton_abi::{Executor, GenTimings, LastTransactionId, TransactionId};
+use nekoton_utils::*;
+
+use crate::core::models::{NetworkCapabilities, ReliableBehavior};
+
+use super::models::{
+    AccountUpdate, ContractStateAt, ExistingContract, LatestBlock, PollContractState,
+    RawContractState, RawTransaction,
+};
+use super::{AccountUpdatesStream, Transport, TransportError, TransportInfo};
+
+/// In-process transport which executes all messages with the local executor.
+///
+/// Every `send_message` call produces a new block with all transactions
+/// of the message tree, so the data returned by this transport is consistent
+/// with what a real node would return.
+pub struct LocalTransport {
+    clock: Arc<dyn Clock>,
+    config: ton_executor::BlockchainConfig,
+    disable_signature_check: bool,
+    state: Mutex<LocalChainState>,
+    block_produced: Notify,
+}
+
+impl LocalTransport {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self::with_config(clock, nekoton_abi::default_blockchain_config().clone())
+    }
+
+    pub fn with_config(clock: Arc<dyn Clock>, config: ton_executor::BlockchainConfig) -> Self {
+        let utime = clock.now_sec_u64() as u32;
+        Self {
+            clock,
+            config,
+            disable_signature_check: false,
+            state: Mutex::new(LocalChainState {
+                accounts: Default::default(),
+                transactions: Default::default(),
+                dst_transactions: Default::default(),
+                subscribers: Default::default(),
+                lt: INITIAL_LT,
+                seqno: 0,
+                utime,
+            }),
+            block_produced: Notify::new(),
+        }
+    }
+
+    pub fn disable_signature_check(&mut self) -> &mut Self {
+        self.disable_signature_check = true;
+        self
+    }
+
+    /// Inserts or replaces the account state
+    pub fn insert_account(&self, account: ton_block::AccountStuff) {
+        let mut state = self.state.lock();
+
+        let latest_lt = account.storage.last_trans_lt;
+        state.lt = std::cmp::max(state.lt, latest_lt + 1);
+
+        let address = account.addr.clone();
+        let account = Account::Account(account);
+        let last_transaction_id = LastTransactionId::Inexact { latest_lt };
+        let snapshot = LocalAccountSnapshot {
+            account: account.clone(),
+            last_transaction_id,
+            seqno: state.seqno,
+            timings: GenTimings::Known {
+                gen_lt: state.lt,
+                gen_utime: state.utime,
+            },
+        };
+
+        state.accounts.insert(
+            address,
+            LocalAccount {
+                account,
+                last_transaction_id,
+                transactions: Default::default(),
+                history: BTreeMap::from([(latest_lt, snapshot)]),
+            },
+        );
+    }
+
+    /// Removes the account with all its transactions
+    pub fn remove_account(&self, address: &MsgAddressInt) {
+        let mut state = self.state.lock();
+        if let Some(account) = state.accounts.remove(address) {
+            for hash in account.transactions.values() {
+                if let Some(tx) = state.transactions.remove(hash) {
+                    if let Some(in_msg) = &tx.data.in_msg {
+                        state.dst_transactions.remove(&in_msg.cell().repr_hash());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the seqno of the latest produced block
+    pub fn latest_block_seqno(&self) -> u32 {
+        self.state.lock().seqno
+    }
+
+    /// Executes the message tree and commits all transactions as a new block.
+    ///
+    /// Nothing is applied if the block could not be produced
+    fn produce_block(&self, message: &ton_block::Message) -> Result<()> {
+        let mut state = self.state.lock();
+
+        let mut block = LocalBlock {
+            seqno: state.seqno + 1,
+            utime: std::cmp::max(state.utime, self.clock.now_sec_u64() as u32),
+            lt: state.lt,
+            accounts: Default::default(),
+            transactions: Vec::new(),
+        };
+
+        let mut messages = VecDeque::from([message.clone()]);
+        let mut executed = 0;
+        while let Some(message) = messages.pop_front() {
+            if executed >= MAX_MESSAGES_PER_BLOCK {
+                return Err(LocalTransportError::TooManyMessages.into());
+            }
+            executed += 1;
+
+            let is_external = matches!(message.header(), ton_block::CommonMsgInfo::ExtInMsgInfo(_));
+            let tx = match state.execute(
+                &mut block,
+                &self.config,
+                self.disable_signature_check,
+                &message,
+            ) {
+                Ok(tx) => tx,
+                Err(e) if is_external => {
+                    return Err(TransportError::MessageRejected(e.to_string()).into())
+                }
+                Err(e) => {
+                    log::warn!("Failed to execute internal message: {e:?}");
+                    continue;
+                }
+            };
+
+            tx.data.iterate_out_msgs(|msg| {
+                if msg.is_internal() {
+                    messages.push_back(msg);
+                }
+                Ok(true)
+            })?;
+        }
+
+        let new_transactions = state.commit(block);
+        state.notify_subscribers(new_transactions);
+        drop(state);
+
+        self.block_produced.notify_waiters();
+
+        Ok(())
+    }
+}
+
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl Transport for LocalTransport {
+    fn info(&self) -> TransportInfo {
+        TransportInfo {
+            max_transactions_per_fetch: 50,
+            max_accounts_per_fetch: u8::MAX,
+            reliable_behavior: ReliableBehavior::IntensivePolling,
+            has_key_blocks: false,
+        }
+    }
+
+    async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
+        self.produce_block(message)
+    }
+
+    async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
+        Ok(self.state.lock().contract_state(address))
+    }
+
+    async fn poll_contract_state(
+        &self,
+        address: &MsgAddressInt,
+        last_trans_lt: u64,
+    ) -> Result<PollContractState> {
+        let state = self.get_contract_state(address).await?;
+        Ok(match state {
+            RawContractState::Exists(contract)
+                if contract.account.storage.last_trans_lt == last_trans_lt =>
+            {
+                PollContractState::Unchanged {
+                    timings: contract.timings,
+                }
+            }
+            state => PollContractState::from(state),
+        })
+    }
+
+    async fn get_contract_state_at(
+        &self,
+        address: &MsgAddressInt,
+        at: ContractStateAt,
+    ) -> Result<RawContractState> {
+        let state = self.state.lock();
+
+        let snapshot = state.accounts.get(address).and_then(|account| match at {
+            ContractStateAt::Lt(lt) => account
+                .history
+                .range(..=lt)
+                .next_back()
+                .map(|(_, snapshot)| snapshot),
+            ContractStateAt::BlockSeqno(seqno) => account
+                .history
+                .values()
+                .rev()
+                .find(|snapshot| snapshot.seqno <= seqno),
+        });
+
+        Ok(match snapshot {
+            Some(LocalAccountSnapshot {
+                account: Account::Account(account),
+                last_transaction_id,
+                timings,
+                ..
+            }) => RawContractState::Exists(ExistingContract {
+                account: account.clone(),
+                timings: *timings,
+                last_transaction_id: *last_transaction_id,
+            }),
+            Some(LocalAccountSnapshot { timings, .. }) => {
+                RawContractState::NotExists { timings: *timings }
+            }
+            None => RawContractState::NotExists {
+                timings: GenTimings::Unknown,
+            },
+        })
+    }
+
+    async fn get_accounts_by_code_hash(
+        &self,
+        code_hash: &UInt256,
+        limit: u8,
+        continuation: &Option<MsgAddressInt>,
+    ) -> Result<Vec<MsgAddressInt>> {
+        let state = self.state.lock();
+
+        let mut addresses = state
+            .accounts
+            .iter()
+            .filter(|(address, _)| match continuation {
+                Some(continuation) => *address > continuation,
+                None => true,
+            })
+            .filter(|(_, item)| item.code_hash().as_ref() == Some(code_hash))
+            .map(|(address, _)| address.clone())
+            .collect::<Vec<_>>();
+
+        addresses.sort();
+        addresses.truncate(limit as usize);
+        Ok(addresses)
+    }
+
+    async fn get_transactions(
+        &self,
+        address: &MsgAddressInt,
+        from_lt: u64,
+        count: u8,
+    ) -> Result<Vec<RawTransaction>> {
+        let state = self.state.lock();
+
+        let account = match state.accounts.get(address) {
+            Some(account) => account,
+            None => return Ok(Vec::new()),
+        };
+
+        Ok(account
+            .transactions
+            .range(..=from_lt)
+            .rev()
+            .take(count as usize)
+            .filter_map(|(_, hash)| state.transactions.get(hash).cloned())
+            .collect())
+    }
+
+    async fn get_transaction(&self, id: &UInt256) -> Result<Option<RawTransaction>> {
+        Ok(self.state.lock().transactions.get(id).cloned())
+    }
+
+    async fn get_dst_transaction(&self, message_hash: &UInt256) -> Result<Option<RawTransaction>> {
+        let state = self.state.lock();
+        Ok(state
+            .dst_transactions
+            .get(message_hash)
+            .and_then(|hash| state.transactions.get(hash))
+            .cloned())
+    }
+
+    async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+        Err(LocalTransportError::NoKeyBlocks.into())
+    }
+
+    /// NOTE: all accounts are in the same shard, block id is the hex encoded seqno
+    async fn get_latest_block(&self, _address: &MsgAddressInt) -> Result<LatestBlock> {
+        let state = self.state.lock();
+        Ok(LatestBlock {
+            id: make_block_id(state.seqno),
+            end_lt: state.lt,
+            gen_utime: state.utime,
+        })
+    }
+
+    /// NOTE: timeout is ignored because there is no timer
+    async fn wait_for_next_block(
+        &self,
+        current: &str,
+        _address: &MsgAddressInt,
+        _timeout: Duration,
+    ) -> Result<String> {
+        let current =
+            u32::from_str_radix(current, 16).map_err(|_| LocalTransportError::UnknownBlock)?;
+
+        loop {
+            // NOTE: future must be created before the check to not miss notifications
+            let notified = self.block_produced.notified();
+
+            let seqno = self.state.lock().seqno;
+            if current > seqno {
+                return Err(LocalTransportError::UnknownBlock.into());
+            } else if current < seqno {
+                return Ok(make_block_id(current + 1));
+            }
+
+            notified.await;
+        }
+    }
+
+    async fn get_capabilities(&self, _clock: &dyn Clock) -> Result<NetworkCapabilities> {
+        Ok(NetworkCapabilities {
+            global_id: self.config.global_id(),
+            raw: self.config.capabilites(),
+        })
+    }
+
+    async fn get_blockchain_config(
+        &self,
+        _clock: &dyn Clock,
+        _force: bool,
+    ) -> Result<ton_executor::BlockchainConfig> {
+        Ok(self.config.clone())
+    }
+
+    fn subscribe_accounts(&self, addresses: &[MsgAddressInt]) -> Option<AccountUpdatesStream> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.state.lock().subscribers.push(LocalSubscriber {
+            addresses: addresses.iter().cloned().collect(),
+            tx,
+        });
+
+        Some(Box::pin(futures_util::stream::unfold(
+            rx,
+            |mut rx| async move { rx.recv().await.map(|update| (Ok(update), rx)) },
+        )))
+    }
+}
+
+struct LocalChainState {
+    accounts: HashMap<MsgAddressInt, LocalAccount>,
+    transactions: HashMap<UInt256, RawTransaction>,
+    dst_transactions: HashMap<UInt256, UInt256>,
+    subscribers: Vec<LocalSubscriber>,
+    lt: u64,
+    seqno: u32,
+    utime: u32,
+}
+
+impl LocalChainState {
+    fn contract_state(&self, address: &MsgAddressInt) -> RawContractState {
+        let timings = GenTimings::Known {
+            gen_lt: self.lt,
+            gen_utime: self.utime,
+        };
+
+        match self.accounts.get(address) {
+            Some(LocalAccount {
+                account: Account::Account(account),
+                last_transaction_id,
+                ..
+            }) => RawContractState::Exists(ExistingContract {
+                account: account.clone(),
+                timings,
+                last_transaction_id: *last_transaction_id,
+            }),
+            _ => RawContractState::NotExists { timings },
+        }
+    }
+
+    fn notify_subscribers(&mut self, transactions: Vec<(MsgAddressInt, RawTransaction)>) {
+        // Remove subscribers which dropped their streams
+        self.subscribers
+            .retain(|subscriber| !subscriber.tx.is_closed());
+        if self.subscribers.is_empty() {
+            return;
+        }
+
+        let mut updates = Vec::<AccountUpdate>::new();
+        for (address, tx) in transactions {
+            match updates.iter_mut().find(|update| update.address == address) {
+                Some(update) => update.transactions.insert(0, tx),
+                None => updates.push(AccountUpdate {
+                    state: self.contract_state(&address),
+                    address,
+                    transactions: vec![tx],
+                }),
+            }
+        }
+
+        for update in updates {
+            for subscriber in &self.subscribers {
+                if subscriber.addresses.contains(&update.address) {
+                    subscriber.tx.send(update.clone()).ok();
+                }
+            }
+        }
+    }
+
+    /// Executes the message on top of the uncommitted block
+    fn execute(
+        &self,
+        block: &mut LocalBlock,
+        config: &ton_executor::BlockchainConfig,
+        disable_signature_check: bool,
+        message: &ton_block::Message,
+    ) -> Result<RawTransaction> {
+        let dst = message.dst().ok_or(LocalTransportError::InvalidMessage)?;
+
+        let account = match block.accounts.get(&dst) {
+            Some(account) => account.clone(),
+            None => match self.accounts.get(&dst) {
+                Some(item) => item.account.clone(),
+                None => Account::AccountNone,
+            },
+        };
+        let last_trans_lt = match &account {
+            Account::Account(account) => account.storage.last_trans_lt,
+            Account::AccountNone => 0,
+        };
+
+        let mut executor = Executor::with_params(
+            config.clone(),
+            account,
+            last_trans_lt,
+            block.utime,
+            block.lt,
+        );
+        if disable_signature_check {
+            executor.disable_signature_check();
+        }
+
+        let data = executor.run_mut(message)?;
+        let hash = data.hash()?;
+        let in_msg_hash = message.hash()?;
+
+        block.lt = std::cmp::max(block.lt, executor.last_transaction_lt()) + 1;
+
+        let account = executor.into_account();
+        block.accounts.insert(dst.clone(), account.clone());
+
+        let tx = RawTransaction { hash, data };
+        block.transactions.push(LocalBlockTransaction {
+            address: dst,
+            account,
+            in_msg_hash,
+            gen_lt: block.lt,
+            tx: tx.clone(),
+        });
+
+        Ok(tx)
+    }
+
+    /// Applies all transactions of the block
+    fn commit(&mut self, block: LocalBlock) -> Vec<(MsgAddressInt, RawTransaction)> {
+        self.seqno = block.seqno;
+        self.utime = block.utime;
+        self.lt = block.lt;
+
+        let mut new_transactions = Vec::with_capacity(block.transactions.len());
+        for item in block.transactions {
+            let tx_id = TransactionId {
+                lt: item.tx.data.lt,
+                hash: item.tx.hash,
+            };
+
+            let account = self
+                .accounts
+                .entry(item.address.clone())
+                .or_insert_with(|| LocalAccount {
+                    account: Account::AccountNone,
+                    last_transaction_id: LastTransactionId::Exact(tx_id),
+                    transactions: Default::default(),
+                    history: Default::default(),
+                });
+            account.account = item.account;
+            account.last_transaction_id = LastTransactionId::Exact(tx_id);
+            account.transactions.insert(tx_id.lt, tx_id.hash);
+            account.history.insert(
+                tx_id.lt,
+                LocalAccountSnapshot {
+                    account: account.account.clone(),
+                    last_transaction_id: account.last_transaction_id,
+                    seqno: block.seqno,
+                    timings: GenTimings::Known {
+                        gen_lt: item.gen_lt,
+                        gen_utime: block.utime,
+                    },
+                },
+            );
+
+            self.dst_transactions.insert(item.in_msg_hash, tx_id.hash);
+            self.transactions.insert(tx_id.hash, item.tx.clone());
+            new_transactions.push((item.address, item.tx));
+        }
+
+        new_transactions
+    }
+}
+
+/// Block which is being produced
+struct LocalBlock {
+    seqno: u32,
+    utime: u32,
+    lt: u64,
+    /// Account states after the block transactions
+    accounts: HashMap<MsgAddressInt, Account>,
+    transactions: Vec<LocalBlockTransaction>,
+}
+
+struct LocalBlockTransaction {
+    address: MsgAddressInt,
+    /// Account state after the transaction
+    account: Account,
+    in_msg_hash: UInt256,
+    gen_lt: u64,
+    tx: RawTransaction,
+}
+
+struct LocalSubscriber {
+    addresses: HashSet<MsgAddressInt>,
+    tx: mpsc::UnboundedSender<AccountUpdate>,
+}
+
+struct LocalAccount {
+    account: Account,
+    last_transaction_id: LastTransactionId,
+    transactions: BTreeMap<u64, UInt256>,
+    /// Account states after each transaction
+    history: BTreeMap<u64, LocalAccountSnapshot>,
+}
+
+struct LocalAccountSnapshot {
+    account: Account,
+    last_transaction_id: LastTransactionId,
+    seqno: u32,
+    timings: GenTimings,
+}
+
+impl LocalAccount {
+    fn code_hash(&self) -> Option<UInt256> {
+        match &self.account {
+            Account::Account(account) => match &account.storage.state {
+                ton_block::AccountState::AccountActive { state_init, .. } => {
+                    state_init.code.as_ref().map(ton_types::Cell::repr_hash)
+                }
+                _ => None,
+            },
+            Account::AccountNone => None,
+        }
+    }
+}
+
+fn make_block_id(seqno: u32) -> String {
+    format!("{seqno:064x}")
+}
+
+const INITIAL_LT: u64 = 1_000_000;
+const MAX_MESSAGES_PER_BLOCK: usize = 10_000;
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum LocalTransportError {
+    #[error("Invalid message")]
+    InvalidMessage,
+    #[error("Too many messages in block")]
+    TooManyMessages,
+    #[error("Local transport has no key blocks")]
+    NoKeyBlocks,
+    #[error("Unknown block")]
+    UnknownBlock,
+}
+
+impl LocalTransportError {
+    pub fn is_retryable(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::str::FromStr;
+
+    use ton_block::{CurrencyCollection, InternalMessageHeader};
+
+    use super::*;
+
+    /// Address `0:{byte}{byte}...{byte}`
+    pub(crate) fn test_address(byte: u8) -> MsgAddressInt {
+        MsgAddressInt::from_str(&format!("0:{}", format!("{byte:02x}").repeat(32))).unwrap()
+    }
+
+    /// Sender of test transfers, `0:1111...1111`
+    pub(crate) fn src_address() -> MsgAddressInt {
+        test_address(0x11)
+    }
+
+    /// Receiver of test transfers, `0:2222...2222`
+    pub(crate) fn dst_address() -> MsgAddressInt {
+        test_address(0x22)
+    }
+
+    /// Non-bounceable transfer from [`src_address`] to [`dst_address`].
+    ///
+    /// Creates the receiver account on the first delivery
+    pub(crate) fn make_transfer(amount: u64) -> ton_block::Message {
+        make_transfer_to(&dst_address(), amount)
+    }
+
+    /// Non-bounceable transfer from [`src_address`] to the specified address
+    pub(crate) fn make_transfer_to(dst: &MsgAddressInt, amount: u64) -> ton_block::Message {
+        let mut header = InternalMessageHeader::with_addresses(
+            src_address(),
+            dst.clone(),
+            CurrencyCollection::with_grams(amount),
+        );
+        header.bounce = false;
+        ton_block::Message::with_int_header(header)
+    }
+
+    #[tokio::test]
+    async fn local_transport_records_transactions() -> Result<()> {
+        let transport = LocalTransport::new(Arc::new(SimpleClock));
+
+        let dst = dst_address();
+
+        assert!(matches!(
+            transport.get_contract_state(&dst).await?,
+            RawContractState::NotExists { .. }
+        ));
+
+        let message = make_transfer(1_000_000_000);
+        transport.send_message(&message).await?;
+        assert_eq!(transport.latest_block_seqno(), 1);
+
+        let state = match transport.get_contract_state(&dst).await? {
+            RawContractState::Exists(state) => state,
+            RawContractState::NotExists { .. } => panic!("account must exist"),
+        };
+        let last_trans_lt = state.account.storage.last_trans_lt;
+
+        assert!(matches!(
+            transport.poll_contract_state(&dst, last_trans_lt).await?,
+            PollContractState::Unchanged { .. }
+        ));
+
+        let transactions = transport.get_transactions(&dst, u64::MAX, 10).await?;
+        assert_eq!(transactions.len(), 1);
+
+        let dst_transaction = transport
+            .get_dst_transaction(&message.hash()?)
+            .await?
+            .unwrap();
+        assert_eq!(dst_transaction, transactions[0]);
+
+        let transaction = transport
+            .get_transaction(&transactions[0].hash)
+            .await?
+            .unwrap();
+        assert_eq!(transaction.data.lt, transactions[0].data.lt);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn local_transport_discards_rejected_blocks() -> Result<()> {
+        use ton_block::ExternalInboundMessageHeader;
+
+        let transport = LocalTransport::new(Arc::new(SimpleClock));
+
+        transport
+            .send_message(&make_transfer(1_000_000_000))
+            .await?;
+        let block = transport.get_latest_block(&dst_address()).await?;
+
+        // External message to an account without code can't be accepted
+        let message = ton_block::Message::with_ext_in_header(ExternalInboundMessageHeader {
+            dst: test_address(0x33),
+            ..Default::default()
+        });
+        let error = transport.send_message(&message).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<TransportError>(),
+            Some(TransportError::MessageRejected(_))
+        ));
+
+        assert_eq!(transport.latest_block_seqno(), 1);
+        let latest = transport.get_latest_block(&dst_address()).await?;
+        assert_eq!(latest.id, block.id);
+        assert_eq!(latest.end_lt, block.end_lt);
+        assert!(matches!(
+            transport.get_contract_state(&test_address(0x33)).await?,
+            RawContractState::NotExists { .. }
+        ));
+        assert!(transport
+            .get_dst_transaction(&message.hash()?)
+            .await?
+            .is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn local_transport_keeps_state_history() -> Result<()> {
+        let transport = LocalTransport::new(Arc::new(SimpleClock));
+
+        let dst = dst_address();
+
+        transport
+            .send_message(&make_transfer(1_000_000_000))
+            .await?;
+        transport
+            .send_message(&make_transfer(2_000_000_000))
+            .await?;
+
+        let transactions = transport.get_transactions(&dst, u64::MAX, 10).await?;
+        assert_eq!(transactions.len(), 2);
+
+        let first = transport
+            .get_contract_state_at(&dst, ContractStateAt::Lt(transactions[1].data.lt))
+            .await?
+            .brief();
+        let latest = transport.get_contract_state(&dst).await?.brief();
+        assert!(first.balance < latest.balance);
+
+        let by_seqno = transport
+            .get_contract_state_at(&dst, ContractStateAt::BlockSeqno(1))
+            .await?
+            .brief();
+        assert_eq!(by_seqno.balance, first.balance);
+
+        assert!(matches!(
+            transport
+                .get_contract_state_at(&dst, ContractStateAt::Lt(transactions[1].data.lt - 1))
+                .await?,
+            RawContractState::NotExists { .. }
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn local_transport_pushes_account_updates() -> Result<()> {
+        use futures_util::StreamExt;
+
+        let transport = LocalTransport::new(Arc::new(SimpleClock));
+
+        let dst = dst_address();
+        let other = test_address(0x33);
+
+        let mut updates = transport
+            .subscribe_accounts(std::slice::from_ref(&dst))
+            .unwrap();
+
+        transport
+            .send_message(&make_transfer_to(&other, 1_000_000_000))
+            .await?;
+        transport
+            .send_message(&make_transfer(1_000_000_000))
+            .await?;
+
+        let update = updates.next().await.unwrap()?;
+        assert_eq!(update.address, dst);
+        assert_eq!(update.transactions.len(), 1);
+        assert!(matches!(update.state, RawContractState::Exists(_)));
+
+        Ok(())
+    }
+}
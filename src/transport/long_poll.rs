@@ -0,0 +1,356 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use ton_block::MsgAddressInt;
+
+use nekoton_utils::*;
+
+use super::models::{AccountUpdate, PollContractState, RawTransaction};
+use super::{AccountUpdatesStream, Transport};
+
+/// Long polling implementation of [`Transport::subscribe_accounts`].
+///
+/// Accounts are grouped by their shards. Each shard waits for its next block
+/// separately, and then states of the accounts in this shard are polled.
+/// New transactions are loaded for each changed account.
+pub fn long_poll_accounts<T>(transport: T, addresses: &[MsgAddressInt]) -> AccountUpdatesStream
+where
+    T: Transport + 'static,
+{
+    let state = LongPollState {
+        transport: Arc::new(transport),
+        accounts: addresses
+            .iter()
+            .map(|address| (address.clone(), None))
+            .collect(),
+        shards: Vec::new(),
+        next_blocks: FuturesUnordered::new(),
+        pending: Default::default(),
+    };
+
+    Box::pin(futures_util::stream::unfold(
+        state,
+        |mut state| async move {
+            loop {
+                if let Some(update) = state.pending.pop_front() {
+                    return Some((Ok(update), state));
+                }
+
+                if let Err(e) = state.poll_next_block().await? {
+                    // Start from the latest blocks after errors
+                    state.shards.clear();
+                    state.next_blocks.clear();
+                    return Some((Err(e), state));
+                }
+            }
+        },
+    ))
+}
+
+struct LongPollState<T> {
+    transport: Arc<T>,
+    /// Addresses with the last known `last_trans_lt`
+    accounts: Vec<(MsgAddressInt, Option<u64>)>,
+    /// Accounts grouped by shards
+    shards: Vec<ShardAccounts>,
+    /// Pending requests of the next block for each shard
+    next_blocks: FuturesUnordered<NextBlockFuture>,
+    pending: VecDeque<AccountUpdate>,
+}
+
+struct ShardAccounts {
+    /// Any account of the shard, used to identify it in requests
+    address: MsgAddressInt,
+    /// Indices of the accounts in this shard
+    accounts: Vec<usize>,
+}
+
+impl<T: Transport + 'static> LongPollState<T> {
+    /// Returns `None` if there are no accounts to poll
+    async fn poll_next_block(&mut self) -> Option<Result<()>> {
+        if self.accounts.is_empty() {
+            return None;
+        }
+
+        Some(if self.shards.is_empty() {
+            self.init_shards().await
+        } else {
+            self.poll_shard().await
+        })
+    }
+
+    /// Groups accounts by the latest blocks of their shards
+    async fn init_shards(&mut self) -> Result<()> {
+        let mut latest_blocks = Vec::<String>::new();
+        for (i, (address, _)) in self.accounts.iter().enumerate() {
+            // NOTE: a shard could be split into two groups if a new block
+            // was produced between requests, which only causes extra polling
+            let latest_block = self.transport.get_latest_block(address).await?.id;
+            match latest_blocks.iter().position(|id| *id == latest_block) {
+                Some(shard) => self.shards[shard].accounts.push(i),
+                None => {
+                    latest_blocks.push(latest_block);
+                    self.shards.push(ShardAccounts {
+                        address: address.clone(),
+                        accounts: vec![i],
+                    });
+                }
+            }
+        }
+
+        for (shard, latest_block) in latest_blocks.into_iter().enumerate() {
+            self.wait_for_next_block(shard, latest_block);
+        }
+
+        let accounts = (0..self.accounts.len()).collect::<Vec<_>>();
+        self.poll_accounts(&accounts).await
+    }
+
+    /// Waits for the next block in any shard and polls its accounts
+    async fn poll_shard(&mut self) -> Result<()> {
+        let (shard, next_block) = match self.next_blocks.next().await {
+            Some(result) => result,
+            None => {
+                // NOTE: there is always one request for each shard
+                self.shards.clear();
+                return Ok(());
+            }
+        };
+        self.wait_for_next_block(shard, next_block?);
+
+        let accounts = self.shards[shard].accounts.clone();
+        self.poll_accounts(&accounts).await
+    }
+
+    fn wait_for_next_block(&mut self, shard: usize, current: String) {
+        let transport = self.transport.clone();
+        let address = self.shards[shard].address.clone();
+        self.next_blocks.push(Box::pin(async move {
+            let next_block = transport
+                .wait_for_next_block(&current, &address, LONG_POLL_TIMEOUT)
+                .await;
+            (shard, next_block)
+        }));
+    }
+
+    async fn poll_accounts(&mut self, accounts: &[usize]) -> Result<()> {
+        for &i in accounts {
+            let (address, known_lt) = &mut self.accounts[i];
+            let state = match known_lt {
+                Some(lt) => match self.transport.poll_contract_state(address, *lt).await? {
+                    PollContractState::Unchanged { .. } => continue,
+                    state => state.to_changed().trust_me(),
+                },
+                // Only remember the initial state
+                None => {
+                    let state = self.transport.get_contract_state(address).await?;
+                    *known_lt = Some(state.brief().last_lt);
+                    continue;
+                }
+            };
+
+            let last_lt = state.brief().last_lt;
+            let prev_lt = known_lt.unwrap_or_default();
+            if last_lt == prev_lt {
+                continue;
+            }
+
+            let transactions =
+                load_new_transactions(self.transport.as_ref(), address, last_lt, prev_lt).await?;
+            *known_lt = Some(last_lt);
+
+            self.pending.push_back(AccountUpdate {
+                address: address.clone(),
+                state,
+                transactions,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "non_threadsafe"))]
+type NextBlockFuture = futures_util::future::BoxFuture<'static, (usize, Result<String>)>;
+#[cfg(feature = "non_threadsafe")]
+type NextBlockFuture = futures_util::future::LocalBoxFuture<'static, (usize, Result<String>)>;
+
+/// Loads all transactions since `known_lt` in descending order
+async fn load_new_transactions<T: Transport>(
+    transport: &T,
+    address: &MsgAddressInt,
+    mut from_lt: u64,
+    known_lt: u64,
+) -> Result<Vec<RawTransaction>> {
+    let count = transport.info().max_transactions_per_fetch;
+
+    let mut result = Vec::new();
+    loop {
+        let transactions = transport.get_transactions(address, from_lt, count).await?;
+        let len = transactions.len();
+        let prev_lt = match transactions.last() {
+            Some(last) => last.data.prev_trans_lt,
+            None => break,
+        };
+
+        result.extend(
+            transactions
+                .into_iter()
+                .filter(|transaction| transaction.data.lt >= known_lt),
+        );
+
+        if len < count as usize || prev_lt == 0 || prev_lt < known_lt {
+            break;
+        }
+        from_lt = prev_lt;
+    }
+
+    Ok(result)
+}
+
+const LONG_POLL_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[cfg(test)]
+#[cfg(feature = "local_transport")]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::core::models::NetworkCapabilities;
+    use crate::transport::local::tests::{dst_address, make_transfer};
+    use crate::transport::local::LocalTransport;
+    use crate::transport::models::{LatestBlock, RawContractState};
+    use crate::transport::TransportInfo;
+
+    /// Local transport with an additional masterchain shard which never produces blocks
+    struct ShardedTransport(Arc<LocalTransport>);
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl Transport for ShardedTransport {
+        fn info(&self) -> TransportInfo {
+            self.0.info()
+        }
+
+        async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
+            self.0.send_message(message).await
+        }
+
+        async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
+            self.0.get_contract_state(address).await
+        }
+
+        async fn poll_contract_state(
+            &self,
+            address: &MsgAddressInt,
+            last_trans_lt: u64,
+        ) -> Result<PollContractState> {
+            self.0.poll_contract_state(address, last_trans_lt).await
+        }
+
+        async fn get_accounts_by_code_hash(
+            &self,
+            code_hash: &ton_types::UInt256,
+            limit: u8,
+            continuation: &Option<MsgAddressInt>,
+        ) -> Result<Vec<MsgAddressInt>> {
+            self.0
+                .get_accounts_by_code_hash(code_hash, limit, continuation)
+                .await
+        }
+
+        async fn get_transactions(
+            &self,
+            address: &MsgAddressInt,
+            from_lt: u64,
+            count: u8,
+        ) -> Result<Vec<RawTransaction>> {
+            self.0.get_transactions(address, from_lt, count).await
+        }
+
+        async fn get_transaction(&self, id: &ton_types::UInt256) -> Result<Option<RawTransaction>> {
+            self.0.get_transaction(id).await
+        }
+
+        async fn get_dst_transaction(
+            &self,
+            message_hash: &ton_types::UInt256,
+        ) -> Result<Option<RawTransaction>> {
+            self.0.get_dst_transaction(message_hash).await
+        }
+
+        async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+            self.0.get_latest_key_block().await
+        }
+
+        async fn get_latest_block(&self, address: &MsgAddressInt) -> Result<LatestBlock> {
+            if address.is_masterchain() {
+                Ok(LatestBlock {
+                    id: "masterchain".to_owned(),
+                    end_lt: 0,
+                    gen_utime: 0,
+                })
+            } else {
+                self.0.get_latest_block(address).await
+            }
+        }
+
+        async fn wait_for_next_block(
+            &self,
+            current: &str,
+            address: &MsgAddressInt,
+            timeout: Duration,
+        ) -> Result<String> {
+            if address.is_masterchain() {
+                futures_util::future::pending().await
+            } else {
+                self.0.wait_for_next_block(current, address, timeout).await
+            }
+        }
+
+        async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
+            self.0.get_capabilities(clock).await
+        }
+
+        async fn get_blockchain_config(
+            &self,
+            clock: &dyn Clock,
+            force: bool,
+        ) -> Result<ton_executor::BlockchainConfig> {
+            self.0.get_blockchain_config(clock, force).await
+        }
+    }
+
+    #[tokio::test]
+    async fn waits_for_blocks_in_all_shards() -> Result<()> {
+        let local = Arc::new(LocalTransport::new(Arc::new(SimpleClock)));
+
+        let mc_address = MsgAddressInt::from_str(
+            "-1:3333333333333333333333333333333333333333333333333333333333333333",
+        )?;
+        let dst = dst_address();
+
+        // The first account is in the shard without new blocks
+        let mut updates =
+            long_poll_accounts(ShardedTransport(local.clone()), &[mc_address, dst.clone()]);
+
+        // NOTE: local transport responds immediately, so the stream starts
+        // waiting for the next blocks before the message is sent
+        let (update, sent) = tokio::join!(
+            updates.next(),
+            local.send_message(&make_transfer(1_000_000_000))
+        );
+
+        sent?;
+
+        let update = update.unwrap()?;
+        assert_eq!(update.address, dst);
+        assert_eq!(update.transactions.len(), 1);
+        assert!(matches!(update.state, RawContractState::Exists(_)));
+
+        Ok(())
+    }
+}
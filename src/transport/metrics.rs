@@ -0,0 +1,682 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use ton_block::MsgAddressInt;
+
+use nekoton_utils::*;
+
+use crate::core::models::NetworkCapabilities;
+
+use super::models::{
+    ContractStateAt, LatestBlock, PollContractState, RawContractState, RawTransaction,
+};
+use super::proof::ContractStateWithProof;
+use super::retry::TransportMethod;
+use super::{AccountUpdatesStream, Transport, TransportInfo};
+
+/// Receives measurements of transport calls and connection requests
+pub trait TransportMetrics: Send + Sync {
+    /// Called after each call of [`InstrumentedTransport`]
+    fn record_call(&self, call: &CallRecord<'_>);
+
+    /// Called after each request of [`InstrumentedConnection`]
+    fn record_request(&self, request: &RequestRecord<'_>) {
+        let _ = request;
+    }
+}
+
+/// Finished transport call
+#[derive(Debug)]
+pub struct CallRecord<'a> {
+    pub method: TransportMethod,
+    /// Account related to the call, if any
+    pub address: Option<&'a MsgAddressInt>,
+    /// Logical time related to the call, if any
+    pub lt: Option<u64>,
+    pub duration: Duration,
+    pub error: Option<&'a anyhow::Error>,
+}
+
+/// Finished connection request
+#[derive(Debug)]
+pub struct RequestRecord<'a> {
+    /// Name of the instrumented connection (e.g. endpoint)
+    pub connection: &'a str,
+    /// Method of the [`InstrumentedTransport`] call which made the request, if any
+    pub method: Option<TransportMethod>,
+    pub request_size: usize,
+    /// Response size, `None` if the request failed
+    pub response_size: Option<usize>,
+    pub duration: Duration,
+    pub error: Option<&'a anyhow::Error>,
+}
+
+/// Transport which reports metrics of the underlying transport calls.
+///
+/// Each call is wrapped into a `transport` span with `method`, `address`
+/// and `lt` fields when the `tracing` feature is enabled.
+pub struct InstrumentedTransport<T> {
+    transport: T,
+    metrics: Arc<dyn TransportMetrics>,
+}
+
+impl<T: Transport> InstrumentedTransport<T> {
+    pub fn new(transport: T, metrics: Arc<dyn TransportMetrics>) -> Self {
+        Self { transport, metrics }
+    }
+
+    /// Returns the underlying transport
+    pub fn inner(&self) -> &T {
+        &self.transport
+    }
+
+    async fn call<'a, R, F, Fut>(
+        &'a self,
+        method: TransportMethod,
+        address: Option<&MsgAddressInt>,
+        lt: Option<u64>,
+        f: F,
+    ) -> Result<R>
+    where
+        F: FnOnce(&'a T) -> Fut,
+        Fut: Future<Output = Result<R>>,
+    {
+        let started_at = now_ms_u64();
+
+        let fut = f(&self.transport);
+        #[cfg(feature = "tracing")]
+        let fut = {
+            use tracing::Instrument;
+
+            let span = tracing::debug_span!(
+                "transport",
+                method = ?method,
+                address = tracing::field::Empty,
+                lt = tracing::field::Empty,
+            );
+            if let Some(address) = address {
+                span.record("address", &tracing::field::display(address));
+            }
+            if let Some(lt) = lt {
+                span.record("lt", &lt);
+            }
+            fut.instrument(span)
+        };
+
+        // NOTE: requests are attributed to the method while the call is polled
+        futures_util::pin_mut!(fut);
+        let result =
+            futures_util::future::poll_fn(|cx| with_method(method, || fut.as_mut().poll(cx))).await;
+        let duration = Duration::from_millis(now_ms_u64().saturating_sub(started_at));
+
+        if let Err(e) = &result {
+            log::debug!("Transport call {method:?} failed after {duration:?}: {e:?}");
+        }
+
+        self.metrics.record_call(&CallRecord {
+            method,
+            address,
+            lt,
+            duration,
+            error: result.as_ref().err(),
+        });
+
+        result
+    }
+}
+
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl<T: Transport> Transport for InstrumentedTransport<T> {
+    fn info(&self) -> TransportInfo {
+        self.transport.info()
+    }
+
+    async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
+        let dst = message.dst();
+        self.call(
+            TransportMethod::SendMessage,
+            dst.as_ref(),
+            None,
+            |transport| transport.send_message(message),
+        )
+        .await
+    }
+
+    async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
+        self.call(
+            TransportMethod::GetContractState,
+            Some(address),
+            None,
+            |transport| transport.get_contract_state(address),
+        )
+        .await
+    }
+
+    async fn get_contract_states(
+        &self,
+        addresses: &[MsgAddressInt],
+    ) -> Result<Vec<RawContractState>> {
+        self.call(
+            TransportMethod::GetContractStates,
+            None,
+            None,
+            |transport| transport.get_contract_states(addresses),
+        )
+        .await
+    }
+
+    async fn poll_contract_state(
+        &self,
+        address: &MsgAddressInt,
+        last_trans_lt: u64,
+    ) -> Result<PollContractState> {
+        self.call(
+            TransportMethod::PollContractState,
+            Some(address),
+            Some(last_trans_lt),
+            |transport| transport.poll_contract_state(address, last_trans_lt),
+        )
+        .await
+    }
+
+    async fn get_contract_state_at(
+        &self,
+        address: &MsgAddressInt,
+        at: ContractStateAt,
+    ) -> Result<RawContractState> {
+        let lt = match at {
+            ContractStateAt::Lt(lt) => Some(lt),
+            ContractStateAt::BlockSeqno(_) => None,
+        };
+        self.call(
+            TransportMethod::GetContractStateAt,
+            Some(address),
+            lt,
+            |transport| transport.get_contract_state_at(address, at),
+        )
+        .await
+    }
+
+    async fn get_contract_state_with_proof(
+        &self,
+        address: &MsgAddressInt,
+    ) -> Result<ContractStateWithProof> {
+        self.call(
+            TransportMethod::GetContractStateWithProof,
+            Some(address),
+            None,
+            |transport| transport.get_contract_state_with_proof(address),
+        )
+        .await
+    }
+
+    async fn get_accounts_by_code_hash(
+        &self,
+        code_hash: &ton_types::UInt256,
+        limit: u8,
+        continuation: &Option<MsgAddressInt>,
+    ) -> Result<Vec<MsgAddressInt>> {
+        self.call(
+            TransportMethod::GetAccountsByCodeHash,
+            continuation.as_ref(),
+            None,
+            |transport| transport.get_accounts_by_code_hash(code_hash, limit, continuation),
+        )
+        .await
+    }
+
+    async fn get_transactions(
+        &self,
+        address: &MsgAddressInt,
+        from_lt: u64,
+        count: u8,
+    ) -> Result<Vec<RawTransaction>> {
+        self.call(
+            TransportMethod::GetTransactions,
+            Some(address),
+            (from_lt != u64::MAX).then_some(from_lt),
+            |transport| transport.get_transactions(address, from_lt, count),
+        )
+        .await
+    }
+
+    async fn get_transaction(&self, id: &ton_types::UInt256) -> Result<Option<RawTransaction>> {
+        self.call(TransportMethod::GetTransaction, None, None, |transport| {
+            transport.get_transaction(id)
+        })
+        .await
+    }
+
+    async fn get_dst_transaction(
+        &self,
+        message_hash: &ton_types::UInt256,
+    ) -> Result<Option<RawTransaction>> {
+        self.call(
+            TransportMethod::GetDstTransaction,
+            None,
+            None,
+            |transport| transport.get_dst_transaction(message_hash),
+        )
+        .await
+    }
+
+    async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+        self.call(
+            TransportMethod::GetLatestKeyBlock,
+            None,
+            None,
+            |transport| transport.get_latest_key_block(),
+        )
+        .await
+    }
+
+    async fn get_latest_block(&self, address: &MsgAddressInt) -> Result<LatestBlock> {
+        self.call(
+            TransportMethod::GetLatestBlock,
+            Some(address),
+            None,
+            |transport| transport.get_latest_block(address),
+        )
+        .await
+    }
+
+    async fn get_block(&self, id: &str) -> Result<ton_block::Block> {
+        self.call(TransportMethod::GetBlock, None, None, |transport| {
+            transport.get_block(id)
+        })
+        .await
+    }
+
+    async fn get_block_by_seqno(
+        &self,
+        shard: &ton_block::ShardIdent,
+        seqno: u32,
+    ) -> Result<ton_block::Block> {
+        self.call(TransportMethod::GetBlockBySeqno, None, None, |transport| {
+            transport.get_block_by_seqno(shard, seqno)
+        })
+        .await
+    }
+
+    async fn wait_for_next_block(
+        &self,
+        current: &str,
+        address: &MsgAddressInt,
+        timeout: Duration,
+    ) -> Result<String> {
+        self.call(
+            TransportMethod::WaitForNextBlock,
+            Some(address),
+            None,
+            |transport| transport.wait_for_next_block(current, address, timeout),
+        )
+        .await
+    }
+
+    async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
+        self.call(TransportMethod::GetCapabilities, None, None, |transport| {
+            transport.get_capabilities(clock)
+        })
+        .await
+    }
+
+    async fn get_blockchain_config(
+        &self,
+        clock: &dyn Clock,
+        force: bool,
+    ) -> Result<ton_executor::BlockchainConfig> {
+        self.call(
+            TransportMethod::GetBlockchainConfig,
+            None,
+            None,
+            |transport| transport.get_blockchain_config(clock, force),
+        )
+        .await
+    }
+
+    fn subscribe_accounts(&self, addresses: &[MsgAddressInt]) -> Option<AccountUpdatesStream> {
+        self.transport.subscribe_accounts(addresses)
+    }
+}
+
+/// Connection which reports sizes and latencies of the underlying connection requests.
+///
+/// Implements the same connection traits as the wrapped connection.
+pub struct InstrumentedConnection<C> {
+    name: String,
+    connection: C,
+    metrics: Arc<dyn TransportMetrics>,
+}
+
+impl<C> InstrumentedConnection<C> {
+    /// `name` is used to distinguish connections in metrics (e.g. an endpoint URL)
+    pub fn new(name: impl Into<String>, connection: C, metrics: Arc<dyn TransportMetrics>) -> Self {
+        Self {
+            name: name.into(),
+            connection,
+            metrics,
+        }
+    }
+
+    /// Returns the underlying connection
+    pub fn inner(&self) -> &C {
+        &self.connection
+    }
+
+    #[cfg(any(
+        feature = "gql_transport",
+        feature = "jrpc_transport",
+        feature = "proto_transport",
+    ))]
+    async fn request<R, Fut>(&self, request_size: usize, fut: Fut) -> Result<R>
+    where
+        R: AsRef<[u8]>,
+        Fut: Future<Output = Result<R>>,
+    {
+        let method = CURRENT_METHOD.with(Cell::get);
+        let started_at = now_ms_u64();
+
+        #[cfg(feature = "tracing")]
+        let fut = {
+            use tracing::Instrument;
+
+            fut.instrument(tracing::debug_span!(
+                "connection",
+                name = %self.name,
+                request_size,
+            ))
+        };
+
+        let result = fut.await;
+        let duration = Duration::from_millis(now_ms_u64().saturating_sub(started_at));
+
+        self.metrics.record_request(&RequestRecord {
+            connection: &self.name,
+            method,
+            request_size,
+            response_size: result.as_ref().ok().map(|data| data.as_ref().len()),
+            duration,
+            error: result.as_ref().err(),
+        });
+
+        result
+    }
+}
+
+thread_local! {
+    /// Method of the [`InstrumentedTransport`] call which is being polled
+    static CURRENT_METHOD: Cell<Option<TransportMethod>> = Cell::new(None);
+}
+
+fn with_method<F, R>(method: TransportMethod, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    struct Guard(Option<TransportMethod>);
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            CURRENT_METHOD.with(|current| current.set(self.0));
+        }
+    }
+
+    let _guard = Guard(CURRENT_METHOD.with(|current| current.replace(Some(method))));
+    f()
+}
+
+#[cfg(feature = "gql_transport")]
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl<C: crate::external::GqlConnection> crate::external::GqlConnection
+    for InstrumentedConnection<C>
+{
+    fn is_local(&self) -> bool {
+        self.connection.is_local()
+    }
+
+    async fn post(&self, req: crate::external::GqlRequest) -> Result<String> {
+        self.request(req.data.len(), self.connection.post(req))
+            .await
+    }
+}
+
+#[cfg(feature = "jrpc_transport")]
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl<C: crate::external::JrpcConnection> crate::external::JrpcConnection
+    for InstrumentedConnection<C>
+{
+    async fn post(&self, req: crate::external::JrpcRequest) -> Result<String> {
+        self.request(req.data.len(), self.connection.post(req))
+            .await
+    }
+
+    fn supports_batch(&self) -> bool {
+        self.connection.supports_batch()
+    }
+}
+
+#[cfg(feature = "proto_transport")]
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl<C: crate::external::ProtoConnection> crate::external::ProtoConnection
+    for InstrumentedConnection<C>
+{
+    async fn post(&self, req: crate::external::ProtoRequest) -> Result<Vec<u8>> {
+        self.request(req.data.len(), self.connection.post(req))
+            .await
+    }
+}
+
+/// Aggregated stats of the method or connection
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct CallStats {
+    pub calls: u64,
+    pub errors: u64,
+    pub total_duration: Duration,
+    pub max_duration: Duration,
+    /// Total size of requests.
+    ///
+    /// Methods have it only if their transport uses [`InstrumentedConnection`]
+    pub request_bytes: u64,
+    /// Total size of responses.
+    ///
+    /// Methods have it only if their transport uses [`InstrumentedConnection`]
+    pub response_bytes: u64,
+}
+
+impl CallStats {
+    pub fn average_duration(&self) -> Duration {
+        match self.calls {
+            0 => Duration::ZERO,
+            calls => self.total_duration / calls as u32,
+        }
+    }
+
+    fn record(&mut self, duration: Duration, is_error: bool) {
+        self.calls += 1;
+        self.errors += is_error as u64;
+        self.total_duration += duration;
+        self.max_duration = self.max_duration.max(duration);
+    }
+
+    fn add_bytes(&mut self, request: &RequestRecord<'_>) {
+        self.request_bytes += request.request_size as u64;
+        self.response_bytes += request.response_size.unwrap_or_default() as u64;
+    }
+}
+
+/// Metrics which are aggregated in memory
+#[derive(Default)]
+pub struct InMemoryTransportMetrics {
+    methods: Mutex<HashMap<TransportMethod, CallStats>>,
+    connections: Mutex<HashMap<String, CallStats>>,
+}
+
+impl InMemoryTransportMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns stats of all called methods
+    pub fn methods(&self) -> HashMap<TransportMethod, CallStats> {
+        self.methods.lock().clone()
+    }
+
+    /// Returns stats of all used connections
+    pub fn connections(&self) -> HashMap<String, CallStats> {
+        self.connections.lock().clone()
+    }
+
+    pub fn reset(&self) {
+        self.methods.lock().clear();
+        self.connections.lock().clear();
+    }
+}
+
+impl TransportMetrics for InMemoryTransportMetrics {
+    fn record_call(&self, call: &CallRecord<'_>) {
+        self.methods
+            .lock()
+            .entry(call.method)
+            .or_default()
+            .record(call.duration, call.error.is_some());
+    }
+
+    fn record_request(&self, request: &RequestRecord<'_>) {
+        let mut connections = self.connections.lock();
+        let stats = match connections.get_mut(request.connection) {
+            Some(stats) => stats,
+            None => connections
+                .entry(request.connection.to_owned())
+                .or_default(),
+        };
+        stats.record(request.duration, request.error.is_some());
+        stats.add_bytes(request);
+        drop(connections);
+
+        if let Some(method) = request.method {
+            self.methods
+                .lock()
+                .entry(method)
+                .or_default()
+                .add_bytes(request);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregates_stats() {
+        let metrics = InMemoryTransportMetrics::new();
+        let error = anyhow::anyhow!("timeout");
+
+        for (duration, error) in [(10, None), (30, Some(&error))] {
+            metrics.record_call(&CallRecord {
+                method: TransportMethod::GetContractState,
+                address: None,
+                lt: None,
+                duration: Duration::from_millis(duration),
+                error,
+            });
+            metrics.record_request(&RequestRecord {
+                connection: "main",
+                method: Some(TransportMethod::GetContractState),
+                request_size: 100,
+                response_size: error.is_none().then_some(1000),
+                duration: Duration::from_millis(duration),
+                error,
+            });
+        }
+
+        let methods = metrics.methods();
+        let stats = &methods[&TransportMethod::GetContractState];
+        assert_eq!(stats.calls, 2);
+        assert_eq!(stats.errors, 1);
+        assert_eq!(stats.max_duration, Duration::from_millis(30));
+        assert_eq!(stats.average_duration(), Duration::from_millis(20));
+        assert_eq!(stats.request_bytes, 200);
+        assert_eq!(stats.response_bytes, 1000);
+
+        let connections = metrics.connections();
+        let stats = &connections["main"];
+        assert_eq!(stats.calls, 2);
+        assert_eq!(stats.request_bytes, 200);
+        assert_eq!(stats.response_bytes, 1000);
+
+        metrics.reset();
+        assert!(metrics.methods().is_empty());
+    }
+
+    #[cfg(feature = "local_transport")]
+    #[tokio::test]
+    async fn records_transport_calls() -> Result<()> {
+        use crate::transport::local::tests::src_address;
+        use crate::transport::local::LocalTransport;
+
+        let metrics = Arc::new(InMemoryTransportMetrics::new());
+        let clock = Arc::new(ConstClock::from_secs(1700000000));
+        let transport = InstrumentedTransport::new(LocalTransport::new(clock), metrics.clone());
+
+        let address = src_address();
+        transport.get_contract_state(&address).await?;
+        transport.get_transactions(&address, u64::MAX, 10).await?;
+
+        let methods = metrics.methods();
+        assert_eq!(methods[&TransportMethod::GetContractState].calls, 1);
+        assert_eq!(methods[&TransportMethod::GetTransactions].calls, 1);
+        assert_eq!(methods.len(), 2);
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "jrpc_transport", feature = "local_transport"))]
+    #[tokio::test]
+    async fn records_request_sizes_per_method() -> Result<()> {
+        use crate::transport::jrpc::server::JrpcServer;
+        use crate::transport::jrpc::JrpcTransport;
+        use crate::transport::local::tests::src_address;
+        use crate::transport::local::LocalTransport;
+
+        let metrics = Arc::new(InMemoryTransportMetrics::new());
+        let clock = Arc::new(ConstClock::from_secs(1700000000));
+        let server = JrpcServer::new(Arc::new(LocalTransport::new(clock)));
+        let connection = InstrumentedConnection::new("local", server, metrics.clone());
+        let transport =
+            InstrumentedTransport::new(JrpcTransport::new(Arc::new(connection)), metrics.clone());
+
+        let address = src_address();
+        transport.get_contract_state(&address).await?;
+        transport.get_transactions(&address, u64::MAX, 10).await?;
+
+        let methods = metrics.methods();
+        let connections = metrics.connections();
+        let connection = &connections["local"];
+        assert_eq!(connection.calls, 2);
+
+        let (state, transactions) = (
+            &methods[&TransportMethod::GetContractState],
+            &methods[&TransportMethod::GetTransactions],
+        );
+        assert!(state.request_bytes > 0 && transactions.request_bytes > 0);
+        assert_eq!(
+            state.request_bytes + transactions.request_bytes,
+            connection.request_bytes
+        );
+        assert_eq!(
+            state.response_bytes + transactions.response_bytes,
+            connection.response_bytes
+        );
+
+        Ok(())
+    }
+}
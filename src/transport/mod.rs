@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use anyhow::Result;
 use nekoton_utils::Clock;
 use serde::{Deserialize, Serialize};
@@ -11,10 +13,27 @@ use self::models::*;
 pub mod gql;
 #[cfg(feature = "jrpc_transport")]
 pub mod jrpc;
+#[cfg(feature = "local_transport")]
+pub mod local;
 #[cfg(feature = "proto_transport")]
 pub mod proto;
 
+pub mod cached;
+pub mod fallback;
+#[cfg(any(
+    feature = "gql_transport",
+    feature = "jrpc_transport",
+    feature = "proto_transport"
+))]
+mod long_poll;
+pub mod metrics;
 pub mod models;
+pub mod offline;
+pub mod pagination;
+pub mod proof;
+pub mod quorum;
+pub mod recording;
+pub mod retry;
 #[cfg(any(
     feature = "gql_transport",
     feature = "jrpc_transport",
@@ -27,16 +46,68 @@ mod utils;
 pub trait Transport: Send + Sync {
     fn info(&self) -> TransportInfo;
 
+    /// Broadcasts an external message.
+    ///
+    /// Messages refused by the node fail with [`TransportError::MessageRejected`].
     async fn send_message(&self, message: &ton_block::Message) -> Result<()>;
 
     async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState>;
 
+    /// Returns states of all specified accounts in the same order.
+    ///
+    /// Default implementation requests each state separately, with at most
+    /// [`TransportInfo::max_accounts_per_fetch`] requests in flight.
+    async fn get_contract_states(
+        &self,
+        addresses: &[MsgAddressInt],
+    ) -> Result<Vec<RawContractState>> {
+        let chunk_size = std::cmp::max(self.info().max_accounts_per_fetch, 1) as usize;
+
+        let mut result = Vec::with_capacity(addresses.len());
+        for addresses in addresses.chunks(chunk_size) {
+            result.extend(
+                futures_util::future::try_join_all(
+                    addresses
+                        .iter()
+                        .map(|address| self.get_contract_state(address)),
+                )
+                .await?,
+            );
+        }
+        Ok(result)
+    }
+
     async fn poll_contract_state(
         &self,
         address: &MsgAddressInt,
         last_trans_lt: u64,
     ) -> Result<PollContractState>;
 
+    /// Returns the account state as it was at the specified point in the past.
+    ///
+    /// Not all transports store historical states, so the default implementation
+    /// returns [`TransportError::MethodNotSupported`].
+    async fn get_contract_state_at(
+        &self,
+        address: &MsgAddressInt,
+        at: ContractStateAt,
+    ) -> Result<RawContractState> {
+        let _ = (address, at);
+        Err(TransportError::MethodNotSupported.into())
+    }
+
+    /// Returns the account state with a proof which can be checked against
+    /// the masterchain by [`proof::ProofVerifier`].
+    ///
+    /// Default implementation returns [`TransportError::MethodNotSupported`].
+    async fn get_contract_state_with_proof(
+        &self,
+        address: &MsgAddressInt,
+    ) -> Result<proof::ContractStateWithProof> {
+        let _ = address;
+        Err(TransportError::MethodNotSupported.into())
+    }
+
     async fn get_accounts_by_code_hash(
         &self,
         code_hash: &ton_types::UInt256,
@@ -60,6 +131,45 @@ pub trait Transport: Send + Sync {
 
     async fn get_latest_key_block(&self) -> Result<ton_block::Block>;
 
+    /// Returns the latest block of the shard which contains the specified account.
+    ///
+    /// Block methods are used for [`ReliableBehavior::BlockWalking`]. Transports
+    /// without block access return [`TransportError::MethodNotSupported`].
+    async fn get_latest_block(&self, address: &MsgAddressInt) -> Result<LatestBlock> {
+        let _ = address;
+        Err(TransportError::MethodNotSupported.into())
+    }
+
+    /// Returns the block with the specified id (hex encoded root hash).
+    async fn get_block(&self, id: &str) -> Result<ton_block::Block> {
+        let _ = id;
+        Err(TransportError::MethodNotSupported.into())
+    }
+
+    /// Returns the block of the specified shard with the specified seqno.
+    async fn get_block_by_seqno(
+        &self,
+        shard: &ton_block::ShardIdent,
+        seqno: u32,
+    ) -> Result<ton_block::Block> {
+        let _ = (shard, seqno);
+        Err(TransportError::MethodNotSupported.into())
+    }
+
+    /// Waits for the block next to `current` in the shard which contains
+    /// the specified account and returns its id.
+    ///
+    /// After a shard split the id of the block with the account is returned.
+    async fn wait_for_next_block(
+        &self,
+        current: &str,
+        address: &MsgAddressInt,
+        timeout: Duration,
+    ) -> Result<String> {
+        let _ = (current, address, timeout);
+        Err(TransportError::MethodNotSupported.into())
+    }
+
     async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities>;
 
     // NOTE: clock is used for caching here
@@ -68,12 +178,198 @@ pub trait Transport: Send + Sync {
         clock: &dyn Clock,
         force: bool,
     ) -> Result<ton_executor::BlockchainConfig>;
+
+    /// Returns a stream of new transactions and states of the specified accounts.
+    ///
+    /// `None` means that the transport doesn't support push notifications
+    /// and accounts must be polled.
+    fn subscribe_accounts(&self, addresses: &[MsgAddressInt]) -> Option<AccountUpdatesStream> {
+        let _ = addresses;
+        None
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum TransportError {
+    #[error("Method is not supported by the transport")]
+    MethodNotSupported,
+    /// The node refused to accept an external message
+    #[error("Message rejected: {0}")]
+    MessageRejected(String),
 }
 
+impl TransportError {
+    /// Unsupported methods and rejected messages are deterministic
+    pub fn is_retryable(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(not(feature = "non_threadsafe"))]
+pub type AccountUpdatesStream = futures_util::stream::BoxStream<'static, Result<AccountUpdate>>;
+#[cfg(feature = "non_threadsafe")]
+pub type AccountUpdatesStream =
+    futures_util::stream::LocalBoxStream<'static, Result<AccountUpdate>>;
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TransportInfo {
     pub max_transactions_per_fetch: u8,
+    #[serde(default = "default_max_accounts_per_fetch")]
+    pub max_accounts_per_fetch: u8,
     pub reliable_behavior: ReliableBehavior,
     pub has_key_blocks: bool,
 }
+
+impl TransportInfo {
+    /// Combines infos of several transports into the most restrictive one
+    pub fn most_restrictive<I>(infos: I) -> Self
+    where
+        I: IntoIterator<Item = TransportInfo>,
+    {
+        let mut result = TransportInfo {
+            max_transactions_per_fetch: u8::MAX,
+            max_accounts_per_fetch: u8::MAX,
+            reliable_behavior: ReliableBehavior::BlockWalking,
+            has_key_blocks: true,
+        };
+
+        for info in infos {
+            result.max_transactions_per_fetch = std::cmp::min(
+                result.max_transactions_per_fetch,
+                info.max_transactions_per_fetch,
+            );
+            result.max_accounts_per_fetch =
+                std::cmp::min(result.max_accounts_per_fetch, info.max_accounts_per_fetch);
+            if info.reliable_behavior == ReliableBehavior::IntensivePolling {
+                result.reliable_behavior = ReliableBehavior::IntensivePolling;
+            }
+            result.has_key_blocks &= info.has_key_blocks;
+        }
+
+        result
+    }
+}
+
+fn default_max_accounts_per_fetch() -> u8 {
+    50
+}
+
+#[cfg(test)]
+#[cfg(feature = "local_transport")]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use nekoton_utils::SimpleClock;
+
+    use super::*;
+    use crate::transport::local::tests::test_address;
+    use crate::transport::local::LocalTransport;
+
+    /// Local transport which tracks the number of concurrent state requests
+    struct ConcurrencyTransport {
+        inner: LocalTransport,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl Transport for ConcurrencyTransport {
+        fn info(&self) -> TransportInfo {
+            TransportInfo {
+                max_accounts_per_fetch: 2,
+                ..self.inner.info()
+            }
+        }
+
+        async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
+            self.inner.send_message(message).await
+        }
+
+        async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::task::yield_now().await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            self.inner.get_contract_state(address).await
+        }
+
+        async fn poll_contract_state(
+            &self,
+            address: &MsgAddressInt,
+            last_trans_lt: u64,
+        ) -> Result<PollContractState> {
+            self.inner.poll_contract_state(address, last_trans_lt).await
+        }
+
+        async fn get_accounts_by_code_hash(
+            &self,
+            code_hash: &ton_types::UInt256,
+            limit: u8,
+            continuation: &Option<MsgAddressInt>,
+        ) -> Result<Vec<MsgAddressInt>> {
+            self.inner
+                .get_accounts_by_code_hash(code_hash, limit, continuation)
+                .await
+        }
+
+        async fn get_transactions(
+            &self,
+            address: &MsgAddressInt,
+            from_lt: u64,
+            count: u8,
+        ) -> Result<Vec<RawTransaction>> {
+            self.inner.get_transactions(address, from_lt, count).await
+        }
+
+        async fn get_transaction(&self, id: &ton_types::UInt256) -> Result<Option<RawTransaction>> {
+            self.inner.get_transaction(id).await
+        }
+
+        async fn get_dst_transaction(
+            &self,
+            message_hash: &ton_types::UInt256,
+        ) -> Result<Option<RawTransaction>> {
+            self.inner.get_dst_transaction(message_hash).await
+        }
+
+        async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+            self.inner.get_latest_key_block().await
+        }
+
+        async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
+            self.inner.get_capabilities(clock).await
+        }
+
+        async fn get_blockchain_config(
+            &self,
+            clock: &dyn Clock,
+            force: bool,
+        ) -> Result<ton_executor::BlockchainConfig> {
+            self.inner.get_blockchain_config(clock, force).await
+        }
+    }
+
+    #[tokio::test]
+    async fn default_get_contract_states_is_bounded() -> Result<()> {
+        let transport = ConcurrencyTransport {
+            inner: LocalTransport::new(Arc::new(SimpleClock)),
+            in_flight: AtomicUsize::new(0),
+            max_in_flight: AtomicUsize::new(0),
+        };
+
+        let addresses = (1..=5).map(test_address).collect::<Vec<_>>();
+        let states = transport.get_contract_states(&addresses).await?;
+
+        assert_eq!(states.len(), addresses.len());
+        assert!(states
+            .iter()
+            .all(|state| matches!(state, RawContractState::NotExists { .. })));
+        assert_eq!(transport.max_in_flight.load(Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+}
@@ -1,11 +1,11 @@
 use std::cmp::Ordering;
 
 use serde::{Deserialize, Serialize};
-use ton_block::{Account, AccountStuff, Transaction};
+use ton_block::{Account, AccountStuff, MsgAddressInt, Transaction};
 use ton_types::UInt256;
 
 use nekoton_abi::{ExecutionContext, GenTimings, LastTransactionId};
-use nekoton_utils::{serde_account_stuff, Clock};
+use nekoton_utils::{serde_account_stuff, serde_cell, serde_ton_block, serde_u64, Clock};
 
 use crate::core::models::{ContractState, PendingTransaction};
 
@@ -92,6 +92,37 @@ impl From<RawContractState> for PollContractState {
     }
 }
 
+/// Point in the account history
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type", content = "value")]
+pub enum ContractStateAt {
+    /// State after the latest transaction with the lt less than or equal to the specified one
+    Lt(#[serde(with = "serde_u64")] u64),
+    /// State at the end of the shard block with the specified seqno
+    BlockSeqno(u32),
+}
+
+/// Latest block of the shard which contains some account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LatestBlock {
+    /// Hex encoded root hash of the block
+    pub id: String,
+    #[serde(with = "serde_u64")]
+    pub end_lt: u64,
+    pub gen_utime: u32,
+}
+
+/// Account changes pushed by the transport
+#[derive(Debug, Clone)]
+pub struct AccountUpdate {
+    pub address: MsgAddressInt,
+    /// Account state after all new transactions
+    pub state: RawContractState,
+    /// New transactions, sorted by lt in descending order
+    pub transactions: Vec<RawTransaction>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ExistingContract {
@@ -173,6 +204,30 @@ impl Ord for RawTransaction {
     }
 }
 
+impl Serialize for RawTransaction {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serde_ton_block::serialize(&self.data, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RawTransaction {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::Error;
+        use ton_block::Deserializable;
+
+        let cell = serde_cell::deserialize(deserializer)?;
+        let hash = cell.repr_hash();
+        let data = Transaction::construct_from_cell(cell).map_err(Error::custom)?;
+        Ok(Self { hash, data })
+    }
+}
+
 impl PartialEq<RawTransaction> for PendingTransaction {
     fn eq(&self, other: &RawTransaction) -> bool {
         if other.data.now >= self.expire_at {
@@ -0,0 +1,465 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use ton_block::{GetRepresentationHash, MsgAddressInt};
+use ton_types::UInt256;
+
+use nekoton_utils::*;
+
+use crate::core::models::{NetworkCapabilities, ReliableBehavior};
+
+use super::models::{
+    ContractStateAt, LatestBlock, PollContractState, RawContractState, RawTransaction,
+};
+use super::{Transport, TransportInfo};
+
+/// Account states and network parameters exported on an online machine
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OfflineSnapshot {
+    /// Unix timestamp of the export
+    pub created_at: u32,
+    pub capabilities: NetworkCapabilities,
+    #[serde(with = "serde_ton_block")]
+    pub config: ton_block::ConfigParams,
+    pub accounts: Vec<SnapshotAccount>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotAccount {
+    #[serde(with = "serde_address")]
+    pub address: MsgAddressInt,
+    pub state: RawContractState,
+}
+
+impl OfflineSnapshot {
+    /// Fetches the network parameters and states of the specified accounts
+    pub async fn export(
+        transport: &dyn Transport,
+        clock: &dyn Clock,
+        addresses: &[MsgAddressInt],
+    ) -> Result<Self> {
+        let capabilities = transport.get_capabilities(clock).await?;
+        let config = transport.get_blockchain_config(clock, true).await?;
+        let states = transport.get_contract_states(addresses).await?;
+
+        Ok(Self {
+            created_at: clock.now_sec_u64() as u32,
+            capabilities,
+            config: config.raw_config().clone(),
+            accounts: addresses
+                .iter()
+                .cloned()
+                .zip(states)
+                .map(|(address, state)| SnapshotAccount { address, state })
+                .collect(),
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let data = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+}
+
+/// Messages which were sent through [`OfflineTransport`]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Outbox {
+    pub messages: Vec<OutboxMessage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxMessage {
+    #[serde(with = "serde_uint256")]
+    pub hash: UInt256,
+    #[serde(with = "serde_ton_block")]
+    pub message: ton_block::Message,
+}
+
+impl Outbox {
+    /// Loads the outbox or returns an empty one if the file doesn't exist
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(data) => Ok(serde_json::from_str(&data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+
+    /// Sends all messages through the online transport
+    pub async fn broadcast(&self, transport: &dyn Transport) -> Result<()> {
+        for item in &self.messages {
+            transport.send_message(&item.message).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Transport for air-gapped machines.
+///
+/// Serves account states, config and capabilities from [`OfflineSnapshot`].
+/// Sent messages are collected into [`Outbox`] (and optionally its file).
+/// Transactions are not exported, so the history of each account is empty.
+/// All other requests fail with [`OfflineTransportError`].
+pub struct OfflineTransport {
+    accounts: HashMap<MsgAddressInt, RawContractState>,
+    capabilities: NetworkCapabilities,
+    config: ton_executor::BlockchainConfig,
+    outbox: Mutex<Outbox>,
+    outbox_path: Option<PathBuf>,
+}
+
+impl OfflineTransport {
+    pub fn new(snapshot: OfflineSnapshot) -> Result<Self> {
+        let config = ton_executor::BlockchainConfig::with_config(
+            snapshot.config,
+            snapshot.capabilities.global_id,
+        )
+        .map_err(|_| OfflineTransportError::InvalidConfig)?;
+
+        Ok(Self {
+            accounts: snapshot
+                .accounts
+                .into_iter()
+                .map(|account| (account.address, account.state))
+                .collect(),
+            capabilities: snapshot.capabilities,
+            config,
+            outbox: Default::default(),
+            outbox_path: None,
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        OfflineSnapshot::load(path).and_then(Self::new)
+    }
+
+    /// Appends each sent message to the outbox file
+    pub fn with_outbox_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        *self.outbox.get_mut() = Outbox::load(&path)?;
+        self.outbox_path = Some(path);
+        Ok(self)
+    }
+
+    /// Returns all sent messages
+    pub fn outbox(&self) -> Outbox {
+        self.outbox.lock().clone()
+    }
+
+    fn account_state(&self, address: &MsgAddressInt) -> Result<&RawContractState> {
+        self.accounts
+            .get(address)
+            .ok_or_else(|| OfflineTransportError::AccountNotFound(address.to_string()).into())
+    }
+}
+
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl Transport for OfflineTransport {
+    fn info(&self) -> TransportInfo {
+        TransportInfo {
+            max_transactions_per_fetch: 50,
+            max_accounts_per_fetch: 50,
+            reliable_behavior: ReliableBehavior::IntensivePolling,
+            has_key_blocks: false,
+        }
+    }
+
+    async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
+        let hash = message.hash()?;
+
+        let mut outbox = self.outbox.lock();
+        if outbox.messages.iter().any(|item| item.hash == hash) {
+            return Ok(());
+        }
+
+        outbox.messages.push(OutboxMessage {
+            hash,
+            message: message.clone(),
+        });
+
+        if let Some(path) = &self.outbox_path {
+            if let Err(e) = outbox.save(path) {
+                outbox.messages.pop();
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
+        self.account_state(address).cloned()
+    }
+
+    async fn poll_contract_state(
+        &self,
+        address: &MsgAddressInt,
+        last_trans_lt: u64,
+    ) -> Result<PollContractState> {
+        let state = self.account_state(address)?;
+        Ok(match state {
+            RawContractState::Exists(contract)
+                if contract.account.storage.last_trans_lt != last_trans_lt =>
+            {
+                PollContractState::Exists(contract.clone())
+            }
+            RawContractState::Exists(contract) => PollContractState::Unchanged {
+                timings: contract.timings,
+            },
+            RawContractState::NotExists { timings } => {
+                PollContractState::NotExists { timings: *timings }
+            }
+        })
+    }
+
+    async fn get_contract_state_at(
+        &self,
+        _address: &MsgAddressInt,
+        _at: ContractStateAt,
+    ) -> Result<RawContractState> {
+        Err(OfflineTransportError::NetworkRequired.into())
+    }
+
+    async fn get_accounts_by_code_hash(
+        &self,
+        _code_hash: &UInt256,
+        _limit: u8,
+        _continuation: &Option<MsgAddressInt>,
+    ) -> Result<Vec<MsgAddressInt>> {
+        Err(OfflineTransportError::NetworkRequired.into())
+    }
+
+    async fn get_transactions(
+        &self,
+        _address: &MsgAddressInt,
+        _from_lt: u64,
+        _count: u8,
+    ) -> Result<Vec<RawTransaction>> {
+        // NOTE: subscriptions preload transactions, so an empty page is
+        // returned instead of an error
+        Ok(Vec::new())
+    }
+
+    async fn get_transaction(&self, _id: &UInt256) -> Result<Option<RawTransaction>> {
+        Err(OfflineTransportError::NetworkRequired.into())
+    }
+
+    async fn get_dst_transaction(&self, _message_hash: &UInt256) -> Result<Option<RawTransaction>> {
+        Err(OfflineTransportError::NetworkRequired.into())
+    }
+
+    async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+        Err(OfflineTransportError::NetworkRequired.into())
+    }
+
+    async fn get_latest_block(&self, _address: &MsgAddressInt) -> Result<LatestBlock> {
+        Err(OfflineTransportError::NetworkRequired.into())
+    }
+
+    async fn get_block(&self, _id: &str) -> Result<ton_block::Block> {
+        Err(OfflineTransportError::NetworkRequired.into())
+    }
+
+    async fn get_block_by_seqno(
+        &self,
+        _shard: &ton_block::ShardIdent,
+        _seqno: u32,
+    ) -> Result<ton_block::Block> {
+        Err(OfflineTransportError::NetworkRequired.into())
+    }
+
+    async fn wait_for_next_block(
+        &self,
+        _current: &str,
+        _address: &MsgAddressInt,
+        _timeout: Duration,
+    ) -> Result<String> {
+        Err(OfflineTransportError::NetworkRequired.into())
+    }
+
+    async fn get_capabilities(&self, _clock: &dyn Clock) -> Result<NetworkCapabilities> {
+        Ok(self.capabilities)
+    }
+
+    async fn get_blockchain_config(
+        &self,
+        _clock: &dyn Clock,
+        _force: bool,
+    ) -> Result<ton_executor::BlockchainConfig> {
+        Ok(self.config.clone())
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum OfflineTransportError {
+    #[error("Account {0} is not in the snapshot")]
+    AccountNotFound(String),
+    #[error("Request requires network access")]
+    NetworkRequired,
+    #[error("Invalid config")]
+    InvalidConfig,
+}
+
+impl OfflineTransportError {
+    /// Snapshot data never changes
+    pub fn is_retryable(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "local_transport")]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::transport::local::tests::{
+        dst_address, make_transfer, make_transfer_to, src_address,
+    };
+    use crate::transport::local::LocalTransport;
+
+    #[tokio::test]
+    async fn works_from_exported_snapshot() -> Result<()> {
+        let clock = SimpleClock;
+        let local = LocalTransport::new(Arc::new(SimpleClock));
+
+        let dst = dst_address();
+        let message = make_transfer(1_000_000_000);
+        local.send_message(&message).await?;
+
+        let snapshot = OfflineSnapshot::export(&local, &clock, &[dst.clone()]).await?;
+        let snapshot = serde_json::from_str(&serde_json::to_string(&snapshot)?)?;
+        let offline = OfflineTransport::new(snapshot)?;
+
+        let state = offline.get_contract_state(&dst).await?;
+        assert_eq!(
+            state.brief().balance,
+            local.get_contract_state(&dst).await?.brief().balance
+        );
+        assert_eq!(
+            offline.get_capabilities(&clock).await?,
+            local.get_capabilities(&clock).await?
+        );
+        offline.get_blockchain_config(&clock, false).await?;
+
+        assert!(matches!(
+            offline
+                .get_contract_state(&src_address())
+                .await
+                .unwrap_err()
+                .downcast_ref::<OfflineTransportError>(),
+            Some(OfflineTransportError::AccountNotFound(_))
+        ));
+        assert!(offline
+            .get_transactions(&dst, u64::MAX, 10)
+            .await?
+            .is_empty());
+        assert!(matches!(
+            offline
+                .get_dst_transaction(&message.hash()?)
+                .await
+                .unwrap_err()
+                .downcast_ref::<OfflineTransportError>(),
+            Some(OfflineTransportError::NetworkRequired)
+        ));
+
+        offline.send_message(&message).await?;
+        offline.send_message(&message).await?;
+        let outbox = offline.outbox();
+        assert_eq!(outbox.messages.len(), 1);
+        assert_eq!(outbox.messages[0].hash, message.hash()?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn prepares_wallet_transfers() -> Result<()> {
+        use crate::core::models::{Expiration, PendingTransaction, Transaction};
+        use crate::core::ton_wallet::{
+            compute_address, Gift, TonWallet, TonWalletSubscriptionHandler, TransferAction,
+            WalletType,
+        };
+
+        struct Handler;
+
+        impl TonWalletSubscriptionHandler for Handler {
+            fn on_message_sent(&self, _: PendingTransaction, _: Option<Transaction>) {}
+
+            fn on_message_expired(&self, _: PendingTransaction) {}
+        }
+
+        let clock = Arc::new(SimpleClock);
+        let local = LocalTransport::new(clock.clone());
+
+        let secret = ed25519_dalek::SecretKey::from_bytes(&[1; 32])?;
+        let public_key = ed25519_dalek::PublicKey::from(&secret);
+        let address = compute_address(&public_key, WalletType::WalletV3, 0);
+
+        // Top up the wallet which is not deployed yet
+        local
+            .send_message(&make_transfer_to(&address, 1_000_000_000))
+            .await?;
+
+        let snapshot =
+            OfflineSnapshot::export(&local, clock.as_ref(), std::slice::from_ref(&address)).await?;
+        let offline = Arc::new(OfflineTransport::new(snapshot)?);
+
+        let mut wallet = TonWallet::subscribe(
+            clock,
+            offline.clone(),
+            0,
+            public_key,
+            WalletType::WalletV3,
+            Arc::new(Handler),
+        )
+        .await?;
+        assert_eq!(wallet.address(), &address);
+
+        let account = match offline.get_contract_state(&address).await? {
+            RawContractState::Exists(contract) => contract.account,
+            RawContractState::NotExists { .. } => anyhow::bail!("account not found"),
+        };
+        let gift = Gift {
+            flags: 3,
+            bounce: false,
+            destination: src_address(),
+            amount: 100_000_000,
+            body: None,
+            state_init: None,
+        };
+        let unsigned =
+            match wallet.prepare_transfer(&account, &public_key, gift, Expiration::Timeout(60))? {
+                TransferAction::Sign(unsigned) => unsigned,
+                TransferAction::DeployFirst => {
+                    anyhow::bail!("wallet must be deployed with transfer")
+                }
+            };
+
+        // Signature is not checked during the estimation
+        let signed = unsigned.sign(&[0; 64])?;
+        assert!(wallet.estimate_fees(&signed.message).await? > 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use ton_block::MsgAddressInt;
+use ton_types::UInt256;
+
+use super::models::RawContractState;
+use super::Transport;
+
+#[cfg(not(feature = "non_threadsafe"))]
+pub type AccountsStream = futures_util::stream::BoxStream<'static, Result<MsgAddressInt>>;
+#[cfg(feature = "non_threadsafe")]
+pub type AccountsStream = futures_util::stream::LocalBoxStream<'static, Result<MsgAddressInt>>;
+
+#[cfg(not(feature = "non_threadsafe"))]
+pub type AccountStatesStream =
+    futures_util::stream::BoxStream<'static, Result<(MsgAddressInt, RawContractState)>>;
+#[cfg(feature = "non_threadsafe")]
+pub type AccountStatesStream =
+    futures_util::stream::LocalBoxStream<'static, Result<(MsgAddressInt, RawContractState)>>;
+
+/// Pages through all accounts with the specified code hash.
+///
+/// ```ignore
+/// let mut accounts = AccountsByCodeHash::new(transport, code_hash)
+///     .with_workchain(0)
+///     .into_stream();
+/// while let Some(address) = accounts.try_next().await? {
+///     // ...
+/// }
+/// ```
+#[derive(Clone)]
+pub struct AccountsByCodeHash {
+    transport: Arc<dyn Transport>,
+    code_hash: UInt256,
+    page_size: Option<u8>,
+    workchain: Option<i32>,
+    continuation: Option<MsgAddressInt>,
+}
+
+impl AccountsByCodeHash {
+    pub fn new(transport: Arc<dyn Transport>, code_hash: UInt256) -> Self {
+        Self {
+            transport,
+            code_hash,
+            page_size: None,
+            workchain: None,
+            continuation: None,
+        }
+    }
+
+    /// Number of accounts requested at once. It is limited by
+    /// [`TransportInfo::max_accounts_per_fetch`](super::TransportInfo::max_accounts_per_fetch)
+    pub fn with_page_size(mut self, page_size: u8) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Skips accounts from other workchains
+    pub fn with_workchain(mut self, workchain: i32) -> Self {
+        self.workchain = Some(workchain);
+        self
+    }
+
+    /// Starts after the specified address (e.g. the last processed one)
+    pub fn with_continuation(mut self, continuation: MsgAddressInt) -> Self {
+        self.continuation = Some(continuation);
+        self
+    }
+
+    /// Returns a stream of account addresses
+    pub fn into_stream(self) -> AccountsStream {
+        let max_page_size = self.transport.info().max_accounts_per_fetch;
+        let page_size = self
+            .page_size
+            .map(|page_size| page_size.min(max_page_size))
+            .unwrap_or(max_page_size)
+            .max(1);
+
+        let state = PaginationState {
+            transport: self.transport,
+            code_hash: self.code_hash,
+            continuation: self.continuation,
+            finished: false,
+        };
+
+        let pages = stream::try_unfold(state, move |mut state| async move {
+            if state.finished {
+                return Ok(None);
+            }
+
+            let page = state
+                .transport
+                .get_accounts_by_code_hash(&state.code_hash, page_size, &state.continuation)
+                .await?;
+
+            // NOTE: continuation is updated before the filtering by workchain
+            state.finished = page.len() < page_size as usize;
+            if let Some(last) = page.last() {
+                state.continuation = Some(last.clone());
+            }
+
+            Ok(Some((page, state)))
+        });
+
+        let workchain = self.workchain;
+        let accounts = pages
+            .map_ok(|page| stream::iter(page.into_iter().map(Ok)))
+            .try_flatten()
+            .try_filter(move |address| {
+                let matches = match workchain {
+                    Some(workchain) => address.workchain_id() == workchain,
+                    None => true,
+                };
+                futures_util::future::ready(matches)
+            });
+
+        box_stream(accounts)
+    }
+
+    /// Returns a stream of account addresses with their states.
+    ///
+    /// Up to `concurrency` states are requested at once, the order of
+    /// accounts is preserved.
+    pub fn into_stream_with_states(self, concurrency: usize) -> AccountStatesStream {
+        let transport = self.transport.clone();
+        let states = self
+            .into_stream()
+            .map_ok(move |address| {
+                let transport = transport.clone();
+                async move {
+                    let state = transport.get_contract_state(&address).await?;
+                    Ok((address, state))
+                }
+            })
+            .try_buffered(concurrency.max(1));
+
+        box_stream(states)
+    }
+}
+
+struct PaginationState {
+    transport: Arc<dyn Transport>,
+    code_hash: UInt256,
+    continuation: Option<MsgAddressInt>,
+    finished: bool,
+}
+
+#[cfg(not(feature = "non_threadsafe"))]
+fn box_stream<'a, S>(stream: S) -> futures_util::stream::BoxStream<'a, S::Item>
+where
+    S: futures_util::Stream + Send + 'a,
+{
+    stream.boxed()
+}
+
+#[cfg(feature = "non_threadsafe")]
+fn box_stream<'a, S>(stream: S) -> futures_util::stream::LocalBoxStream<'a, S::Item>
+where
+    S: futures_util::Stream + 'a,
+{
+    stream.boxed_local()
+}
+
+#[cfg(test)]
+#[cfg(feature = "local_transport")]
+mod tests {
+    use nekoton_utils::SimpleClock;
+    use ton_block::{AccountState, AccountStorage, AccountStuff, CurrencyCollection, StateInit};
+
+    use super::*;
+    use crate::transport::local::LocalTransport;
+
+    fn make_account(workchain: i8, id: u8, code: &ton_types::Cell) -> AccountStuff {
+        AccountStuff {
+            addr: MsgAddressInt::with_standart(None, workchain, [id; 32].into()).unwrap(),
+            storage_stat: Default::default(),
+            storage: AccountStorage {
+                last_trans_lt: 0,
+                balance: CurrencyCollection::with_grams(1_000_000_000),
+                state: AccountState::AccountActive {
+                    state_init: StateInit {
+                        code: Some(code.clone()),
+                        ..Default::default()
+                    },
+                },
+                init_code_hash: None,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn pages_through_all_accounts() -> Result<()> {
+        let code = ton_types::BuilderData::new().into_cell()?;
+        let other_code = {
+            let mut builder = ton_types::BuilderData::new();
+            builder.append_u32(1)?;
+            builder.into_cell()?
+        };
+
+        let transport = Arc::new(LocalTransport::new(Arc::new(SimpleClock)));
+        for id in 1..=10 {
+            transport.insert_account(make_account(0, id, &code));
+        }
+        transport.insert_account(make_account(-1, 11, &code));
+        transport.insert_account(make_account(0, 12, &other_code));
+
+        let accounts = AccountsByCodeHash::new(transport.clone(), code.repr_hash())
+            .with_page_size(3)
+            .into_stream()
+            .try_collect::<Vec<_>>()
+            .await?;
+        assert_eq!(accounts.len(), 11);
+
+        let accounts = AccountsByCodeHash::new(transport.clone(), code.repr_hash())
+            .with_page_size(3)
+            .with_workchain(0)
+            .into_stream_with_states(4)
+            .try_collect::<Vec<_>>()
+            .await?;
+        assert_eq!(accounts.len(), 10);
+        assert!(accounts
+            .iter()
+            .all(|(address, state)| address.workchain_id() == 0
+                && matches!(state, RawContractState::Exists(_))));
+
+        // Accounts are returned in the order of addresses
+        let mut sorted = accounts.clone();
+        sorted.sort_by(|(a, _), (b, _)| a.cmp(b));
+        assert!(sorted.iter().zip(&accounts).all(|((a, _), (b, _))| a == b));
+
+        Ok(())
+    }
+}
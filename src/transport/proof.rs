@@ -0,0 +1,1040 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use nekoton_abi::{GenTimings, LastTransactionId};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use ton_block::{Deserializable, HashmapAugType, MsgAddressInt, Serializable};
+use ton_types::{HashmapType, UInt256};
+
+use nekoton_utils::*;
+
+use crate::core::models::NetworkCapabilities;
+
+use super::models::{
+    ContractStateAt, LatestBlock, PollContractState, RawContractState, RawTransaction,
+};
+use super::{AccountUpdatesStream, Transport, TransportError, TransportInfo};
+
+/// Account state with all data needed to check it against the masterchain
+#[derive(Debug, Clone)]
+pub struct ContractStateWithProof {
+    pub state: RawContractState,
+    pub proof: ContractStateProof,
+}
+
+#[derive(Debug, Clone)]
+pub struct ContractStateProof {
+    /// Signed proofs of key blocks since the last trusted one, in ascending order
+    pub key_block_proofs: Vec<ton_block::BlockProof>,
+    /// Signed proof of the masterchain block which references the shard block
+    pub mc_block_proof: ton_block::BlockProof,
+    /// Merkle proof of the shard block. Not used for masterchain accounts
+    pub shard_block_proof: Option<ton_types::Cell>,
+    /// Merkle proof of the shard state with the account
+    pub state_proof: ton_types::Cell,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProofVerifierSettings {
+    /// Masterchain blocks generated earlier than this are rejected. Default: `120000`
+    #[serde(with = "serde_duration_ms")]
+    pub max_block_age: Duration,
+}
+
+impl Default for ProofVerifierSettings {
+    fn default() -> Self {
+        Self {
+            max_block_age: Duration::from_secs(120),
+        }
+    }
+}
+
+/// Checks account state proofs starting from the trusted key block.
+///
+/// Newer key blocks are trusted only if they are signed by the validators
+/// of the previous trusted key block.
+pub struct ProofVerifier {
+    clock: Arc<dyn Clock>,
+    trusted: Mutex<TrustedKeyBlock>,
+    max_block_age: u64,
+}
+
+impl ProofVerifier {
+    pub fn new(clock: Arc<dyn Clock>, trusted_key_block: &ton_block::Block) -> Result<Self> {
+        Self::with_settings(clock, trusted_key_block, Default::default())
+    }
+
+    pub fn with_settings(
+        clock: Arc<dyn Clock>,
+        trusted_key_block: &ton_block::Block,
+        settings: ProofVerifierSettings,
+    ) -> Result<Self> {
+        Ok(Self {
+            clock,
+            trusted: Mutex::new(TrustedKeyBlock::new(trusted_key_block)?),
+            max_block_age: settings.max_block_age.as_secs(),
+        })
+    }
+
+    /// Returns the seqno of the latest trusted key block
+    pub fn trusted_key_block_seqno(&self) -> u32 {
+        self.trusted.lock().seqno
+    }
+
+    pub fn verify(
+        &self,
+        address: &MsgAddressInt,
+        state: &RawContractState,
+        proof: &ContractStateProof,
+    ) -> Result<()> {
+        // Update trusted key block
+        let trusted = {
+            let mut trusted = self.trusted.lock();
+            for key_block_proof in &proof.key_block_proofs {
+                let block = trusted.check_block_proof(key_block_proof)?;
+                let info = block.read_info()?;
+                if !info.key_block() || info.seq_no() <= trusted.seqno {
+                    return Err(ProofError::InvalidKeyBlock.into());
+                }
+                *trusted = TrustedKeyBlock::new(&block)?;
+            }
+            trusted.clone()
+        };
+
+        let mc_block = trusted.check_block_proof(&proof.mc_block_proof)?;
+        if !proof.mc_block_proof.proof_for.shard().is_masterchain() {
+            return Err(ProofError::InvalidBlockProof.into());
+        }
+
+        // Old blocks are correctly signed but may contain outdated states
+        let gen_utime = mc_block.read_info()?.gen_utime().as_u32() as u64;
+        if gen_utime + self.max_block_age < self.clock.now_sec_u64() {
+            return Err(ProofError::BlockTooOld.into());
+        }
+
+        // Find the hash of the shard state with the account
+        let state_hash = if address.is_masterchain() {
+            mc_block.read_state_update()?.new_hash
+        } else {
+            let shard_block_id = mc_block
+                .read_extra()?
+                .read_custom()?
+                .ok_or(ProofError::InvalidBlockProof)?
+                .shards()
+                .find_shard_by_prefix(&ton_block::AccountIdPrefixFull::prefix(address)?)?
+                .ok_or(ProofError::InvalidShardProof)?
+                .block_id()
+                .clone();
+
+            let shard_block_proof = proof
+                .shard_block_proof
+                .clone()
+                .ok_or(ProofError::InvalidShardProof)?;
+            let shard_block: ton_block::Block =
+                read_merkle_proof(shard_block_proof, &shard_block_id.root_hash)
+                    .map_err(|_| ProofError::InvalidShardProof)?;
+            shard_block.read_state_update()?.new_hash
+        };
+
+        let shard_state: ton_block::ShardStateUnsplit =
+            read_merkle_proof(proof.state_proof.clone(), &state_hash)
+                .map_err(|_| ProofError::InvalidStateProof)?;
+
+        let account_id = UInt256::from_be_bytes(&address.address().get_bytestring(0));
+        let shard_account = shard_state
+            .read_accounts()
+            .and_then(|accounts| accounts.get(&account_id))
+            .map_err(|_| ProofError::InvalidStateProof)?;
+
+        let timings = match state {
+            RawContractState::Exists(contract) => &contract.timings,
+            RawContractState::NotExists { timings } => timings,
+        };
+        if let GenTimings::Known { gen_lt, gen_utime } = *timings {
+            if gen_lt != shard_state.gen_lt() || gen_utime != shard_state.gen_time() {
+                return Err(ProofError::StateMismatch.into());
+            }
+        }
+
+        let none_hash = ton_block::Account::AccountNone.serialize()?.repr_hash();
+        let is_valid = match (shard_account, state) {
+            (Some(shard_account), RawContractState::Exists(contract)) => {
+                let hash = ton_block::Account::Account(contract.account.clone())
+                    .serialize()?
+                    .repr_hash();
+
+                // Transactions are fetched starting from the last transaction id,
+                // so it must be the same as in the proven state
+                let last_transaction_id_is_valid = match contract.last_transaction_id {
+                    LastTransactionId::Exact(id) => {
+                        id.lt == shard_account.last_trans_lt()
+                            && &id.hash == shard_account.last_trans_hash()
+                    }
+                    LastTransactionId::Inexact { latest_lt } => {
+                        latest_lt == shard_account.last_trans_lt()
+                    }
+                };
+
+                shard_account.account_cell().repr_hash() == hash && last_transaction_id_is_valid
+            }
+            (Some(shard_account), RawContractState::NotExists { .. }) => {
+                shard_account.account_cell().repr_hash() == none_hash
+            }
+            (None, RawContractState::NotExists { .. }) => true,
+            (None, RawContractState::Exists(_)) => false,
+        };
+
+        if is_valid {
+            Ok(())
+        } else {
+            Err(ProofError::StateMismatch.into())
+        }
+    }
+}
+
+#[derive(Clone)]
+struct TrustedKeyBlock {
+    seqno: u32,
+    validator_set: ton_block::ValidatorSet,
+    catchain_config: ton_block::CatchainConfig,
+}
+
+impl TrustedKeyBlock {
+    fn new(block: &ton_block::Block) -> Result<Self> {
+        let info = block.read_info()?;
+        if !info.key_block() {
+            return Err(ProofError::InvalidKeyBlock.into());
+        }
+
+        let extra = block
+            .read_extra()?
+            .read_custom()?
+            .ok_or(ProofError::InvalidKeyBlock)?;
+        let config = extra.config().ok_or(ProofError::InvalidKeyBlock)?;
+
+        Ok(Self {
+            seqno: info.seq_no(),
+            validator_set: config.validator_set()?,
+            catchain_config: config.catchain_config()?,
+        })
+    }
+
+    /// Checks validator signatures and returns the virtual block from the proof
+    fn check_block_proof(&self, proof: &ton_block::BlockProof) -> Result<ton_block::Block> {
+        let block_id = &proof.proof_for;
+
+        let block: ton_block::Block = read_merkle_proof(proof.root.clone(), &block_id.root_hash)
+            .map_err(|_| ProofError::InvalidBlockProof)?;
+
+        let info = block.read_info()?;
+        if info.seq_no() != block_id.seq_no || info.prev_key_block_seqno() != self.seqno {
+            return Err(ProofError::UnknownKeyBlock.into());
+        }
+
+        let signatures = proof.signatures.as_ref().ok_or(ProofError::NoSignatures)?;
+
+        let (subset, hash_short) = self.validator_set.calc_subset(
+            &self.catchain_config,
+            ton_block::SHARD_FULL,
+            ton_block::MASTERCHAIN_ID,
+            signatures.validator_info.catchain_seqno,
+            Default::default(),
+        )?;
+        if hash_short != signatures.validator_info.validator_list_hash_short {
+            return Err(ProofError::InvalidValidatorSet.into());
+        }
+
+        let mut data = Vec::with_capacity(4 + 32 + 32);
+        data.extend_from_slice(&BLOCK_SIGNATURE_MAGIC);
+        data.extend_from_slice(block_id.root_hash.as_slice());
+        data.extend_from_slice(block_id.file_hash.as_slice());
+
+        let total_weight = subset.iter().map(|validator| validator.weight).sum::<u64>();
+        let mut signed_weight = 0;
+        let mut signed = HashSet::new();
+
+        signatures
+            .pure_signatures
+            .signatures()
+            .iterate_slices(|_, ref mut slice| {
+                let pair = ton_block::CryptoSignaturePair::construct_from(slice)?;
+
+                let index = match subset
+                    .iter()
+                    .position(|validator| compute_node_id_short(validator) == pair.node_id_short)
+                {
+                    Some(index) => index,
+                    None => return Ok(true),
+                };
+
+                // Count each validator only once
+                if !signed.insert(index) {
+                    return Ok(true);
+                }
+
+                let validator = &subset[index];
+                let is_valid =
+                    ed25519_dalek::PublicKey::from_bytes(validator.public_key.key_bytes())
+                        .ok()
+                        .zip(ed25519_dalek::Signature::from_bytes(&pair.sign.to_bytes()).ok())
+                        .map(|(public_key, signature)| {
+                            ed25519_dalek::Verifier::verify(&public_key, &data, &signature).is_ok()
+                        })
+                        .unwrap_or_default();
+
+                if is_valid {
+                    signed_weight += validator.weight;
+                }
+                Ok(true)
+            })?;
+
+        if signed_weight * 3 <= total_weight * 2 {
+            return Err(ProofError::NotEnoughSignatures.into());
+        }
+
+        Ok(block)
+    }
+}
+
+/// Transport which only returns account states with valid proofs.
+///
+/// The underlying transport must implement [`Transport::get_contract_state_with_proof`].
+/// Other data (transactions, blocks, config) is returned as is.
+pub struct VerifiedTransport<T> {
+    transport: T,
+    verifier: ProofVerifier,
+}
+
+impl<T: Transport> VerifiedTransport<T> {
+    pub fn new(transport: T, verifier: ProofVerifier) -> Self {
+        Self {
+            transport,
+            verifier,
+        }
+    }
+
+    /// Returns the underlying transport
+    pub fn inner(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn verifier(&self) -> &ProofVerifier {
+        &self.verifier
+    }
+}
+
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl<T: Transport> Transport for VerifiedTransport<T> {
+    fn info(&self) -> TransportInfo {
+        self.transport.info()
+    }
+
+    async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
+        self.transport.send_message(message).await
+    }
+
+    async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
+        self.get_contract_state_with_proof(address)
+            .await
+            .map(|response| response.state)
+    }
+
+    async fn poll_contract_state(
+        &self,
+        address: &MsgAddressInt,
+        last_trans_lt: u64,
+    ) -> Result<PollContractState> {
+        Ok(match self.get_contract_state(address).await? {
+            RawContractState::Exists(contract)
+                if contract.account.storage.last_trans_lt == last_trans_lt =>
+            {
+                PollContractState::Unchanged {
+                    timings: contract.timings,
+                }
+            }
+            state => state.into(),
+        })
+    }
+
+    async fn get_contract_state_at(
+        &self,
+        _address: &MsgAddressInt,
+        _at: ContractStateAt,
+    ) -> Result<RawContractState> {
+        // NOTE: historical states can't be verified
+        Err(TransportError::MethodNotSupported.into())
+    }
+
+    async fn get_contract_state_with_proof(
+        &self,
+        address: &MsgAddressInt,
+    ) -> Result<ContractStateWithProof> {
+        let response = self
+            .transport
+            .get_contract_state_with_proof(address)
+            .await?;
+        self.verifier
+            .verify(address, &response.state, &response.proof)?;
+        Ok(response)
+    }
+
+    async fn get_accounts_by_code_hash(
+        &self,
+        code_hash: &UInt256,
+        limit: u8,
+        continuation: &Option<MsgAddressInt>,
+    ) -> Result<Vec<MsgAddressInt>> {
+        self.transport
+            .get_accounts_by_code_hash(code_hash, limit, continuation)
+            .await
+    }
+
+    async fn get_transactions(
+        &self,
+        address: &MsgAddressInt,
+        from_lt: u64,
+        count: u8,
+    ) -> Result<Vec<RawTransaction>> {
+        self.transport
+            .get_transactions(address, from_lt, count)
+            .await
+    }
+
+    async fn get_transaction(&self, id: &UInt256) -> Result<Option<RawTransaction>> {
+        self.transport.get_transaction(id).await
+    }
+
+    async fn get_dst_transaction(&self, message_hash: &UInt256) -> Result<Option<RawTransaction>> {
+        self.transport.get_dst_transaction(message_hash).await
+    }
+
+    async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+        self.transport.get_latest_key_block().await
+    }
+
+    async fn get_latest_block(&self, address: &MsgAddressInt) -> Result<LatestBlock> {
+        self.transport.get_latest_block(address).await
+    }
+
+    async fn get_block(&self, id: &str) -> Result<ton_block::Block> {
+        self.transport.get_block(id).await
+    }
+
+    async fn get_block_by_seqno(
+        &self,
+        shard: &ton_block::ShardIdent,
+        seqno: u32,
+    ) -> Result<ton_block::Block> {
+        self.transport.get_block_by_seqno(shard, seqno).await
+    }
+
+    async fn wait_for_next_block(
+        &self,
+        current: &str,
+        address: &MsgAddressInt,
+        timeout: Duration,
+    ) -> Result<String> {
+        self.transport
+            .wait_for_next_block(current, address, timeout)
+            .await
+    }
+
+    async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
+        self.transport.get_capabilities(clock).await
+    }
+
+    async fn get_blockchain_config(
+        &self,
+        clock: &dyn Clock,
+        force: bool,
+    ) -> Result<ton_executor::BlockchainConfig> {
+        self.transport.get_blockchain_config(clock, force).await
+    }
+
+    fn subscribe_accounts(&self, _addresses: &[MsgAddressInt]) -> Option<AccountUpdatesStream> {
+        // NOTE: pushed states can't be verified
+        None
+    }
+}
+
+/// Parses the Merkle proof and returns the virtual root of the specified type
+fn read_merkle_proof<T>(proof: ton_types::Cell, expected_hash: &UInt256) -> Result<T>
+where
+    T: Deserializable,
+{
+    let proof = ton_block::MerkleProof::construct_from_cell(proof)?;
+    if &proof.hash != expected_hash {
+        return Err(ProofError::HashMismatch.into());
+    }
+    T::construct_from_cell(proof.proof.virtualize(1))
+}
+
+fn compute_node_id_short(validator: &ton_block::ValidatorDescr) -> UInt256 {
+    // `pub.ed25519` TL constructor followed by the public key
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(ED25519_PUBLIC_KEY_MAGIC.to_le_bytes());
+    hasher.update(validator.public_key.key_bytes());
+    UInt256::from_slice(hasher.finalize().as_slice())
+}
+
+/// `ton.blockId` TL constructor as it is serialized in the signed data
+const BLOCK_SIGNATURE_MAGIC: [u8; 4] = [0x70, 0x6e, 0x0b, 0xc5];
+const ED25519_PUBLIC_KEY_MAGIC: u32 = 0x4813b4c6;
+
+#[derive(thiserror::Error, Debug, Copy, Clone)]
+pub enum ProofError {
+    #[error("Merkle proof hash mismatch")]
+    HashMismatch,
+    #[error("Invalid block proof")]
+    InvalidBlockProof,
+    #[error("Invalid key block")]
+    InvalidKeyBlock,
+    #[error("Block was signed by unknown validators")]
+    UnknownKeyBlock,
+    #[error("Block proof has no signatures")]
+    NoSignatures,
+    #[error("Invalid validator set")]
+    InvalidValidatorSet,
+    #[error("Not enough validator signatures")]
+    NotEnoughSignatures,
+    #[error("Invalid shard block proof")]
+    InvalidShardProof,
+    #[error("Invalid shard state proof")]
+    InvalidStateProof,
+    #[error("Account state doesn't match the proof")]
+    StateMismatch,
+    #[error("Masterchain block is too old")]
+    BlockTooOld,
+}
+
+impl ProofError {
+    /// Only outdated blocks could be replaced by a newer response
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::BlockTooOld)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::str::FromStr;
+
+    use ed25519_dalek::Signer;
+    use nekoton_abi::TransactionId;
+    use ton_types::Cell;
+
+    use super::*;
+    use crate::core::models::ReliableBehavior;
+    use crate::transport::models::ExistingContract;
+
+    const VALIDATOR_COUNT: usize = 3;
+    const NOW: u32 = 1700000000;
+
+    struct TestChain {
+        keys: Vec<ed25519_dalek::Keypair>,
+        validator_set: ton_block::ValidatorSet,
+        catchain_config: ton_block::CatchainConfig,
+        config: ton_block::ConfigParams,
+        address: MsgAddressInt,
+        account: ton_block::Account,
+        state: Cell,
+    }
+
+    impl TestChain {
+        fn new() -> Result<Self> {
+            let keys = (0..VALIDATOR_COUNT)
+                .map(|i| {
+                    let secret = ed25519_dalek::SecretKey::from_bytes(&[i as u8 + 1; 32])?;
+                    let public = ed25519_dalek::PublicKey::from(&secret);
+                    Ok(ed25519_dalek::Keypair { secret, public })
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let mut validators = Vec::new();
+            for key in &keys {
+                validators.push(make_validator(key)?);
+            }
+            let validator_set =
+                ton_block::ValidatorSet::new(0, u32::MAX, VALIDATOR_COUNT as u16, validators)?;
+            let catchain_config = ton_block::CatchainConfig::default();
+
+            let mut config = ton_block::ConfigParams::new();
+            config.set_config(ton_block::ConfigParamEnum::ConfigParam28(
+                catchain_config.clone(),
+            ))?;
+            config.set_config(ton_block::ConfigParamEnum::ConfigParam34(
+                ton_block::ConfigParam34 {
+                    cur_validators: validator_set.clone(),
+                },
+            ))?;
+
+            let address = MsgAddressInt::from_str(
+                "-1:3333333333333333333333333333333333333333333333333333333333333333",
+            )?;
+            let account = ton_block::Account::with_address_and_ballance(
+                &address,
+                &ton_block::CurrencyCollection::with_grams(1_000_000_000),
+            );
+
+            let mut shard_state =
+                ton_block::ShardStateUnsplit::with_ident(ton_block::ShardIdent::masterchain());
+            shard_state.insert_account(
+                &account_id(&address),
+                &ton_block::ShardAccount::with_params(&account, UInt256::default(), 0)?,
+            )?;
+            let state = shard_state.serialize()?;
+
+            Ok(Self {
+                keys,
+                validator_set,
+                catchain_config,
+                config,
+                address,
+                account,
+                state,
+            })
+        }
+
+        fn make_key_block(
+            &self,
+            seqno: u32,
+            prev_key_block_seqno: u32,
+        ) -> Result<ton_block::Block> {
+            make_block(
+                seqno,
+                prev_key_block_seqno,
+                NOW,
+                Some(self.config.clone()),
+                &self.state,
+            )
+        }
+
+        fn make_mc_block(&self, seqno: u32, prev_key_block_seqno: u32) -> Result<ton_block::Block> {
+            self.make_mc_block_at(seqno, prev_key_block_seqno, NOW)
+        }
+
+        fn make_mc_block_at(
+            &self,
+            seqno: u32,
+            prev_key_block_seqno: u32,
+            gen_utime: u32,
+        ) -> Result<ton_block::Block> {
+            make_block(seqno, prev_key_block_seqno, gen_utime, None, &self.state)
+        }
+
+        fn make_verifier(&self) -> Result<ProofVerifier> {
+            ProofVerifier::new(
+                Arc::new(ConstClock::from_secs(NOW as u64)),
+                &self.make_key_block(1, 0)?,
+            )
+        }
+
+        /// Returns the block proof signed by the first `signers` validators
+        fn make_block_proof(
+            &self,
+            block: &ton_block::Block,
+            signers: usize,
+        ) -> Result<ton_block::BlockProof> {
+            let cell = block.serialize()?;
+            let file_hash = UInt256::from_slice(
+                sha2::Sha256::digest(&ton_types::serialize_toc(&cell)?).as_slice(),
+            );
+            let proof_for = ton_block::BlockIdExt::with_params(
+                ton_block::ShardIdent::masterchain(),
+                block.read_info()?.seq_no(),
+                cell.repr_hash(),
+                file_hash,
+            );
+
+            let (_, hash_short) = self.validator_set.calc_subset(
+                &self.catchain_config,
+                ton_block::SHARD_FULL,
+                ton_block::MASTERCHAIN_ID,
+                0,
+                Default::default(),
+            )?;
+
+            let mut data = Vec::with_capacity(4 + 32 + 32);
+            data.extend_from_slice(&BLOCK_SIGNATURE_MAGIC);
+            data.extend_from_slice(proof_for.root_hash.as_slice());
+            data.extend_from_slice(proof_for.file_hash.as_slice());
+
+            let mut pure_signatures = ton_block::BlockSignaturesPure::default();
+            for key in self.keys.iter().take(signers) {
+                let signature = key.sign(&data);
+                pure_signatures.add_sigpair(ton_block::CryptoSignaturePair::with_params(
+                    compute_node_id_short(&make_validator(key)?),
+                    ton_block::CryptoSignature::from_bytes(&signature.to_bytes())?,
+                ));
+            }
+
+            Ok(ton_block::BlockProof {
+                proof_for,
+                root: make_merkle_proof(&cell)?,
+                signatures: Some(ton_block::BlockSignatures::with_params(
+                    ton_block::ValidatorBaseInfo::with_params(hash_short, 0),
+                    pure_signatures,
+                )),
+            })
+        }
+
+        fn make_proof(&self, mc_block_proof: ton_block::BlockProof) -> Result<ContractStateProof> {
+            Ok(ContractStateProof {
+                key_block_proofs: Vec::new(),
+                mc_block_proof,
+                shard_block_proof: None,
+                state_proof: make_merkle_proof(&self.state)?,
+            })
+        }
+
+        fn contract_state(&self) -> RawContractState {
+            match &self.account {
+                ton_block::Account::Account(account) => {
+                    RawContractState::Exists(ExistingContract {
+                        account: account.clone(),
+                        timings: GenTimings::Unknown,
+                        last_transaction_id: LastTransactionId::Inexact { latest_lt: 0 },
+                    })
+                }
+                ton_block::Account::AccountNone => RawContractState::NotExists {
+                    timings: GenTimings::Unknown,
+                },
+            }
+        }
+    }
+
+    /// Returns the verifier and the signed state of the test account
+    pub(crate) fn make_state_with_proof(
+    ) -> Result<(ProofVerifier, MsgAddressInt, ContractStateWithProof)> {
+        let chain = TestChain::new()?;
+        let verifier = chain.make_verifier()?;
+
+        let mc_block = chain.make_mc_block(2, 1)?;
+        let proof = chain.make_proof(chain.make_block_proof(&mc_block, VALIDATOR_COUNT)?)?;
+
+        let state = ContractStateWithProof {
+            state: chain.contract_state(),
+            proof,
+        };
+        Ok((verifier, chain.address, state))
+    }
+
+    /// Backend which only returns the same state with proof
+    pub(crate) struct ProofTransport(pub ContractStateWithProof);
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl Transport for ProofTransport {
+        fn info(&self) -> TransportInfo {
+            TransportInfo {
+                max_transactions_per_fetch: 50,
+                max_accounts_per_fetch: 50,
+                reliable_behavior: ReliableBehavior::IntensivePolling,
+                has_key_blocks: false,
+            }
+        }
+
+        async fn send_message(&self, _: &ton_block::Message) -> Result<()> {
+            not_supported()
+        }
+
+        async fn get_contract_state(&self, _: &MsgAddressInt) -> Result<RawContractState> {
+            Ok(self.0.state.clone())
+        }
+
+        async fn get_contract_state_with_proof(
+            &self,
+            _: &MsgAddressInt,
+        ) -> Result<ContractStateWithProof> {
+            Ok(self.0.clone())
+        }
+
+        async fn get_accounts_by_code_hash(
+            &self,
+            _: &UInt256,
+            _: u8,
+            _: &Option<MsgAddressInt>,
+        ) -> Result<Vec<MsgAddressInt>> {
+            not_supported()
+        }
+
+        async fn get_transactions(
+            &self,
+            _: &MsgAddressInt,
+            _: u64,
+            _: u8,
+        ) -> Result<Vec<RawTransaction>> {
+            not_supported()
+        }
+
+        async fn get_transaction(&self, _: &UInt256) -> Result<Option<RawTransaction>> {
+            not_supported()
+        }
+
+        async fn get_dst_transaction(&self, _: &UInt256) -> Result<Option<RawTransaction>> {
+            not_supported()
+        }
+
+        async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+            not_supported()
+        }
+
+        async fn get_capabilities(&self, _: &dyn Clock) -> Result<NetworkCapabilities> {
+            not_supported()
+        }
+
+        async fn get_blockchain_config(
+            &self,
+            _: &dyn Clock,
+            _: bool,
+        ) -> Result<ton_executor::BlockchainConfig> {
+            not_supported()
+        }
+    }
+
+    fn not_supported<T>() -> Result<T> {
+        Err(TransportError::MethodNotSupported.into())
+    }
+
+    fn make_validator(key: &ed25519_dalek::Keypair) -> Result<ton_block::ValidatorDescr> {
+        Ok(ton_block::ValidatorDescr {
+            public_key: ton_block::SigPubKey::from_bytes(key.public.as_bytes())?,
+            weight: 1,
+            ..Default::default()
+        })
+    }
+
+    fn make_block(
+        seqno: u32,
+        prev_key_block_seqno: u32,
+        gen_utime: u32,
+        config: Option<ton_block::ConfigParams>,
+        state: &Cell,
+    ) -> Result<ton_block::Block> {
+        let mut info = ton_block::BlockInfo::default();
+        info.set_shard(ton_block::ShardIdent::masterchain());
+        info.set_seq_no(seqno)?;
+        info.set_gen_utime(ton_block::UnixTime32::new(gen_utime));
+        info.set_key_block(config.is_some());
+        info.set_prev_key_block_seqno(prev_key_block_seqno);
+
+        let mut mc_extra = ton_block::McBlockExtra::default();
+        *mc_extra.config_mut() = config;
+
+        let mut extra = ton_block::BlockExtra::default();
+        extra.write_custom(Some(&mc_extra))?;
+
+        let state_update = ton_block::MerkleUpdate::create(&Cell::default(), state)?;
+
+        ton_block::Block::with_params(
+            0,
+            info,
+            ton_block::ValueFlow::default(),
+            state_update,
+            extra,
+        )
+    }
+
+    fn make_merkle_proof(cell: &Cell) -> Result<Cell> {
+        ton_block::MerkleProof::create(cell, |_| true)?.serialize()
+    }
+
+    fn account_id(address: &MsgAddressInt) -> UInt256 {
+        UInt256::from_be_bytes(&address.address().get_bytestring(0))
+    }
+
+    fn proof_error(result: Result<()>) -> Option<ProofError> {
+        result.unwrap_err().downcast_ref::<ProofError>().copied()
+    }
+
+    #[test]
+    fn verifies_signed_state_proofs() -> Result<()> {
+        let chain = TestChain::new()?;
+
+        let verifier = chain.make_verifier()?;
+        assert_eq!(verifier.trusted_key_block_seqno(), 1);
+
+        let mc_block = chain.make_mc_block(2, 1)?;
+        let proof = chain.make_proof(chain.make_block_proof(&mc_block, VALIDATOR_COUNT)?)?;
+        verifier.verify(&chain.address, &chain.contract_state(), &proof)?;
+
+        // Missing accounts are verified too
+        let other = MsgAddressInt::from_str(
+            "-1:4444444444444444444444444444444444444444444444444444444444444444",
+        )?;
+        let not_exists = RawContractState::NotExists {
+            timings: GenTimings::Unknown,
+        };
+        verifier.verify(&other, &not_exists, &proof)?;
+
+        // Newer key blocks are trusted after their signatures are checked
+        let key_block = chain.make_key_block(3, 1)?;
+        let mc_block = chain.make_mc_block(4, 3)?;
+        let mut proof = chain.make_proof(chain.make_block_proof(&mc_block, VALIDATOR_COUNT)?)?;
+        proof.key_block_proofs = vec![chain.make_block_proof(&key_block, VALIDATOR_COUNT)?];
+        verifier.verify(&chain.address, &chain.contract_state(), &proof)?;
+        assert_eq!(verifier.trusted_key_block_seqno(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_tampered_proofs() -> Result<()> {
+        let chain = TestChain::new()?;
+        let verifier = chain.make_verifier()?;
+
+        let mc_block = chain.make_mc_block(2, 1)?;
+        let mc_block_proof = chain.make_block_proof(&mc_block, VALIDATOR_COUNT)?;
+        let proof = chain.make_proof(mc_block_proof.clone())?;
+
+        // Different account state
+        let mut state = chain.contract_state();
+        if let RawContractState::Exists(contract) = &mut state {
+            contract.account.storage.balance = ton_block::CurrencyCollection::with_grams(1);
+        }
+        assert!(matches!(
+            proof_error(verifier.verify(&chain.address, &state, &proof)),
+            Some(ProofError::StateMismatch)
+        ));
+
+        // Proof of a state which is not referenced by the block
+        let mut proof_without_account = proof.clone();
+        proof_without_account.state_proof = make_merkle_proof(
+            &ton_block::ShardStateUnsplit::with_ident(ton_block::ShardIdent::masterchain())
+                .serialize()?,
+        )?;
+        assert!(matches!(
+            proof_error(verifier.verify(
+                &chain.address,
+                &chain.contract_state(),
+                &proof_without_account
+            )),
+            Some(ProofError::InvalidStateProof)
+        ));
+
+        // Block proof for another block
+        let mut tampered = mc_block_proof.clone();
+        tampered.root = make_merkle_proof(&chain.make_mc_block(3, 1)?.serialize()?)?;
+        assert!(matches!(
+            proof_error(verifier.verify(
+                &chain.address,
+                &chain.contract_state(),
+                &chain.make_proof(tampered)?
+            )),
+            Some(ProofError::InvalidBlockProof)
+        ));
+
+        // Signatures of another block id
+        let mut tampered = mc_block_proof;
+        tampered.proof_for.file_hash = UInt256::default();
+        assert!(matches!(
+            proof_error(verifier.verify(
+                &chain.address,
+                &chain.contract_state(),
+                &chain.make_proof(tampered)?
+            )),
+            Some(ProofError::NotEnoughSignatures)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_redirected_and_stale_states() -> Result<()> {
+        let chain = TestChain::new()?;
+        let verifier = chain.make_verifier()?;
+
+        let mc_block = chain.make_mc_block(2, 1)?;
+        let proof = chain.make_proof(chain.make_block_proof(&mc_block, VALIDATOR_COUNT)?)?;
+
+        // Timings and the last transaction id of the proven state are accepted
+        let mut state = chain.contract_state();
+        if let RawContractState::Exists(contract) = &mut state {
+            contract.timings = GenTimings::Known {
+                gen_lt: 0,
+                gen_utime: 0,
+            };
+            contract.last_transaction_id = LastTransactionId::Exact(TransactionId {
+                lt: 0,
+                hash: UInt256::default(),
+            });
+        }
+        verifier.verify(&chain.address, &state, &proof)?;
+
+        // Last transaction id which points to another history
+        let mut redirected = state.clone();
+        if let RawContractState::Exists(contract) = &mut redirected {
+            contract.last_transaction_id = LastTransactionId::Exact(TransactionId {
+                lt: 0,
+                hash: UInt256::from([1; 32]),
+            });
+        }
+        assert!(matches!(
+            proof_error(verifier.verify(&chain.address, &redirected, &proof)),
+            Some(ProofError::StateMismatch)
+        ));
+
+        let mut redirected = state.clone();
+        if let RawContractState::Exists(contract) = &mut redirected {
+            contract.last_transaction_id = LastTransactionId::Inexact { latest_lt: 10 };
+        }
+        assert!(matches!(
+            proof_error(verifier.verify(&chain.address, &redirected, &proof)),
+            Some(ProofError::StateMismatch)
+        ));
+
+        // Timings of another state
+        let mut retimed = state;
+        if let RawContractState::Exists(contract) = &mut retimed {
+            contract.timings = GenTimings::Known {
+                gen_lt: 10,
+                gen_utime: NOW,
+            };
+        }
+        assert!(matches!(
+            proof_error(verifier.verify(&chain.address, &retimed, &proof)),
+            Some(ProofError::StateMismatch)
+        ));
+
+        // Correctly signed but outdated block
+        let old_block = chain.make_mc_block_at(2, 1, NOW - 3600)?;
+        let proof = chain.make_proof(chain.make_block_proof(&old_block, VALIDATOR_COUNT)?)?;
+        assert!(matches!(
+            proof_error(verifier.verify(&chain.address, &chain.contract_state(), &proof)),
+            Some(ProofError::BlockTooOld)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_not_enough_signatures() -> Result<()> {
+        let chain = TestChain::new()?;
+        let verifier = chain.make_verifier()?;
+
+        // 2 of 3 validators with equal weights is exactly 2/3, which is not enough
+        let mc_block = chain.make_mc_block(2, 1)?;
+        let proof = chain.make_proof(chain.make_block_proof(&mc_block, VALIDATOR_COUNT - 1)?)?;
+        assert!(matches!(
+            proof_error(verifier.verify(&chain.address, &chain.contract_state(), &proof)),
+            Some(ProofError::NotEnoughSignatures)
+        ));
+
+        // Untrusted key blocks are rejected
+        let key_block = chain.make_key_block(3, 1)?;
+        let mut proof = chain.make_proof(chain.make_block_proof(&mc_block, VALIDATOR_COUNT)?)?;
+        proof.key_block_proofs = vec![chain.make_block_proof(&key_block, 1)?];
+        assert!(matches!(
+            proof_error(verifier.verify(&chain.address, &chain.contract_state(), &proof)),
+            Some(ProofError::NotEnoughSignatures)
+        ));
+        assert_eq!(verifier.trusted_key_block_seqno(), 1);
+
+        Ok(())
+    }
+}
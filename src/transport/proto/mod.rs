@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Result;
 use ton_block::{Block, Deserializable, MsgAddressInt, Serializable};
@@ -11,11 +12,15 @@ use nekoton_utils::*;
 
 use crate::core::models::{NetworkCapabilities, ReliableBehavior};
 use crate::external::{self, ProtoConnection};
-use crate::transport::models::{ExistingContract, PollContractState};
+use crate::transport::models::{ExistingContract, LatestBlock, PollContractState};
 
+use super::long_poll::long_poll_accounts;
 use super::models::{RawContractState, RawTransaction};
+use super::proof::{ContractStateProof, ContractStateWithProof};
 use super::utils::*;
-use super::{Transport, TransportInfo};
+use super::{AccountUpdatesStream, Transport, TransportError, TransportInfo};
+
+pub mod server;
 
 pub struct ProtoTransport {
     connection: Arc<dyn ProtoConnection>,
@@ -39,7 +44,8 @@ impl Transport for ProtoTransport {
     fn info(&self) -> TransportInfo {
         TransportInfo {
             max_transactions_per_fetch: 50,
-            reliable_behavior: ReliableBehavior::IntensivePolling,
+            max_accounts_per_fetch: 50,
+            reliable_behavior: ReliableBehavior::BlockWalking,
             has_key_blocks: true,
         }
     }
@@ -56,7 +62,18 @@ impl Transport for ProtoTransport {
             requires_db: false,
         };
 
-        let data = self.connection.post(req).await?;
+        let data = match self.connection.post(req).await {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(match e.downcast::<ProtoClientError>() {
+                    Ok(ProtoClientError::ErrorResponse { message, .. }) => {
+                        TransportError::MessageRejected(message).into()
+                    }
+                    Ok(e) => e.into(),
+                    Err(e) => e,
+                })
+            }
+        };
         let response = rpc::Response::decode(Bytes::from(data))?;
 
         match response.result {
@@ -141,6 +158,56 @@ impl Transport for ProtoTransport {
         Ok(result)
     }
 
+    async fn get_contract_state_with_proof(
+        &self,
+        address: &MsgAddressInt,
+    ) -> Result<ContractStateWithProof> {
+        let data = rpc::Request {
+            call: Some(rpc::request::Call::GetContractStateWithProof(
+                rpc::request::GetContractStateWithProof {
+                    address: utils::addr_to_bytes(address),
+                },
+            )),
+        };
+
+        let req = external::ProtoRequest {
+            data: data.encode_to_vec(),
+            requires_db: false,
+        };
+
+        let data = self.connection.post(req).await?;
+        let response = rpc::Response::decode(Bytes::from(data))?;
+
+        match response.result {
+            Some(rpc::response::Result::GetContractStateWithProof(response)) => {
+                let state = response.state.ok_or(ProtoClientError::InvalidResponse)?;
+                let state = parse_contract_state(state)?
+                    .to_changed()
+                    .map_err(|_| ProtoClientError::InvalidResponse)?;
+
+                Ok(ContractStateWithProof {
+                    state,
+                    proof: ContractStateProof {
+                        key_block_proofs: response
+                            .key_block_proofs
+                            .iter()
+                            .map(|bytes| ton_block::BlockProof::construct_from_bytes(bytes))
+                            .collect::<Result<_>>()?,
+                        mc_block_proof: ton_block::BlockProof::construct_from_bytes(
+                            &response.mc_block_proof,
+                        )?,
+                        shard_block_proof: response
+                            .shard_block_proof
+                            .map(decode_cell)
+                            .transpose()?,
+                        state_proof: decode_cell(response.state_proof)?,
+                    },
+                })
+            }
+            _ => Err(ProtoClientError::InvalidResponse.into()),
+        }
+    }
+
     async fn get_accounts_by_code_hash(
         &self,
         code_hash: &ton_types::UInt256,
@@ -286,6 +353,99 @@ impl Transport for ProtoTransport {
         }
     }
 
+    async fn get_latest_block(&self, address: &MsgAddressInt) -> Result<LatestBlock> {
+        let data = rpc::Request {
+            call: Some(rpc::request::Call::GetLatestBlock(
+                rpc::request::GetLatestBlock {
+                    address: utils::addr_to_bytes(address),
+                },
+            )),
+        };
+
+        let req = external::ProtoRequest {
+            data: data.encode_to_vec(),
+            requires_db: false,
+        };
+
+        let data = self.connection.post(req).await?;
+        let response = rpc::Response::decode(Bytes::from(data))?;
+
+        match response.result {
+            Some(rpc::response::Result::GetLatestBlock(block)) => Ok(LatestBlock {
+                id: hex::encode(block.id),
+                end_lt: block.end_lt,
+                gen_utime: block.gen_utime,
+            }),
+            _ => Err(ProtoClientError::InvalidResponse.into()),
+        }
+    }
+
+    async fn get_block(&self, id: &str) -> Result<Block> {
+        let data = rpc::Request {
+            call: Some(rpc::request::Call::GetBlock(rpc::request::GetBlock {
+                id: hex::decode(id)?.into(),
+            })),
+        };
+
+        let req = external::ProtoRequest {
+            data: data.encode_to_vec(),
+            requires_db: true,
+        };
+
+        let data = self.connection.post(req).await?;
+        parse_block_response(rpc::Response::decode(Bytes::from(data))?)
+    }
+
+    async fn get_block_by_seqno(&self, shard: &ton_block::ShardIdent, seqno: u32) -> Result<Block> {
+        let data = rpc::Request {
+            call: Some(rpc::request::Call::GetBlockBySeqno(
+                rpc::request::GetBlockBySeqno {
+                    workchain: shard.workchain_id(),
+                    shard: shard.shard_prefix_with_tag(),
+                    seqno,
+                },
+            )),
+        };
+
+        let req = external::ProtoRequest {
+            data: data.encode_to_vec(),
+            requires_db: true,
+        };
+
+        let data = self.connection.post(req).await?;
+        parse_block_response(rpc::Response::decode(Bytes::from(data))?)
+    }
+
+    async fn wait_for_next_block(
+        &self,
+        current: &str,
+        address: &MsgAddressInt,
+        timeout: Duration,
+    ) -> Result<String> {
+        let data = rpc::Request {
+            call: Some(rpc::request::Call::WaitForNextBlock(
+                rpc::request::WaitForNextBlock {
+                    current: hex::decode(current)?.into(),
+                    address: utils::addr_to_bytes(address),
+                    timeout_ms: timeout.as_millis() as u64,
+                },
+            )),
+        };
+
+        let req = external::ProtoRequest {
+            data: data.encode_to_vec(),
+            requires_db: false,
+        };
+
+        let data = self.connection.post(req).await?;
+        let response = rpc::Response::decode(Bytes::from(data))?;
+
+        match response.result {
+            Some(rpc::response::Result::WaitForNextBlock(block)) => Ok(hex::encode(block.id)),
+            _ => Err(ProtoClientError::InvalidResponse.into()),
+        }
+    }
+
     async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
         let (capabilities, _) = self
             .config_cache
@@ -305,6 +465,12 @@ impl Transport for ProtoTransport {
             .await?;
         Ok(config)
     }
+
+    fn subscribe_accounts(&self, addresses: &[MsgAddressInt]) -> Option<AccountUpdatesStream> {
+        // NOTE: the stream must own its transport
+        let transport = ProtoTransport::new(self.connection.clone());
+        Some(long_poll_accounts(transport, addresses))
+    }
 }
 
 fn decode_raw_transaction(bytes: Bytes) -> Result<RawTransaction> {
@@ -314,11 +480,29 @@ fn decode_raw_transaction(bytes: Bytes) -> Result<RawTransaction> {
     Ok(RawTransaction { hash, data })
 }
 
+fn decode_cell(bytes: Bytes) -> Result<ton_types::Cell> {
+    ton_types::deserialize_tree_of_cells(&mut bytes.as_ref())
+}
+
+fn parse_block_response(response: rpc::Response) -> Result<Block> {
+    match response.result {
+        Some(rpc::response::Result::GetBlock(block)) => {
+            Ok(Block::construct_from_bytes(block.block.as_ref())?)
+        }
+        _ => Err(ProtoClientError::InvalidResponse.into()),
+    }
+}
+
 fn parse_response(response: rpc::Response) -> Result<PollContractState> {
-    let result = match response.result {
-        Some(rpc::response::Result::GetContractState(rpc::response::GetContractState {
-            state: Some(state),
-        })) => match state {
+    match response.result {
+        Some(rpc::response::Result::GetContractState(state)) => parse_contract_state(state),
+        _ => Err(ProtoClientError::InvalidResponse.into()),
+    }
+}
+
+fn parse_contract_state(state: rpc::response::GetContractState) -> Result<PollContractState> {
+    let result = match state.state {
+        Some(state) => match state {
             rpc::response::get_contract_state::State::Exists(state) => {
                 let account = utils::deserialize_account_stuff(&state.account)?;
 
@@ -359,16 +543,37 @@ fn parse_response(response: rpc::Response) -> Result<PollContractState> {
                 }
             }
         },
-        _ => return Err(ProtoClientError::InvalidResponse.into()),
+        None => return Err(ProtoClientError::InvalidResponse.into()),
     };
 
     Ok(result)
 }
 
-#[derive(thiserror::Error, Copy, Clone, Debug)]
+#[derive(thiserror::Error, Clone, Debug)]
 pub enum ProtoClientError {
     #[error("Failed to parse response")]
     InvalidResponse,
+    /// [`rpc::Error`] sent by the server with `422 Unprocessable Entity` status.
+    ///
+    /// Connections should return it to separate server errors from network failures
+    #[error("{message}")]
+    ErrorResponse { code: i32, message: String },
+}
+
+impl ProtoClientError {
+    /// NOTE: broken responses are usually produced by proxies
+    pub fn is_retryable(&self) -> bool {
+        true
+    }
+}
+
+impl From<rpc::Error> for ProtoClientError {
+    fn from(error: rpc::Error) -> Self {
+        Self::ErrorResponse {
+            code: error.code,
+            message: error.message,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -376,6 +581,7 @@ mod tests {
     use std::str::FromStr;
 
     use futures_util::StreamExt;
+    use parking_lot::Mutex;
 
     use super::*;
 
@@ -424,6 +630,117 @@ mod tests {
         Ok(())
     }
 
+    /// Answers block requests and records them
+    struct BlocksConnection {
+        block: Bytes,
+        requests: Mutex<Vec<rpc::request::Call>>,
+    }
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl ProtoConnection for BlocksConnection {
+        async fn post(&self, req: external::ProtoRequest) -> Result<Vec<u8>> {
+            use rpc::request::Call;
+            use rpc::response::Result as Response;
+
+            let call = rpc::Request::decode(req.data.as_slice())?
+                .call
+                .ok_or(ProtoClientError::InvalidResponse)?;
+
+            let result = match &call {
+                Call::GetLatestBlock(_) => {
+                    Response::GetLatestBlock(rpc::response::GetLatestBlock {
+                        id: vec![0xaa; 32].into(),
+                        end_lt: 123,
+                        gen_utime: 456,
+                    })
+                }
+                Call::GetBlock(_) | Call::GetBlockBySeqno(_) => {
+                    Response::GetBlock(rpc::response::GetBlock {
+                        block: self.block.clone(),
+                    })
+                }
+                Call::WaitForNextBlock(_) => {
+                    Response::WaitForNextBlock(rpc::response::WaitForNextBlock {
+                        id: vec![0xbb; 32].into(),
+                    })
+                }
+                _ => anyhow::bail!("unexpected request"),
+            };
+            self.requests.lock().push(call);
+
+            Ok(rpc::Response {
+                result: Some(result),
+            }
+            .encode_to_vec())
+        }
+    }
+
+    #[tokio::test]
+    async fn encodes_block_requests() -> Result<()> {
+        use rpc::request::Call;
+
+        let address = MsgAddressInt::from_str(
+            "0:3333333333333333333333333333333333333333333333333333333333333333",
+        )?;
+        let block = Block::default();
+        let block_hash = block.serialize()?.repr_hash();
+
+        let connection = Arc::new(BlocksConnection {
+            block: block.write_to_bytes()?.into(),
+            requests: Default::default(),
+        });
+        let transport = ProtoTransport::new(connection.clone());
+        assert_eq!(
+            transport.info().reliable_behavior,
+            ReliableBehavior::BlockWalking
+        );
+
+        let current = "aa".repeat(32);
+
+        let latest = transport.get_latest_block(&address).await?;
+        assert_eq!(latest.id, current);
+        assert_eq!(latest.end_lt, 123);
+        assert_eq!(latest.gen_utime, 456);
+
+        let block = transport.get_block(&current).await?;
+        assert_eq!(block.serialize()?.repr_hash(), block_hash);
+
+        let shard = ton_block::ShardIdent::with_tagged_prefix(0, 0x8000_0000_0000_0000)?;
+        let block = transport.get_block_by_seqno(&shard, 10).await?;
+        assert_eq!(block.serialize()?.repr_hash(), block_hash);
+
+        let next = transport
+            .wait_for_next_block(&current, &address, Duration::from_secs(5))
+            .await?;
+        assert_eq!(next, "bb".repeat(32));
+
+        let requests = connection.requests.lock();
+        assert_eq!(requests.len(), 4);
+        assert!(matches!(
+            &requests[0],
+            Call::GetLatestBlock(request) if request.address == utils::addr_to_bytes(&address)
+        ));
+        assert!(matches!(
+            &requests[1],
+            Call::GetBlock(request) if request.id.as_ref() == [0xaa; 32]
+        ));
+        assert!(matches!(
+            &requests[2],
+            Call::GetBlockBySeqno(request) if request.workchain == 0
+                && request.shard == 0x8000_0000_0000_0000
+                && request.seqno == 10
+        ));
+        assert!(matches!(
+            &requests[3],
+            Call::WaitForNextBlock(request) if request.current.as_ref() == [0xaa; 32]
+                && request.address == utils::addr_to_bytes(&address)
+                && request.timeout_ms == 5000
+        ));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_connection() -> Result<()> {
         let transport = ProtoTransport::new(Arc::new(reqwest::Client::new()));
@@ -0,0 +1,431 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use ton_block::{Block, Deserializable, Serializable};
+use ton_types::UInt256;
+
+use nekoton_proto::prost::{bytes::Bytes, Message};
+use nekoton_proto::protos::rpc;
+use nekoton_proto::utils;
+
+use nekoton_utils::*;
+
+use crate::external::{self, ProtoConnection};
+use crate::transport::models::{PollContractState, RawTransaction};
+use crate::transport::Transport;
+
+/// Server side of the protobuf RPC schema.
+///
+/// Decodes [`rpc::Request`], calls the backend and encodes [`rpc::Response`].
+/// Errors must be sent with `422 Unprocessable Entity` status.
+///
+/// Also implements [`ProtoConnection`], so the backend can be used through
+/// [`ProtoTransport`](super::ProtoTransport) without a network.
+pub struct ProtoServer {
+    backend: Arc<dyn Transport>,
+    clock: Arc<dyn Clock>,
+}
+
+impl ProtoServer {
+    pub fn new(backend: Arc<dyn Transport>, clock: Arc<dyn Clock>) -> Self {
+        Self { backend, clock }
+    }
+
+    /// Handles the encoded request and returns the encoded response
+    pub async fn handle(&self, data: &[u8]) -> Result<Vec<u8>, rpc::Error> {
+        let request = rpc::Request::decode(data)
+            .map_err(|e| make_error(ProtoServerError::InvalidRequest, e))?;
+        let response = self.dispatch(request).await?;
+        Ok(response.encode_to_vec())
+    }
+
+    pub async fn dispatch(&self, request: rpc::Request) -> Result<rpc::Response, rpc::Error> {
+        use rpc::request::Call;
+        use rpc::response::Result as Response;
+
+        let call = request
+            .call
+            .ok_or_else(|| make_error(ProtoServerError::InvalidRequest, "empty call"))?;
+
+        let result = match call {
+            Call::GetCapabilities(()) | Call::GetTimings(()) => {
+                return Err(make_error(
+                    ProtoServerError::MethodNotSupported,
+                    "method is not supported",
+                ))
+            }
+            Call::GetStatus(()) => Response::GetStatus(rpc::response::GetStatus { ready: true }),
+            Call::GetLatestKeyBlock(()) => {
+                let block = self
+                    .backend
+                    .get_latest_key_block()
+                    .await
+                    .map_err(backend_error)?;
+                Response::GetLatestKeyBlock(rpc::response::GetLatestKeyBlock {
+                    block: encode_block(&block)?,
+                })
+            }
+            Call::GetBlockchainConfig(()) => {
+                let clock = self.clock.as_ref();
+                let capabilities = self
+                    .backend
+                    .get_capabilities(clock)
+                    .await
+                    .map_err(backend_error)?;
+                let config = self
+                    .backend
+                    .get_blockchain_config(clock, false)
+                    .await
+                    .map_err(backend_error)?;
+                Response::GetBlockchainConfig(rpc::response::GetBlockchainConfig {
+                    global_id: capabilities.global_id,
+                    config: config
+                        .raw_config()
+                        .write_to_bytes()
+                        .map_err(backend_error)?
+                        .into(),
+                })
+            }
+            Call::GetContractState(request) => {
+                let address = decode_address(&request.address)?;
+                let state = match request.last_transaction_lt {
+                    Some(lt) => self.backend.poll_contract_state(&address, lt).await,
+                    None => self
+                        .backend
+                        .get_contract_state(&address)
+                        .await
+                        .map(Into::into),
+                }
+                .map_err(backend_error)?;
+                Response::GetContractState(encode_contract_state(state)?)
+            }
+            Call::GetContractStateWithProof(request) => {
+                let address = decode_address(&request.address)?;
+                let state = self
+                    .backend
+                    .get_contract_state_with_proof(&address)
+                    .await
+                    .map_err(backend_error)?;
+                let proof = &state.proof;
+                Response::GetContractStateWithProof(rpc::response::GetContractStateWithProof {
+                    state: Some(encode_contract_state(state.state.clone().into())?),
+                    key_block_proofs: proof
+                        .key_block_proofs
+                        .iter()
+                        .map(encode_block_proof)
+                        .collect::<Result<_, _>>()?,
+                    mc_block_proof: encode_block_proof(&proof.mc_block_proof)?,
+                    shard_block_proof: proof
+                        .shard_block_proof
+                        .as_ref()
+                        .map(encode_cell)
+                        .transpose()?,
+                    state_proof: encode_cell(&proof.state_proof)?,
+                })
+            }
+            Call::GetTransaction(request) => {
+                let transaction = self
+                    .backend
+                    .get_transaction(&decode_hash(&request.id)?)
+                    .await
+                    .map_err(backend_error)?;
+                Response::GetRawTransaction(rpc::response::GetRawTransaction {
+                    transaction: transaction.as_ref().map(encode_transaction).transpose()?,
+                })
+            }
+            Call::GetDstTransaction(request) => {
+                let transaction = self
+                    .backend
+                    .get_dst_transaction(&decode_hash(&request.message_hash)?)
+                    .await
+                    .map_err(backend_error)?;
+                Response::GetRawTransaction(rpc::response::GetRawTransaction {
+                    transaction: transaction.as_ref().map(encode_transaction).transpose()?,
+                })
+            }
+            Call::GetTransactionsList(request) => {
+                let address = decode_address(&request.account)?;
+                let transactions = self
+                    .backend
+                    .get_transactions(
+                        &address,
+                        request.last_transaction_lt.unwrap_or(u64::MAX),
+                        request.limit.min(u8::MAX as u32) as u8,
+                    )
+                    .await
+                    .map_err(backend_error)?;
+                Response::GetTransactionsList(rpc::response::GetTransactionsList {
+                    transactions: transactions
+                        .iter()
+                        .map(encode_transaction)
+                        .collect::<Result<_, _>>()?,
+                })
+            }
+            Call::GetAccountsByCodeHash(request) => {
+                let continuation = request
+                    .continuation
+                    .as_ref()
+                    .map(decode_address)
+                    .transpose()?;
+                let accounts = self
+                    .backend
+                    .get_accounts_by_code_hash(
+                        &decode_hash(&request.code_hash)?,
+                        request.limit.min(u8::MAX as u32) as u8,
+                        &continuation,
+                    )
+                    .await
+                    .map_err(backend_error)?;
+                Response::GetAccounts(rpc::response::GetAccountsByCodeHash {
+                    account: accounts.iter().map(utils::addr_to_bytes).collect(),
+                })
+            }
+            Call::SendMessage(request) => {
+                let message = ton_block::Message::construct_from_bytes(&request.message)
+                    .map_err(|e| make_error(ProtoServerError::InvalidRequest, e))?;
+                self.backend
+                    .send_message(&message)
+                    .await
+                    .map_err(backend_error)?;
+                Response::SendMessage(())
+            }
+            Call::GetLatestBlock(request) => {
+                let address = decode_address(&request.address)?;
+                let block = self
+                    .backend
+                    .get_latest_block(&address)
+                    .await
+                    .map_err(backend_error)?;
+                Response::GetLatestBlock(rpc::response::GetLatestBlock {
+                    id: decode_hex_hash(&block.id)?,
+                    end_lt: block.end_lt,
+                    gen_utime: block.gen_utime,
+                })
+            }
+            Call::GetBlock(request) => {
+                let block = self
+                    .backend
+                    .get_block(&hex::encode(&request.id))
+                    .await
+                    .map_err(backend_error)?;
+                Response::GetBlock(rpc::response::GetBlock {
+                    block: encode_block(&block)?,
+                })
+            }
+            Call::GetBlockBySeqno(request) => {
+                let shard =
+                    ton_block::ShardIdent::with_tagged_prefix(request.workchain, request.shard)
+                        .map_err(|e| make_error(ProtoServerError::InvalidRequest, e))?;
+                let block = self
+                    .backend
+                    .get_block_by_seqno(&shard, request.seqno)
+                    .await
+                    .map_err(backend_error)?;
+                Response::GetBlock(rpc::response::GetBlock {
+                    block: encode_block(&block)?,
+                })
+            }
+            Call::WaitForNextBlock(request) => {
+                let address = decode_address(&request.address)?;
+                let id = self
+                    .backend
+                    .wait_for_next_block(
+                        &hex::encode(&request.current),
+                        &address,
+                        Duration::from_millis(request.timeout_ms),
+                    )
+                    .await
+                    .map_err(backend_error)?;
+                Response::WaitForNextBlock(rpc::response::WaitForNextBlock {
+                    id: decode_hex_hash(&id)?,
+                })
+            }
+        };
+
+        Ok(rpc::Response {
+            result: Some(result),
+        })
+    }
+}
+
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl ProtoConnection for ProtoServer {
+    async fn post(&self, req: external::ProtoRequest) -> Result<Vec<u8>> {
+        self.handle(&req.data)
+            .await
+            .map_err(|e| super::ProtoClientError::from(e).into())
+    }
+}
+
+fn encode_contract_state(
+    state: PollContractState,
+) -> Result<rpc::response::GetContractState, rpc::Error> {
+    use rpc::response::get_contract_state::{Exists, State};
+
+    let state = match state {
+        PollContractState::Unchanged { timings } => State::Unchanged(timings.into()),
+        PollContractState::NotExists { timings } => State::NotExists(timings.into()),
+        PollContractState::Exists(contract) => State::Exists(Exists {
+            account: utils::serialize_account_stuff(&contract.account).map_err(backend_error)?,
+            gen_timings: Some(contract.timings.into()),
+            last_transaction_id: Some(contract.last_transaction_id.into()),
+        }),
+    };
+
+    Ok(rpc::response::GetContractState { state: Some(state) })
+}
+
+fn encode_transaction(transaction: &RawTransaction) -> Result<Bytes, rpc::Error> {
+    transaction
+        .data
+        .write_to_bytes()
+        .map(Bytes::from)
+        .map_err(backend_error)
+}
+
+fn encode_block(block: &Block) -> Result<Bytes, rpc::Error> {
+    block
+        .write_to_bytes()
+        .map(Bytes::from)
+        .map_err(backend_error)
+}
+
+fn encode_block_proof(proof: &ton_block::BlockProof) -> Result<Bytes, rpc::Error> {
+    proof
+        .write_to_bytes()
+        .map(Bytes::from)
+        .map_err(backend_error)
+}
+
+fn encode_cell(cell: &ton_types::Cell) -> Result<Bytes, rpc::Error> {
+    ton_types::serialize_toc(cell)
+        .map(Bytes::from)
+        .map_err(backend_error)
+}
+
+fn decode_address(bytes: &Bytes) -> Result<ton_block::MsgAddressInt, rpc::Error> {
+    utils::bytes_to_addr(bytes).map_err(|e| make_error(ProtoServerError::InvalidRequest, e))
+}
+
+fn decode_hash(bytes: &Bytes) -> Result<UInt256, rpc::Error> {
+    match <[u8; 32]>::try_from(bytes.as_ref()) {
+        Ok(hash) => Ok(UInt256::from(hash)),
+        Err(_) => Err(make_error(ProtoServerError::InvalidRequest, "invalid hash")),
+    }
+}
+
+fn decode_hex_hash(id: &str) -> Result<Bytes, rpc::Error> {
+    hex::decode(id).map(Bytes::from).map_err(backend_error)
+}
+
+fn backend_error<E: std::fmt::Display>(error: E) -> rpc::Error {
+    make_error(ProtoServerError::Backend, error)
+}
+
+fn make_error<E: std::fmt::Display>(code: ProtoServerError, error: E) -> rpc::Error {
+    rpc::Error {
+        code: code as i32,
+        message: error.to_string(),
+    }
+}
+
+/// Error codes of [`rpc::Error`], the same as in JSON-RPC
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(i32)]
+pub enum ProtoServerError {
+    InvalidRequest = -32600,
+    MethodNotSupported = -32601,
+    Backend = -32000,
+}
+
+#[cfg(test)]
+#[cfg(feature = "local_transport")]
+mod tests {
+    use ton_block::GetRepresentationHash;
+
+    use super::*;
+    use crate::transport::local::tests::{dst_address, make_transfer};
+    use crate::transport::local::LocalTransport;
+    use crate::transport::models::RawContractState;
+    use crate::transport::proof::tests::{make_state_with_proof, ProofTransport};
+    use crate::transport::proto::ProtoTransport;
+
+    #[tokio::test]
+    async fn proto_transport_works_through_server() -> Result<()> {
+        let clock = Arc::new(ConstClock::from_secs(1700000000));
+        let backend = Arc::new(LocalTransport::new(clock.clone()));
+        let transport = ProtoTransport::new(Arc::new(ProtoServer::new(backend, clock)));
+
+        let dst = dst_address();
+        let message = make_transfer(1_000_000_000);
+        transport.send_message(&message).await?;
+
+        let contract = match transport.get_contract_state(&dst).await? {
+            RawContractState::Exists(contract) => contract,
+            RawContractState::NotExists { .. } => anyhow::bail!("account not found"),
+        };
+
+        let transactions = transport.get_transactions(&dst, u64::MAX, 10).await?;
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].data.lt, contract.last_transaction_id.lt());
+
+        let transaction = transport
+            .get_dst_transaction(&message.hash()?)
+            .await?
+            .expect("transaction not found");
+        assert_eq!(transaction.hash, transactions[0].hash);
+
+        // Known states are polled
+        assert!(matches!(
+            transport.get_contract_state(&dst).await?,
+            RawContractState::Exists(_)
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn proto_transport_returns_state_proofs() -> Result<()> {
+        let clock = Arc::new(ConstClock::from_secs(1700000000));
+        let (verifier, address, state) = make_state_with_proof()?;
+        let backend = Arc::new(ProofTransport(state));
+        let transport = ProtoTransport::new(Arc::new(ProtoServer::new(backend, clock)));
+
+        let state = transport.get_contract_state_with_proof(&address).await?;
+        assert!(matches!(state.state, RawContractState::Exists(_)));
+        verifier.verify(&address, &state.state, &state.proof)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn proto_transport_long_polls_accounts() -> Result<()> {
+        use futures_util::StreamExt;
+
+        let clock = Arc::new(ConstClock::from_secs(1700000000));
+        let backend = Arc::new(LocalTransport::new(clock.clone()));
+        let transport = ProtoTransport::new(Arc::new(ProtoServer::new(backend.clone(), clock)));
+
+        let dst = dst_address();
+        let message = make_transfer(1_000_000_000);
+
+        let mut updates = transport
+            .subscribe_accounts(std::slice::from_ref(&dst))
+            .unwrap();
+
+        // NOTE: the stream is polled first, so it remembers the initial state
+        // and waits for the next block before the message is sent
+        let (update, sent) =
+            futures_util::future::join(updates.next(), backend.send_message(&message)).await;
+        sent?;
+
+        let update = update.unwrap()?;
+        assert_eq!(update.address, dst);
+        assert_eq!(update.transactions.len(), 1);
+        assert!(matches!(update.state, RawContractState::Exists(_)));
+
+        Ok(())
+    }
+}
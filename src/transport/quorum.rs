@@ -0,0 +1,584 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use ton_block::{MsgAddressInt, Serializable};
+
+use nekoton_utils::*;
+
+use crate::core::models::NetworkCapabilities;
+
+use super::models::{
+    ContractStateAt, LatestBlock, PollContractState, RawContractState, RawTransaction,
+};
+use super::proof::ContractStateWithProof;
+use super::{Transport, TransportInfo};
+
+/// Transport which cross-checks responses of several transports.
+///
+/// Account states, transactions and blocks are requested from all transports
+/// at once. The response is accepted as soon as `quorum` transports returned
+/// the same data, other transports are not awaited. Transports which lag behind
+/// the others are ignored, so different responses are reported only when
+/// no response reached the quorum.
+pub struct QuorumTransport {
+    transports: Vec<Arc<dyn Transport>>,
+    quorum: usize,
+}
+
+impl QuorumTransport {
+    pub fn new(transports: Vec<Arc<dyn Transport>>, quorum: usize) -> Result<Self> {
+        if quorum == 0 || quorum > transports.len() {
+            return Err(QuorumTransportError::InvalidQuorum {
+                quorum,
+                total: transports.len(),
+            }
+            .into());
+        }
+
+        Ok(Self { transports, quorum })
+    }
+
+    pub fn quorum(&self) -> usize {
+        self.quorum
+    }
+
+    async fn query_verified<'a, T, F, Fut, H>(&'a self, f: F, fingerprint: H) -> Result<T>
+    where
+        F: Fn(&'a dyn Transport) -> Fut,
+        Fut: Future<Output = Result<T>>,
+        H: Fn(&T) -> Result<String>,
+    {
+        let mut responses = self
+            .transports
+            .iter()
+            .enumerate()
+            .map(|(i, transport)| {
+                let fut = f(transport.as_ref());
+                async move { (i, fut.await) }
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        // Responses grouped by their fingerprint
+        let mut groups = Vec::<ResponseGroup<T>>::new();
+        let mut received = 0;
+        while let Some((i, response)) = responses.next().await {
+            let response = match response {
+                Ok(response) => response,
+                Err(e) => {
+                    log::warn!("Transport {i} failed: {e:?}");
+                    continue;
+                }
+            };
+            received += 1;
+
+            let fingerprint = fingerprint(&response)?;
+            let index = match groups
+                .iter()
+                .position(|group| group.fingerprint == fingerprint)
+            {
+                Some(index) => {
+                    groups[index].transports.push(i);
+                    index
+                }
+                None => {
+                    groups.push(ResponseGroup {
+                        fingerprint,
+                        transports: vec![i],
+                        response,
+                    });
+                    groups.len() - 1
+                }
+            };
+
+            if groups[index].transports.len() >= self.quorum {
+                let group = groups.swap_remove(index);
+                for other in &groups {
+                    log::debug!(
+                        "Transports {:?} returned {} instead of {}",
+                        other.transports,
+                        other.fingerprint,
+                        group.fingerprint
+                    );
+                }
+                return Ok(group.response);
+            }
+
+            // Stop waiting when the quorum can't be reached
+            let max_group_len = groups
+                .iter()
+                .map(|group| group.transports.len())
+                .max()
+                .unwrap_or_default();
+            if max_group_len + responses.len() < self.quorum {
+                break;
+            }
+        }
+
+        if groups.len() > 1 {
+            let responses = groups
+                .iter()
+                .map(|group| format!("{} from {:?}", group.fingerprint, group.transports))
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Err(QuorumTransportError::Disagreement { responses }.into());
+        }
+
+        Err(QuorumTransportError::NotEnoughResponses {
+            received,
+            required: self.quorum,
+        }
+        .into())
+    }
+}
+
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl Transport for QuorumTransport {
+    fn info(&self) -> TransportInfo {
+        TransportInfo::most_restrictive(self.transports.iter().map(|transport| transport.info()))
+    }
+
+    async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
+        // Broadcast message through all transports
+        let mut last_error = None;
+        let mut responses = self
+            .transports
+            .iter()
+            .map(|transport| transport.send_message(message))
+            .collect::<FuturesUnordered<_>>();
+
+        let mut sent = false;
+        while let Some(response) = responses.next().await {
+            match response {
+                Ok(()) => sent = true,
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        match last_error {
+            Some(e) if !sent => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
+        self.query_verified(
+            |transport| transport.get_contract_state(address),
+            |state| match state {
+                RawContractState::NotExists { .. } => Ok("not exists".to_owned()),
+                RawContractState::Exists(contract) => account_fingerprint(&contract.account),
+            },
+        )
+        .await
+    }
+
+    async fn poll_contract_state(
+        &self,
+        address: &MsgAddressInt,
+        last_trans_lt: u64,
+    ) -> Result<PollContractState> {
+        self.query_verified(
+            |transport| transport.poll_contract_state(address, last_trans_lt),
+            |state| match state {
+                PollContractState::Unchanged { .. } => Ok("unchanged".to_owned()),
+                PollContractState::NotExists { .. } => Ok("not exists".to_owned()),
+                PollContractState::Exists(contract) => account_fingerprint(&contract.account),
+            },
+        )
+        .await
+    }
+
+    async fn get_contract_state_at(
+        &self,
+        address: &MsgAddressInt,
+        at: ContractStateAt,
+    ) -> Result<RawContractState> {
+        self.query_verified(
+            |transport| transport.get_contract_state_at(address, at),
+            |state| match state {
+                RawContractState::NotExists { .. } => Ok("not exists".to_owned()),
+                RawContractState::Exists(contract) => account_fingerprint(&contract.account),
+            },
+        )
+        .await
+    }
+
+    async fn get_contract_state_with_proof(
+        &self,
+        address: &MsgAddressInt,
+    ) -> Result<ContractStateWithProof> {
+        self.query_verified(
+            |transport| transport.get_contract_state_with_proof(address),
+            |response| {
+                let state = match &response.state {
+                    RawContractState::NotExists { .. } => "not exists".to_owned(),
+                    RawContractState::Exists(contract) => account_fingerprint(&contract.account)?,
+                };
+                Ok(format!(
+                    "{state} at {}",
+                    response.proof.mc_block_proof.proof_for.root_hash
+                ))
+            },
+        )
+        .await
+    }
+
+    async fn get_accounts_by_code_hash(
+        &self,
+        code_hash: &ton_types::UInt256,
+        limit: u8,
+        continuation: &Option<MsgAddressInt>,
+    ) -> Result<Vec<MsgAddressInt>> {
+        self.query_verified(
+            |transport| transport.get_accounts_by_code_hash(code_hash, limit, continuation),
+            |addresses| {
+                Ok(format!(
+                    "accounts [{}]",
+                    addresses
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            },
+        )
+        .await
+    }
+
+    async fn get_transactions(
+        &self,
+        address: &MsgAddressInt,
+        from_lt: u64,
+        count: u8,
+    ) -> Result<Vec<RawTransaction>> {
+        self.query_verified(
+            |transport| transport.get_transactions(address, from_lt, count),
+            |transactions| {
+                Ok(format!(
+                    "transactions [{}]",
+                    transactions
+                        .iter()
+                        .map(transaction_fingerprint)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            },
+        )
+        .await
+    }
+
+    async fn get_transaction(&self, id: &ton_types::UInt256) -> Result<Option<RawTransaction>> {
+        self.query_verified(
+            |transport| transport.get_transaction(id),
+            |transaction| Ok(optional_transaction_fingerprint(transaction)),
+        )
+        .await
+    }
+
+    async fn get_dst_transaction(
+        &self,
+        message_hash: &ton_types::UInt256,
+    ) -> Result<Option<RawTransaction>> {
+        self.query_verified(
+            |transport| transport.get_dst_transaction(message_hash),
+            |transaction| Ok(optional_transaction_fingerprint(transaction)),
+        )
+        .await
+    }
+
+    async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+        self.query_verified(
+            |transport| transport.get_latest_key_block(),
+            |block| Ok(format!("block {:x}", block.serialize()?.repr_hash())),
+        )
+        .await
+    }
+
+    async fn get_latest_block(&self, address: &MsgAddressInt) -> Result<LatestBlock> {
+        self.query_verified(
+            |transport| transport.get_latest_block(address),
+            |block| Ok(format!("latest block {}", block.id)),
+        )
+        .await
+    }
+
+    async fn get_block(&self, id: &str) -> Result<ton_block::Block> {
+        self.query_verified(
+            |transport| transport.get_block(id),
+            |block| Ok(format!("block {:x}", block.serialize()?.repr_hash())),
+        )
+        .await
+    }
+
+    async fn get_block_by_seqno(
+        &self,
+        shard: &ton_block::ShardIdent,
+        seqno: u32,
+    ) -> Result<ton_block::Block> {
+        self.query_verified(
+            |transport| transport.get_block_by_seqno(shard, seqno),
+            |block| Ok(format!("block {:x}", block.serialize()?.repr_hash())),
+        )
+        .await
+    }
+
+    async fn wait_for_next_block(
+        &self,
+        current: &str,
+        address: &MsgAddressInt,
+        timeout: Duration,
+    ) -> Result<String> {
+        self.query_verified(
+            |transport| transport.wait_for_next_block(current, address, timeout),
+            |id| Ok(format!("next block {id}")),
+        )
+        .await
+    }
+
+    async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
+        self.query_verified(
+            |transport| transport.get_capabilities(clock),
+            |capabilities| {
+                Ok(format!(
+                    "capabilities {}:{:x}",
+                    capabilities.global_id, capabilities.raw
+                ))
+            },
+        )
+        .await
+    }
+
+    async fn get_blockchain_config(
+        &self,
+        clock: &dyn Clock,
+        force: bool,
+    ) -> Result<ton_executor::BlockchainConfig> {
+        self.query_verified(
+            |transport| transport.get_blockchain_config(clock, force),
+            |config| {
+                Ok(format!(
+                    "config {:x}",
+                    config.raw_config().serialize()?.repr_hash()
+                ))
+            },
+        )
+        .await
+    }
+}
+
+struct ResponseGroup<T> {
+    fingerprint: String,
+    transports: Vec<usize>,
+    response: T,
+}
+
+fn account_fingerprint(account: &ton_block::AccountStuff) -> Result<String> {
+    let hash = ton_block::Account::Account(account.clone())
+        .serialize()?
+        .repr_hash();
+    Ok(format!(
+        "account {}:{:x}",
+        account.storage.last_trans_lt, hash
+    ))
+}
+
+fn transaction_fingerprint(transaction: &RawTransaction) -> String {
+    format!("{}:{:x}", transaction.data.lt, transaction.hash)
+}
+
+fn optional_transaction_fingerprint(transaction: &Option<RawTransaction>) -> String {
+    match transaction {
+        Some(transaction) => format!("transaction {}", transaction_fingerprint(transaction)),
+        None => "no transaction".to_owned(),
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum QuorumTransportError {
+    #[error("Invalid quorum {quorum} for {total} transports")]
+    InvalidQuorum { quorum: usize, total: usize },
+    #[error("Transports disagree: {responses}")]
+    Disagreement { responses: String },
+    #[error("Not enough responses: received {received}, required {required}")]
+    NotEnoughResponses { received: usize, required: usize },
+}
+
+impl QuorumTransportError {
+    /// Responses may converge when the transports catch up
+    pub fn is_retryable(&self) -> bool {
+        !matches!(self, Self::InvalidQuorum { .. })
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "local_transport")]
+mod tests {
+    use super::*;
+    use crate::core::models::ReliableBehavior;
+    use crate::transport::local::tests::{dst_address, make_transfer};
+    use crate::transport::local::LocalTransport;
+
+    async fn make_transport(message: Option<&ton_block::Message>) -> Arc<dyn Transport> {
+        let transport = LocalTransport::new(Arc::new(ConstClock::from_secs(1700000000)));
+        if let Some(message) = message {
+            transport.send_message(message).await.unwrap();
+        }
+        Arc::new(transport)
+    }
+
+    /// Transport which never responds
+    struct HangingTransport;
+
+    impl HangingTransport {
+        async fn hang<T>(&self) -> Result<T> {
+            futures_util::future::pending().await
+        }
+    }
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl Transport for HangingTransport {
+        fn info(&self) -> TransportInfo {
+            TransportInfo {
+                max_transactions_per_fetch: 50,
+                max_accounts_per_fetch: 50,
+                reliable_behavior: ReliableBehavior::IntensivePolling,
+                has_key_blocks: false,
+            }
+        }
+
+        async fn send_message(&self, _: &ton_block::Message) -> Result<()> {
+            self.hang().await
+        }
+
+        async fn get_contract_state(&self, _: &MsgAddressInt) -> Result<RawContractState> {
+            self.hang().await
+        }
+
+        async fn poll_contract_state(
+            &self,
+            _: &MsgAddressInt,
+            _: u64,
+        ) -> Result<PollContractState> {
+            self.hang().await
+        }
+
+        async fn get_accounts_by_code_hash(
+            &self,
+            _: &ton_types::UInt256,
+            _: u8,
+            _: &Option<MsgAddressInt>,
+        ) -> Result<Vec<MsgAddressInt>> {
+            self.hang().await
+        }
+
+        async fn get_transactions(
+            &self,
+            _: &MsgAddressInt,
+            _: u64,
+            _: u8,
+        ) -> Result<Vec<RawTransaction>> {
+            self.hang().await
+        }
+
+        async fn get_transaction(&self, _: &ton_types::UInt256) -> Result<Option<RawTransaction>> {
+            self.hang().await
+        }
+
+        async fn get_dst_transaction(
+            &self,
+            _: &ton_types::UInt256,
+        ) -> Result<Option<RawTransaction>> {
+            self.hang().await
+        }
+
+        async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+            self.hang().await
+        }
+
+        async fn get_capabilities(&self, _: &dyn Clock) -> Result<NetworkCapabilities> {
+            self.hang().await
+        }
+
+        async fn get_blockchain_config(
+            &self,
+            _: &dyn Clock,
+            _: bool,
+        ) -> Result<ton_executor::BlockchainConfig> {
+            self.hang().await
+        }
+    }
+
+    #[tokio::test]
+    async fn quorum_transport_detects_disagreement() -> Result<()> {
+        let dst = dst_address();
+        let message = make_transfer(1_000_000_000);
+
+        let transport = QuorumTransport::new(
+            vec![
+                make_transport(Some(&message)).await,
+                make_transport(None).await,
+            ],
+            2,
+        )?;
+        let error = transport.get_contract_state(&dst).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<QuorumTransportError>(),
+            Some(QuorumTransportError::Disagreement { .. })
+        ));
+
+        assert!(QuorumTransport::new(vec![make_transport(None).await], 2).is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn quorum_transport_ignores_lagging_transports() -> Result<()> {
+        let dst = dst_address();
+        let message = make_transfer(1_000_000_000);
+
+        // The last transport hasn't received the block yet
+        let transport = QuorumTransport::new(
+            vec![
+                make_transport(Some(&message)).await,
+                make_transport(Some(&message)).await,
+                make_transport(None).await,
+            ],
+            2,
+        )?;
+        assert!(matches!(
+            transport.get_contract_state(&dst).await?,
+            RawContractState::Exists(_)
+        ));
+        assert_eq!(
+            transport.get_latest_block(&dst).await?.id,
+            format!("{:064x}", 1)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn quorum_transport_does_not_wait_for_all_transports() -> Result<()> {
+        let dst = dst_address();
+        let message = make_transfer(1_000_000_000);
+
+        let transport = QuorumTransport::new(
+            vec![
+                Arc::new(HangingTransport),
+                make_transport(Some(&message)).await,
+                make_transport(Some(&message)).await,
+            ],
+            2,
+        )?;
+        assert!(matches!(
+            transport.get_contract_state(&dst).await?,
+            RawContractState::Exists(_)
+        ));
+
+        Ok(())
+    }
+}
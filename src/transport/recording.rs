@@ -0,0 +1,660 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use ton_block::{GetRepresentationHash, MsgAddressInt};
+use ton_types::UInt256;
+
+use nekoton_utils::*;
+
+use crate::core::models::NetworkCapabilities;
+
+use super::models::{
+    ContractStateAt, LatestBlock, PollContractState, RawContractState, RawTransaction,
+};
+use super::proof::ContractStateWithProof;
+use super::{Transport, TransportInfo};
+
+/// Recorded transport calls which can be served back by [`ReplayTransport`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransportFixture {
+    pub info: TransportInfo,
+    pub calls: Vec<RecordedCall>,
+}
+
+impl TransportFixture {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let data = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedCall {
+    pub request: RecordedRequest,
+    /// Serialized response or an error description
+    pub result: Result<serde_json::Value, String>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "method", content = "params")]
+pub enum RecordedRequest {
+    #[serde(rename_all = "camelCase")]
+    SendMessage {
+        #[serde(with = "serde_uint256")]
+        message_hash: UInt256,
+    },
+    #[serde(rename_all = "camelCase")]
+    GetContractState {
+        #[serde(with = "serde_address")]
+        address: MsgAddressInt,
+    },
+    #[serde(rename_all = "camelCase")]
+    PollContractState {
+        #[serde(with = "serde_address")]
+        address: MsgAddressInt,
+        #[serde(with = "serde_u64")]
+        last_trans_lt: u64,
+    },
+    #[serde(rename_all = "camelCase")]
+    GetContractStateAt {
+        #[serde(with = "serde_address")]
+        address: MsgAddressInt,
+        at: ContractStateAt,
+    },
+    #[serde(rename_all = "camelCase")]
+    GetAccountsByCodeHash {
+        #[serde(with = "serde_uint256")]
+        code_hash: UInt256,
+        limit: u8,
+        #[serde(with = "serde_optional_address")]
+        continuation: Option<MsgAddressInt>,
+    },
+    #[serde(rename_all = "camelCase")]
+    GetTransactions {
+        #[serde(with = "serde_address")]
+        address: MsgAddressInt,
+        #[serde(with = "serde_u64")]
+        from_lt: u64,
+        count: u8,
+    },
+    #[serde(rename_all = "camelCase")]
+    GetTransaction {
+        #[serde(with = "serde_uint256")]
+        id: UInt256,
+    },
+    #[serde(rename_all = "camelCase")]
+    GetDstTransaction {
+        #[serde(with = "serde_uint256")]
+        message_hash: UInt256,
+    },
+    GetLatestKeyBlock,
+    #[serde(rename_all = "camelCase")]
+    GetLatestBlock {
+        #[serde(with = "serde_address")]
+        address: MsgAddressInt,
+    },
+    #[serde(rename_all = "camelCase")]
+    GetBlock {
+        id: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    GetBlockBySeqno {
+        workchain: i32,
+        #[serde(with = "serde_u64")]
+        shard: u64,
+        seqno: u32,
+    },
+    /// Timeout is not recorded
+    #[serde(rename_all = "camelCase")]
+    WaitForNextBlock {
+        current: String,
+        #[serde(with = "serde_address")]
+        address: MsgAddressInt,
+    },
+    GetCapabilities,
+    GetBlockchainConfig,
+}
+
+/// Transport which records all calls to the underlying transport
+pub struct RecordingTransport {
+    transport: Arc<dyn Transport>,
+    calls: Mutex<Vec<RecordedCall>>,
+}
+
+impl RecordingTransport {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self {
+            transport,
+            calls: Default::default(),
+        }
+    }
+
+    /// Returns all recorded calls
+    pub fn fixture(&self) -> TransportFixture {
+        TransportFixture {
+            info: self.transport.info(),
+            calls: self.calls.lock().clone(),
+        }
+    }
+
+    /// Saves all recorded calls into the JSON file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.fixture().save(path)
+    }
+
+    fn record<T>(&self, request: RecordedRequest, result: Result<T, &anyhow::Error>)
+    where
+        T: Serialize,
+    {
+        let result = match result {
+            Ok(value) => serde_json::to_value(value).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        self.calls.lock().push(RecordedCall { request, result });
+    }
+}
+
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl Transport for RecordingTransport {
+    fn info(&self) -> TransportInfo {
+        self.transport.info()
+    }
+
+    async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
+        let request = RecordedRequest::SendMessage {
+            message_hash: message.hash()?,
+        };
+        let result = self.transport.send_message(message).await;
+        self.record(request, result.as_ref());
+        result
+    }
+
+    async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
+        let result = self.transport.get_contract_state(address).await;
+        self.record(
+            RecordedRequest::GetContractState {
+                address: address.clone(),
+            },
+            result.as_ref(),
+        );
+        result
+    }
+
+    async fn poll_contract_state(
+        &self,
+        address: &MsgAddressInt,
+        last_trans_lt: u64,
+    ) -> Result<PollContractState> {
+        let result = self
+            .transport
+            .poll_contract_state(address, last_trans_lt)
+            .await;
+        self.record(
+            RecordedRequest::PollContractState {
+                address: address.clone(),
+                last_trans_lt,
+            },
+            result.as_ref(),
+        );
+        result
+    }
+
+    async fn get_contract_state_at(
+        &self,
+        address: &MsgAddressInt,
+        at: ContractStateAt,
+    ) -> Result<RawContractState> {
+        let result = self.transport.get_contract_state_at(address, at).await;
+        self.record(
+            RecordedRequest::GetContractStateAt {
+                address: address.clone(),
+                at,
+            },
+            result.as_ref(),
+        );
+        result
+    }
+
+    async fn get_contract_state_with_proof(
+        &self,
+        address: &MsgAddressInt,
+    ) -> Result<ContractStateWithProof> {
+        // NOTE: proofs are not recorded, so replay doesn't support them
+        self.transport.get_contract_state_with_proof(address).await
+    }
+
+    async fn get_accounts_by_code_hash(
+        &self,
+        code_hash: &UInt256,
+        limit: u8,
+        continuation: &Option<MsgAddressInt>,
+    ) -> Result<Vec<MsgAddressInt>> {
+        let result = self
+            .transport
+            .get_accounts_by_code_hash(code_hash, limit, continuation)
+            .await;
+        self.record(
+            RecordedRequest::GetAccountsByCodeHash {
+                code_hash: *code_hash,
+                limit,
+                continuation: continuation.clone(),
+            },
+            result.as_ref().map(|value| Addresses(value.clone())),
+        );
+        result
+    }
+
+    async fn get_transactions(
+        &self,
+        address: &MsgAddressInt,
+        from_lt: u64,
+        count: u8,
+    ) -> Result<Vec<RawTransaction>> {
+        let result = self
+            .transport
+            .get_transactions(address, from_lt, count)
+            .await;
+        self.record(
+            RecordedRequest::GetTransactions {
+                address: address.clone(),
+                from_lt,
+                count,
+            },
+            result.as_ref(),
+        );
+        result
+    }
+
+    async fn get_transaction(&self, id: &UInt256) -> Result<Option<RawTransaction>> {
+        let result = self.transport.get_transaction(id).await;
+        self.record(RecordedRequest::GetTransaction { id: *id }, result.as_ref());
+        result
+    }
+
+    async fn get_dst_transaction(&self, message_hash: &UInt256) -> Result<Option<RawTransaction>> {
+        let result = self.transport.get_dst_transaction(message_hash).await;
+        self.record(
+            RecordedRequest::GetDstTransaction {
+                message_hash: *message_hash,
+            },
+            result.as_ref(),
+        );
+        result
+    }
+
+    async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+        let result = self.transport.get_latest_key_block().await;
+        self.record(
+            RecordedRequest::GetLatestKeyBlock,
+            result.as_ref().map(|value| BlockBoc(value.clone())),
+        );
+        result
+    }
+
+    async fn get_latest_block(&self, address: &MsgAddressInt) -> Result<LatestBlock> {
+        let result = self.transport.get_latest_block(address).await;
+        self.record(
+            RecordedRequest::GetLatestBlock {
+                address: address.clone(),
+            },
+            result.as_ref(),
+        );
+        result
+    }
+
+    async fn get_block(&self, id: &str) -> Result<ton_block::Block> {
+        let result = self.transport.get_block(id).await;
+        self.record(
+            RecordedRequest::GetBlock { id: id.to_owned() },
+            result.as_ref().map(|value| BlockBoc(value.clone())),
+        );
+        result
+    }
+
+    async fn get_block_by_seqno(
+        &self,
+        shard: &ton_block::ShardIdent,
+        seqno: u32,
+    ) -> Result<ton_block::Block> {
+        let result = self.transport.get_block_by_seqno(shard, seqno).await;
+        self.record(
+            RecordedRequest::GetBlockBySeqno {
+                workchain: shard.workchain_id(),
+                shard: shard.shard_prefix_with_tag(),
+                seqno,
+            },
+            result.as_ref().map(|value| BlockBoc(value.clone())),
+        );
+        result
+    }
+
+    async fn wait_for_next_block(
+        &self,
+        current: &str,
+        address: &MsgAddressInt,
+        timeout: Duration,
+    ) -> Result<String> {
+        let result = self
+            .transport
+            .wait_for_next_block(current, address, timeout)
+            .await;
+        self.record(
+            RecordedRequest::WaitForNextBlock {
+                current: current.to_owned(),
+                address: address.clone(),
+            },
+            result.as_ref(),
+        );
+        result
+    }
+
+    async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
+        let result = self.transport.get_capabilities(clock).await;
+        self.record(RecordedRequest::GetCapabilities, result.as_ref());
+        result
+    }
+
+    async fn get_blockchain_config(
+        &self,
+        clock: &dyn Clock,
+        force: bool,
+    ) -> Result<ton_executor::BlockchainConfig> {
+        let result = self.transport.get_blockchain_config(clock, force).await;
+        self.record(
+            RecordedRequest::GetBlockchainConfig,
+            result.as_ref().map(|value| StoredConfig {
+                global_id: value.global_id(),
+                params: value.raw_config().clone(),
+            }),
+        );
+        result
+    }
+}
+
+/// Transport which serves responses from the recorded fixture.
+///
+/// Identical requests are served in the recorded order. The last response
+/// is repeated when the recorded ones are exhausted.
+pub struct ReplayTransport {
+    info: TransportInfo,
+    calls: Mutex<HashMap<RecordedRequest, VecDeque<Result<serde_json::Value, String>>>>,
+}
+
+impl ReplayTransport {
+    pub fn new(fixture: TransportFixture) -> Self {
+        let mut calls = HashMap::<_, VecDeque<_>>::new();
+        for call in fixture.calls {
+            calls
+                .entry(call.request)
+                .or_default()
+                .push_back(call.result);
+        }
+
+        Self {
+            info: fixture.info,
+            calls: Mutex::new(calls),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        TransportFixture::load(path).map(Self::new)
+    }
+
+    fn replay<T>(&self, request: RecordedRequest) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let result = {
+            let mut calls = self.calls.lock();
+            let responses = match calls.get_mut(&request) {
+                Some(responses) if !responses.is_empty() => responses,
+                _ => return Err(ReplayTransportError::UnexpectedRequest(request).into()),
+            };
+
+            if responses.len() > 1 {
+                responses.pop_front().trust_me()
+            } else {
+                responses.front().cloned().trust_me()
+            }
+        };
+
+        match result {
+            Ok(value) => Ok(serde_json::from_value(value)?),
+            Err(e) => Err(ReplayTransportError::RecordedError(e).into()),
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl Transport for ReplayTransport {
+    fn info(&self) -> TransportInfo {
+        self.info
+    }
+
+    async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
+        self.replay(RecordedRequest::SendMessage {
+            message_hash: message.hash()?,
+        })
+    }
+
+    async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
+        self.replay(RecordedRequest::GetContractState {
+            address: address.clone(),
+        })
+    }
+
+    async fn poll_contract_state(
+        &self,
+        address: &MsgAddressInt,
+        last_trans_lt: u64,
+    ) -> Result<PollContractState> {
+        self.replay(RecordedRequest::PollContractState {
+            address: address.clone(),
+            last_trans_lt,
+        })
+    }
+
+    async fn get_contract_state_at(
+        &self,
+        address: &MsgAddressInt,
+        at: ContractStateAt,
+    ) -> Result<RawContractState> {
+        self.replay(RecordedRequest::GetContractStateAt {
+            address: address.clone(),
+            at,
+        })
+    }
+
+    async fn get_accounts_by_code_hash(
+        &self,
+        code_hash: &UInt256,
+        limit: u8,
+        continuation: &Option<MsgAddressInt>,
+    ) -> Result<Vec<MsgAddressInt>> {
+        self.replay(RecordedRequest::GetAccountsByCodeHash {
+            code_hash: *code_hash,
+            limit,
+            continuation: continuation.clone(),
+        })
+        .map(|Addresses(addresses)| addresses)
+    }
+
+    async fn get_transactions(
+        &self,
+        address: &MsgAddressInt,
+        from_lt: u64,
+        count: u8,
+    ) -> Result<Vec<RawTransaction>> {
+        self.replay(RecordedRequest::GetTransactions {
+            address: address.clone(),
+            from_lt,
+            count,
+        })
+    }
+
+    async fn get_transaction(&self, id: &UInt256) -> Result<Option<RawTransaction>> {
+        self.replay(RecordedRequest::GetTransaction { id: *id })
+    }
+
+    async fn get_dst_transaction(&self, message_hash: &UInt256) -> Result<Option<RawTransaction>> {
+        self.replay(RecordedRequest::GetDstTransaction {
+            message_hash: *message_hash,
+        })
+    }
+
+    async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+        self.replay(RecordedRequest::GetLatestKeyBlock)
+            .map(|BlockBoc(block)| block)
+    }
+
+    async fn get_latest_block(&self, address: &MsgAddressInt) -> Result<LatestBlock> {
+        self.replay(RecordedRequest::GetLatestBlock {
+            address: address.clone(),
+        })
+    }
+
+    async fn get_block(&self, id: &str) -> Result<ton_block::Block> {
+        self.replay(RecordedRequest::GetBlock { id: id.to_owned() })
+            .map(|BlockBoc(block)| block)
+    }
+
+    async fn get_block_by_seqno(
+        &self,
+        shard: &ton_block::ShardIdent,
+        seqno: u32,
+    ) -> Result<ton_block::Block> {
+        self.replay(RecordedRequest::GetBlockBySeqno {
+            workchain: shard.workchain_id(),
+            shard: shard.shard_prefix_with_tag(),
+            seqno,
+        })
+        .map(|BlockBoc(block)| block)
+    }
+
+    async fn wait_for_next_block(
+        &self,
+        current: &str,
+        address: &MsgAddressInt,
+        _timeout: Duration,
+    ) -> Result<String> {
+        self.replay(RecordedRequest::WaitForNextBlock {
+            current: current.to_owned(),
+            address: address.clone(),
+        })
+    }
+
+    async fn get_capabilities(&self, _clock: &dyn Clock) -> Result<NetworkCapabilities> {
+        self.replay(RecordedRequest::GetCapabilities)
+    }
+
+    async fn get_blockchain_config(
+        &self,
+        _clock: &dyn Clock,
+        _force: bool,
+    ) -> Result<ton_executor::BlockchainConfig> {
+        let config: StoredConfig = self.replay(RecordedRequest::GetBlockchainConfig)?;
+        ton_executor::BlockchainConfig::with_config(config.params, config.global_id)
+            .map_err(|_| ReplayTransportError::InvalidConfig.into())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+struct Addresses(#[serde(with = "serde_vec_address")] Vec<MsgAddressInt>);
+
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+struct BlockBoc(#[serde(with = "serde_ton_block")] ton_block::Block);
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredConfig {
+    global_id: i32,
+    #[serde(with = "serde_ton_block")]
+    params: ton_block::ConfigParams,
+}
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum ReplayTransportError {
+    #[error("Unexpected request: {0:?}")]
+    UnexpectedRequest(RecordedRequest),
+    #[error("Recorded error: {0}")]
+    RecordedError(String),
+    #[error("Invalid config")]
+    InvalidConfig,
+}
+
+impl ReplayTransportError {
+    /// Recorded responses never change
+    pub fn is_retryable(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "local_transport")]
+mod tests {
+    use super::*;
+    use crate::transport::local::tests::{dst_address, make_transfer};
+    use crate::transport::local::LocalTransport;
+
+    #[tokio::test]
+    async fn replay_recorded_calls() -> Result<()> {
+        let local = Arc::new(LocalTransport::new(Arc::new(SimpleClock)));
+        let transport = RecordingTransport::new(local);
+
+        let dst = dst_address();
+        let message = make_transfer(1_000_000_000);
+
+        transport.send_message(&message).await?;
+        let state = transport.get_contract_state(&dst).await?;
+        let transactions = transport.get_transactions(&dst, u64::MAX, 10).await?;
+        assert!(transport.get_latest_key_block().await.is_err());
+
+        let fixture = serde_json::to_string(&transport.fixture())?;
+        let replay = ReplayTransport::new(serde_json::from_str(&fixture)?);
+
+        replay.send_message(&message).await?;
+        let replayed_state = replay.get_contract_state(&dst).await?.brief();
+        assert_eq!(replayed_state.last_lt, state.brief().last_lt);
+        assert_eq!(replayed_state.balance, state.brief().balance);
+        assert_eq!(
+            replay.get_transactions(&dst, u64::MAX, 10).await?,
+            transactions
+        );
+        assert!(matches!(
+            replay
+                .get_latest_key_block()
+                .await
+                .unwrap_err()
+                .downcast_ref::<ReplayTransportError>(),
+            Some(ReplayTransportError::RecordedError(_))
+        ));
+        assert!(matches!(
+            replay
+                .get_transaction(&UInt256::default())
+                .await
+                .unwrap_err()
+                .downcast_ref::<ReplayTransportError>(),
+            Some(ReplayTransportError::UnexpectedRequest(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,868 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use ton_block::MsgAddressInt;
+
+use nekoton_utils::*;
+
+use crate::core::models::NetworkCapabilities;
+use crate::external::Timer;
+
+use super::models::{
+    ContractStateAt, LatestBlock, PollContractState, RawContractState, RawTransaction,
+};
+use super::proof::ContractStateWithProof;
+use super::{AccountUpdatesStream, Transport, TransportInfo};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TransportMethod {
+    SendMessage,
+    GetContractState,
+    GetContractStates,
+    GetContractStateAt,
+    GetContractStateWithProof,
+    PollContractState,
+    GetAccountsByCodeHash,
+    GetTransactions,
+    GetTransaction,
+    GetDstTransaction,
+    GetLatestKeyBlock,
+    GetLatestBlock,
+    GetBlock,
+    GetBlockBySeqno,
+    WaitForNextBlock,
+    GetCapabilities,
+    GetBlockchainConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one. Default: `3`
+    pub max_attempts: u32,
+    /// Delay before the first retry. Default: `200`
+    #[serde(with = "serde_duration_ms")]
+    pub initial_backoff: Duration,
+    /// Max delay between retries. Default: `5000`
+    #[serde(with = "serde_duration_ms")]
+    pub max_backoff: Duration,
+    /// Backoff growth factor. Default: `2.0`
+    pub multiplier: f64,
+}
+
+impl RetryPolicy {
+    /// Policy which never retries failed requests
+    pub fn no_retries() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the delay before the specified retry (starting from zero)
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self.initial_backoff.as_secs_f64() * self.multiplier.powi(retry as i32);
+        if backoff.is_finite() {
+            Duration::from_secs_f64(backoff.max(0.0)).min(self.max_backoff)
+        } else {
+            self.max_backoff
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+            multiplier: 2.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitSettings {
+    /// Number of requests which are restored every second. Default: `10.0`
+    pub requests_per_second: f64,
+    /// Max number of requests which can be sent at once. Default: `10`
+    pub burst: u32,
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        Self {
+            requests_per_second: 10.0,
+            burst: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CircuitBreakerSettings {
+    /// Number of consecutive failures after which requests are rejected. Default: `5`
+    pub failure_threshold: u32,
+    /// How long requests are rejected before the next trial request. Default: `30000`
+    #[serde(with = "serde_duration_ms")]
+    pub reset_timeout: Duration,
+}
+
+impl Default for CircuitBreakerSettings {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            reset_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryTransportSettings {
+    /// Policy for methods without an explicit one
+    pub default_policy: RetryPolicy,
+    /// Policies for specific methods
+    pub method_policies: HashMap<TransportMethod, RetryPolicy>,
+    /// Limits the rate of requests (including retries). Disabled by default
+    pub rate_limit: Option<RateLimitSettings>,
+    /// Rejects requests while the transport keeps failing. Disabled by default
+    pub circuit_breaker: Option<CircuitBreakerSettings>,
+}
+
+pub type ErrorClassifier = Arc<dyn Fn(&anyhow::Error) -> bool + Send + Sync>;
+
+/// Transport which retries failed requests of the underlying transport.
+///
+/// Only retryable errors (see [`is_retryable_error`]) are retried. Deterministic
+/// errors are returned immediately, because repeating the request will not help.
+pub struct RetryTransport<T> {
+    transport: T,
+    clock: Arc<dyn Clock>,
+    timer: Arc<dyn Timer>,
+    default_policy: RetryPolicy,
+    method_policies: HashMap<TransportMethod, RetryPolicy>,
+    rate_limiter: Option<TokenBucket>,
+    circuit_breaker: Option<CircuitBreaker>,
+    is_retryable: ErrorClassifier,
+}
+
+impl<T: Transport> RetryTransport<T> {
+    pub fn new(transport: T, clock: Arc<dyn Clock>, timer: Arc<dyn Timer>) -> Self {
+        Self::with_settings(transport, clock, timer, Default::default())
+    }
+
+    pub fn with_settings(
+        transport: T,
+        clock: Arc<dyn Clock>,
+        timer: Arc<dyn Timer>,
+        settings: RetryTransportSettings,
+    ) -> Self {
+        Self {
+            transport,
+            clock,
+            timer,
+            default_policy: settings.default_policy,
+            method_policies: settings.method_policies,
+            rate_limiter: settings.rate_limit.map(TokenBucket::new),
+            circuit_breaker: settings.circuit_breaker.map(CircuitBreaker::new),
+            is_retryable: Arc::new(is_retryable_error),
+        }
+    }
+
+    /// Replaces the default error classification
+    pub fn with_error_classifier<F>(mut self, is_retryable: F) -> Self
+    where
+        F: Fn(&anyhow::Error) -> bool + Send + Sync + 'static,
+    {
+        self.is_retryable = Arc::new(is_retryable);
+        self
+    }
+
+    /// Returns the underlying transport
+    pub fn inner(&self) -> &T {
+        &self.transport
+    }
+
+    fn policy(&self, method: TransportMethod) -> &RetryPolicy {
+        self.method_policies
+            .get(&method)
+            .unwrap_or(&self.default_policy)
+    }
+
+    async fn call<'a, R, F, Fut>(&'a self, method: TransportMethod, f: F) -> Result<R>
+    where
+        F: Fn(&'a T) -> Fut,
+        Fut: Future<Output = Result<R>>,
+    {
+        let policy = self.policy(method);
+
+        let mut retry = 0;
+        let mut last_error = None;
+        loop {
+            if let Some(circuit_breaker) = &self.circuit_breaker {
+                if let Err(e) = circuit_breaker.check(self.clock.now_ms_u64()) {
+                    // Prefer the original error if the circuit was opened during retries
+                    return Err(last_error.unwrap_or_else(|| e.into()));
+                }
+            }
+
+            if let Some(rate_limiter) = &self.rate_limiter {
+                while let Err(delay) = rate_limiter.try_acquire(self.clock.now_ms_u64()) {
+                    self.timer.sleep(delay).await;
+                }
+            }
+
+            let error = match f(&self.transport).await {
+                Ok(result) => {
+                    self.on_success();
+                    return Ok(result);
+                }
+                Err(e) if !(self.is_retryable)(&e) => {
+                    // The transport is reachable, so it is not a failure
+                    self.on_success();
+                    return Err(e);
+                }
+                Err(e) => e,
+            };
+
+            if let Some(circuit_breaker) = &self.circuit_breaker {
+                circuit_breaker.on_failure(self.clock.now_ms_u64());
+            }
+
+            if retry + 1 >= policy.max_attempts {
+                return Err(error);
+            }
+
+            let backoff = policy.backoff(retry);
+            log::debug!("Retrying {method:?} in {backoff:?} after error: {error:?}");
+            last_error = Some(error);
+            self.timer.sleep(backoff).await;
+            retry += 1;
+        }
+    }
+
+    fn on_success(&self) {
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            circuit_breaker.on_success();
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+#[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+impl<T: Transport> Transport for RetryTransport<T> {
+    fn info(&self) -> TransportInfo {
+        self.transport.info()
+    }
+
+    async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
+        self.call(TransportMethod::SendMessage, |transport| {
+            transport.send_message(message)
+        })
+        .await
+    }
+
+    async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
+        self.call(TransportMethod::GetContractState, |transport| {
+            transport.get_contract_state(address)
+        })
+        .await
+    }
+
+    async fn get_contract_states(
+        &self,
+        addresses: &[MsgAddressInt],
+    ) -> Result<Vec<RawContractState>> {
+        self.call(TransportMethod::GetContractStates, |transport| {
+            transport.get_contract_states(addresses)
+        })
+        .await
+    }
+
+    async fn poll_contract_state(
+        &self,
+        address: &MsgAddressInt,
+        last_trans_lt: u64,
+    ) -> Result<PollContractState> {
+        self.call(TransportMethod::PollContractState, |transport| {
+            transport.poll_contract_state(address, last_trans_lt)
+        })
+        .await
+    }
+
+    async fn get_contract_state_at(
+        &self,
+        address: &MsgAddressInt,
+        at: ContractStateAt,
+    ) -> Result<RawContractState> {
+        self.call(TransportMethod::GetContractStateAt, |transport| {
+            transport.get_contract_state_at(address, at)
+        })
+        .await
+    }
+
+    async fn get_contract_state_with_proof(
+        &self,
+        address: &MsgAddressInt,
+    ) -> Result<ContractStateWithProof> {
+        self.call(TransportMethod::GetContractStateWithProof, |transport| {
+            transport.get_contract_state_with_proof(address)
+        })
+        .await
+    }
+
+    async fn get_accounts_by_code_hash(
+        &self,
+        code_hash: &ton_types::UInt256,
+        limit: u8,
+        continuation: &Option<MsgAddressInt>,
+    ) -> Result<Vec<MsgAddressInt>> {
+        self.call(TransportMethod::GetAccountsByCodeHash, |transport| {
+            transport.get_accounts_by_code_hash(code_hash, limit, continuation)
+        })
+        .await
+    }
+
+    async fn get_transactions(
+        &self,
+        address: &MsgAddressInt,
+        from_lt: u64,
+        count: u8,
+    ) -> Result<Vec<RawTransaction>> {
+        self.call(TransportMethod::GetTransactions, |transport| {
+            transport.get_transactions(address, from_lt, count)
+        })
+        .await
+    }
+
+    async fn get_transaction(&self, id: &ton_types::UInt256) -> Result<Option<RawTransaction>> {
+        self.call(TransportMethod::GetTransaction, |transport| {
+            transport.get_transaction(id)
+        })
+        .await
+    }
+
+    async fn get_dst_transaction(
+        &self,
+        message_hash: &ton_types::UInt256,
+    ) -> Result<Option<RawTransaction>> {
+        self.call(TransportMethod::GetDstTransaction, |transport| {
+            transport.get_dst_transaction(message_hash)
+        })
+        .await
+    }
+
+    async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+        self.call(TransportMethod::GetLatestKeyBlock, |transport| {
+            transport.get_latest_key_block()
+        })
+        .await
+    }
+
+    async fn get_latest_block(&self, address: &MsgAddressInt) -> Result<LatestBlock> {
+        self.call(TransportMethod::GetLatestBlock, |transport| {
+            transport.get_latest_block(address)
+        })
+        .await
+    }
+
+    async fn get_block(&self, id: &str) -> Result<ton_block::Block> {
+        self.call(TransportMethod::GetBlock, |transport| {
+            transport.get_block(id)
+        })
+        .await
+    }
+
+    async fn get_block_by_seqno(
+        &self,
+        shard: &ton_block::ShardIdent,
+        seqno: u32,
+    ) -> Result<ton_block::Block> {
+        self.call(TransportMethod::GetBlockBySeqno, |transport| {
+            transport.get_block_by_seqno(shard, seqno)
+        })
+        .await
+    }
+
+    async fn wait_for_next_block(
+        &self,
+        current: &str,
+        address: &MsgAddressInt,
+        timeout: Duration,
+    ) -> Result<String> {
+        self.call(TransportMethod::WaitForNextBlock, |transport| {
+            transport.wait_for_next_block(current, address, timeout)
+        })
+        .await
+    }
+
+    async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
+        self.call(TransportMethod::GetCapabilities, |transport| {
+            transport.get_capabilities(clock)
+        })
+        .await
+    }
+
+    async fn get_blockchain_config(
+        &self,
+        clock: &dyn Clock,
+        force: bool,
+    ) -> Result<ton_executor::BlockchainConfig> {
+        self.call(TransportMethod::GetBlockchainConfig, |transport| {
+            transport.get_blockchain_config(clock, force)
+        })
+        .await
+    }
+
+    fn subscribe_accounts(&self, addresses: &[MsgAddressInt]) -> Option<AccountUpdatesStream> {
+        self.transport.subscribe_accounts(addresses)
+    }
+}
+
+/// Returns `false` for errors which will not disappear after the request is repeated.
+///
+/// Transport errors are classified by their `is_retryable` method.
+/// Other errors (e.g. of external connections) are considered retryable.
+pub fn is_retryable_error(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .find_map(classify_transport_error)
+        .unwrap_or(true)
+}
+
+fn classify_transport_error(error: &(dyn std::error::Error + 'static)) -> Option<bool> {
+    macro_rules! classify {
+        ($($ty:ty),*$(,)?) => {
+            $(if let Some(error) = error.downcast_ref::<$ty>() {
+                return Some(error.is_retryable());
+            })*
+        };
+    }
+
+    classify!(
+        super::TransportError,
+        RetryTransportError,
+        super::fallback::FallbackTransportError,
+        super::quorum::QuorumTransportError,
+        super::recording::ReplayTransportError,
+        super::proof::ProofError,
+        super::offline::OfflineTransportError,
+    );
+
+    #[cfg(feature = "local_transport")]
+    classify!(super::local::LocalTransportError);
+
+    #[cfg(feature = "gql_transport")]
+    classify!(super::gql::NodeClientError);
+
+    #[cfg(feature = "proto_transport")]
+    classify!(super::proto::ProtoClientError);
+
+    None
+}
+
+struct TokenBucket {
+    tokens_per_ms: f64,
+    capacity: f64,
+    state: Mutex<TokenBucketState>,
+}
+
+struct TokenBucketState {
+    tokens: f64,
+    updated_at: Option<u64>,
+}
+
+impl TokenBucket {
+    fn new(settings: RateLimitSettings) -> Self {
+        let capacity = settings.burst.max(1) as f64;
+        Self {
+            tokens_per_ms: settings.requests_per_second.max(f64::EPSILON) / 1000.0,
+            capacity,
+            state: Mutex::new(TokenBucketState {
+                tokens: capacity,
+                updated_at: None,
+            }),
+        }
+    }
+
+    /// Takes one token or returns the delay until it will be available
+    fn try_acquire(&self, now: u64) -> Result<(), Duration> {
+        let mut state = self.state.lock();
+
+        if let Some(updated_at) = state.updated_at {
+            let elapsed = now.saturating_sub(updated_at) as f64;
+            state.tokens = (state.tokens + elapsed * self.tokens_per_ms).min(self.capacity);
+        }
+        state.updated_at = Some(now);
+
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            Ok(())
+        } else {
+            let delay = ((1.0 - state.tokens) / self.tokens_per_ms).ceil();
+            Err(Duration::from_millis(delay as u64))
+        }
+    }
+}
+
+struct CircuitBreaker {
+    failure_threshold: u32,
+    reset_timeout_ms: u64,
+    state: Mutex<CircuitState>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum CircuitState {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: u64,
+    },
+    /// Only one trial request is allowed until it completes or the timeout passes
+    HalfOpen {
+        until: u64,
+    },
+}
+
+impl CircuitBreaker {
+    fn new(settings: CircuitBreakerSettings) -> Self {
+        Self {
+            failure_threshold: settings.failure_threshold.max(1),
+            reset_timeout_ms: settings.reset_timeout.as_millis() as u64,
+            state: Mutex::new(CircuitState::Closed { failures: 0 }),
+        }
+    }
+
+    fn check(&self, now: u64) -> Result<(), RetryTransportError> {
+        let mut state = self.state.lock();
+        match *state {
+            CircuitState::Closed { .. } => Ok(()),
+            CircuitState::Open { until } | CircuitState::HalfOpen { until } if now >= until => {
+                *state = CircuitState::HalfOpen {
+                    until: now.saturating_add(self.reset_timeout_ms),
+                };
+                Ok(())
+            }
+            CircuitState::Open { .. } | CircuitState::HalfOpen { .. } => {
+                Err(RetryTransportError::CircuitOpen)
+            }
+        }
+    }
+
+    fn on_success(&self) {
+        *self.state.lock() = CircuitState::Closed { failures: 0 };
+    }
+
+    fn on_failure(&self, now: u64) {
+        let mut state = self.state.lock();
+        *state = match *state {
+            CircuitState::Closed { failures } if failures + 1 < self.failure_threshold => {
+                CircuitState::Closed {
+                    failures: failures + 1,
+                }
+            }
+            _ => CircuitState::Open {
+                until: now.saturating_add(self.reset_timeout_ms),
+            },
+        };
+    }
+}
+
+#[derive(thiserror::Error, Debug, Copy, Clone)]
+pub enum RetryTransportError {
+    #[error("Circuit breaker is open")]
+    CircuitOpen,
+}
+
+impl RetryTransportError {
+    pub fn is_retryable(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::transport::local::tests::dst_address;
+    use crate::transport::local::LocalTransport;
+    use crate::transport::TransportError;
+
+    #[test]
+    fn backoff_grows_exponentially() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(1000),
+            multiplier: 2.0,
+        };
+
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(800));
+        assert_eq!(policy.backoff(4), Duration::from_millis(1000));
+        assert_eq!(policy.backoff(1000), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn token_bucket_limits_rate() {
+        let bucket = TokenBucket::new(RateLimitSettings {
+            requests_per_second: 10.0,
+            burst: 2,
+        });
+
+        assert!(bucket.try_acquire(1000).is_ok());
+        assert!(bucket.try_acquire(1000).is_ok());
+        assert_eq!(bucket.try_acquire(1000), Err(Duration::from_millis(100)));
+        assert!(bucket.try_acquire(1050).is_err());
+        assert!(bucket.try_acquire(1100).is_ok());
+    }
+
+    #[test]
+    fn circuit_breaker_opens_after_failures() {
+        let breaker = CircuitBreaker::new(CircuitBreakerSettings {
+            failure_threshold: 2,
+            reset_timeout: Duration::from_millis(100),
+        });
+
+        breaker.on_failure(0);
+        assert!(breaker.check(0).is_ok());
+        breaker.on_failure(0);
+        assert!(breaker.check(50).is_err());
+
+        // Only one trial request is allowed
+        assert!(breaker.check(100).is_ok());
+        assert!(breaker.check(100).is_err());
+
+        breaker.on_success();
+        assert!(breaker.check(100).is_ok());
+    }
+
+    #[test]
+    fn deterministic_errors_are_not_retried() {
+        assert!(is_retryable_error(&anyhow::anyhow!("connection reset")));
+        assert!(!is_retryable_error(
+            &RetryTransportError::CircuitOpen.into()
+        ));
+        assert!(!is_retryable_error(
+            &anyhow::Error::from(
+                crate::transport::fallback::FallbackTransportError::NoTransportsSpecified
+            )
+            .context("failed to get contract state")
+        ));
+        assert!(is_retryable_error(
+            &crate::transport::fallback::FallbackTransportError::RequestTimeout.into()
+        ));
+    }
+
+    #[tokio::test]
+    async fn retries_retryable_errors() -> Result<()> {
+        let flaky = FlakyTransport::new(2, || anyhow::anyhow!("connection reset"));
+        let transport = RetryTransport::new(flaky, Arc::new(SimpleClock), Arc::new(InstantTimer));
+
+        transport.get_contract_state(&dst_address()).await?;
+        assert_eq!(transport.inner().calls(), 3);
+
+        // Not enough attempts
+        let flaky = FlakyTransport::new(3, || anyhow::anyhow!("connection reset"));
+        let transport = RetryTransport::new(flaky, Arc::new(SimpleClock), Arc::new(InstantTimer));
+
+        assert!(transport.get_contract_state(&dst_address()).await.is_err());
+        assert_eq!(transport.inner().calls(), 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_transport_errors() -> Result<()> {
+        let flaky = FlakyTransport::new(1, || {
+            anyhow::Error::from(TransportError::MethodNotSupported)
+        });
+        let transport = RetryTransport::new(flaky, Arc::new(SimpleClock), Arc::new(InstantTimer));
+
+        let error = transport
+            .get_contract_state(&dst_address())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<TransportError>(),
+            Some(TransportError::MethodNotSupported)
+        ));
+        assert_eq!(transport.inner().calls(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn circuit_breaker_rejects_requests() -> Result<()> {
+        let clock = Arc::new(ClockWithOffset::new(0));
+        let flaky = FlakyTransport::new(2, || anyhow::anyhow!("connection reset"));
+        let transport = RetryTransport::with_settings(
+            flaky,
+            clock.clone(),
+            Arc::new(InstantTimer),
+            RetryTransportSettings {
+                default_policy: RetryPolicy::no_retries(),
+                circuit_breaker: Some(CircuitBreakerSettings {
+                    failure_threshold: 2,
+                    reset_timeout: Duration::from_secs(10),
+                }),
+                ..Default::default()
+            },
+        );
+
+        for _ in 0..2 {
+            assert!(transport.get_contract_state(&dst_address()).await.is_err());
+        }
+        assert_eq!(transport.inner().calls(), 2);
+
+        // Requests are rejected without reaching the transport
+        let error = transport
+            .get_contract_state(&dst_address())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<RetryTransportError>(),
+            Some(RetryTransportError::CircuitOpen)
+        ));
+        assert_eq!(transport.inner().calls(), 2);
+
+        // Trial request after the reset timeout closes the circuit
+        clock.update_offset(10_000);
+        transport.get_contract_state(&dst_address()).await?;
+        transport.get_contract_state(&dst_address()).await?;
+        assert_eq!(transport.inner().calls(), 4);
+
+        Ok(())
+    }
+
+    struct InstantTimer;
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl Timer for InstantTimer {
+        async fn sleep(&self, _: Duration) {}
+    }
+
+    /// Transport which fails the specified number of first requests
+    struct FlakyTransport {
+        inner: LocalTransport,
+        failures: u32,
+        calls: AtomicU32,
+        make_error: fn() -> anyhow::Error,
+    }
+
+    impl FlakyTransport {
+        fn new(failures: u32, make_error: fn() -> anyhow::Error) -> Self {
+            Self {
+                inner: LocalTransport::new(Arc::new(SimpleClock)),
+                failures,
+                calls: AtomicU32::new(0),
+                make_error,
+            }
+        }
+
+        fn calls(&self) -> u32 {
+            self.calls.load(Ordering::Acquire)
+        }
+
+        fn check(&self) -> Result<()> {
+            if self.calls.fetch_add(1, Ordering::AcqRel) < self.failures {
+                Err((self.make_error)())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[cfg_attr(not(feature = "non_threadsafe"), async_trait::async_trait)]
+    #[cfg_attr(feature = "non_threadsafe", async_trait::async_trait(?Send))]
+    impl Transport for FlakyTransport {
+        fn info(&self) -> TransportInfo {
+            self.inner.info()
+        }
+
+        async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
+            self.check()?;
+            self.inner.send_message(message).await
+        }
+
+        async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
+            self.check()?;
+            self.inner.get_contract_state(address).await
+        }
+
+        async fn poll_contract_state(
+            &self,
+            address: &MsgAddressInt,
+            last_trans_lt: u64,
+        ) -> Result<PollContractState> {
+            self.check()?;
+            self.inner.poll_contract_state(address, last_trans_lt).await
+        }
+
+        async fn get_accounts_by_code_hash(
+            &self,
+            code_hash: &ton_types::UInt256,
+            limit: u8,
+            continuation: &Option<MsgAddressInt>,
+        ) -> Result<Vec<MsgAddressInt>> {
+            self.check()?;
+            self.inner
+                .get_accounts_by_code_hash(code_hash, limit, continuation)
+                .await
+        }
+
+        async fn get_transactions(
+            &self,
+            address: &MsgAddressInt,
+            from_lt: u64,
+            count: u8,
+        ) -> Result<Vec<RawTransaction>> {
+            self.check()?;
+            self.inner.get_transactions(address, from_lt, count).await
+        }
+
+        async fn get_transaction(&self, id: &ton_types::UInt256) -> Result<Option<RawTransaction>> {
+            self.check()?;
+            self.inner.get_transaction(id).await
+        }
+
+        async fn get_dst_transaction(
+            &self,
+            message_hash: &ton_types::UInt256,
+        ) -> Result<Option<RawTransaction>> {
+            self.check()?;
+            self.inner.get_dst_transaction(message_hash).await
+        }
+
+        async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+            self.check()?;
+            self.inner.get_latest_key_block().await
+        }
+
+        async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
+            self.check()?;
+            self.inner.get_capabilities(clock).await
+        }
+
+        async fn get_blockchain_config(
+            &self,
+            clock: &dyn Clock,
+            force: bool,
+        ) -> Result<ton_executor::BlockchainConfig> {
+            self.check()?;
+            self.inner.get_blockchain_config(clock, force).await
+        }
+    }
+}